@@ -0,0 +1,87 @@
+//! Compares decompressing the blocks of a large deflate file with the
+//! recycled decoder state the datafile reader uses against creating a
+//! new `DeflateDecoder` for each block, counting allocations and time.
+//! Run it with `cargo bench --features bench`.
+
+use flate2::bufread::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const BLOCKS: usize = 10_000;
+const VALUES_PER_BLOCK: usize = 100;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Runs `f`, returning its result along with how many allocations it
+/// made and how long it took.
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize, Duration) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+
+    (result, ALLOCATIONS.load(Ordering::Relaxed) - allocations, elapsed)
+}
+
+/// Compresses blocks of strings like the ones a datafile of varying
+/// length strings would hold.
+fn deflate_blocks() -> Vec<Vec<u8>> {
+    (0..BLOCKS)
+        .map(|block| {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+
+            for i in block * VALUES_PER_BLOCK..(block + 1) * VALUES_PER_BLOCK {
+                write!(encoder, "{}-{}", i, "x".repeat(i % 40)).unwrap();
+            }
+
+            encoder.finish().unwrap()
+        })
+        .collect()
+}
+
+fn main() {
+    let blocks = deflate_blocks();
+
+    let (fresh_len, fresh_allocations, fresh_time) = measure(|| {
+        blocks
+            .iter()
+            .map(|block| io::copy(&mut DeflateDecoder::new(block.as_slice()), &mut io::sink()).unwrap())
+            .sum::<u64>()
+    });
+
+    let (recycled_len, recycled_allocations, recycled_time) =
+        measure(|| lancaster::bench_decompress_deflate_blocks(&blocks).unwrap());
+
+    println!("{} blocks, {} bytes decompressed", blocks.len(), fresh_len);
+    println!(
+        "new decoder per block: {} allocations in {:?}",
+        fresh_allocations, fresh_time
+    );
+    println!(
+        "recycled decoder:      {} allocations in {:?}",
+        recycled_allocations, recycled_time
+    );
+
+    assert_eq!(recycled_len, fresh_len);
+    assert!(recycled_allocations < fresh_allocations / 100);
+}
@@ -49,6 +49,7 @@ writeAvroFile(dir, "null.avro", parseSchema('"null"'), [null, null])
 writeAvroFile(dir, "array.avro", parseSchema('{"type":"array", "items":"int"}'), [[1, 2, 3], [-10, -20]])
 writeAvroFile(dir, "map.avro", parseSchema('{"type":"map", "values":"int"}'), [["foo": 1, "bar": 2], ["hi": -1]])
 writeAvroFile(dir, "union.avro", parseSchema('["null", "boolean"]'), [null, true])
+writeAvroFile(dir, "union_string.avro", parseSchema('["null", "string"]'), [null, "foo", null, "bar"])
 
 def fixedSchema = parseSchema("""
 {
@@ -109,3 +110,11 @@ writeAvroFileWithCodec(
     ["foo", "bar", "foo"],
     CodecFactory.deflateCodec(5)
 )
+
+writeAvroFileWithCodec(
+    dir,
+    "string_snappy.avro",
+    parseSchema('"string"'),
+    ["foo", "bar", "foo"],
+    CodecFactory.snappyCodec()
+)
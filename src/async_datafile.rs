@@ -0,0 +1,294 @@
+use crate::schema::Schema;
+use crate::{
+    encoding, read_header_metadata, read_snappy_block, AvroDatafile, AvroValue, Codec, Error, ReadOptions,
+    SchemaRegistry, SyncMarker,
+};
+use flate2::read::DeflateDecoder;
+use futures_util::stream::{self, Stream};
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Reads a datafile from an `AsyncRead` stream. Data is buffered a
+/// whole block at a time, and each block is then decompressed and
+/// decoded with the same synchronous code as `AvroDatafile`, so only
+/// waiting on the stream is async. Values are decoded with the file's
+/// schema; reader schemas and field overrides aren't supported.
+pub(crate) struct AsyncAvroDatafile<'a, R> {
+    reader: R,
+    buffer: Vec<u8>,
+    schema: &'a Schema,
+    sync_marker: SyncMarker,
+    codec: Codec,
+    options: ReadOptions<'a>,
+}
+
+impl<'a, R: AsyncRead + Unpin> AsyncAvroDatafile<'a, R> {
+    pub(crate) async fn from_reader(reader: R, schema_registry: &'a mut SchemaRegistry) -> Result<Self, Error> {
+        Self::from_reader_with_options(reader, schema_registry, ReadOptions::default()).await
+    }
+
+    pub(crate) async fn from_reader_with_options(
+        reader: R,
+        schema_registry: &'a mut SchemaRegistry,
+        options: ReadOptions<'a>,
+    ) -> Result<Self, Error> {
+        let mut unparsed = UnparsedInput {
+            reader,
+            buffer: Vec::new(),
+        };
+
+        let (metadata, sync_marker) = unparsed
+            .parse(|input| {
+                let metadata = read_header_metadata(input)?;
+                let mut sync_marker: SyncMarker = [0; 16];
+                Read::read_exact(input, &mut sync_marker)?;
+                Ok((metadata, sync_marker))
+            })
+            .await?;
+
+        let schema_bytes = metadata.get("avro.schema").ok_or(Error::InvalidFormat)?;
+        let schema_str = std::str::from_utf8(schema_bytes).map_err(|_| Error::InvalidFormat)?;
+        let schema = Schema::parse(schema_str).map_err(|_| Error::InvalidFormat)?;
+
+        Ok(Self {
+            reader: unparsed.reader,
+            buffer: unparsed.buffer,
+            schema: schema_registry.register(schema),
+            sync_marker,
+            codec: Codec::from_header(&metadata)?,
+            options,
+        })
+    }
+
+    pub(crate) fn schema(&self) -> &'a Schema {
+        self.schema
+    }
+
+    /// Reads and decodes the next data block, or returns `None` once
+    /// the stream ends cleanly between blocks.
+    pub(crate) async fn next_block(&mut self) -> Option<Result<Vec<AvroValue<'a>>, Error>> {
+        let mut unparsed = UnparsedInput {
+            reader: &mut self.reader,
+            buffer: std::mem::take(&mut self.buffer),
+        };
+
+        let block = Self::read_block(&mut unparsed, &self.sync_marker).await;
+        self.buffer = unparsed.buffer;
+
+        match block {
+            Ok(Some((object_count, data))) => Some(self.decode_block(object_count, data)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Reads a block's object count and its data, still compressed.
+    async fn read_block(
+        unparsed: &mut UnparsedInput<&mut R>,
+        sync_marker: &SyncMarker,
+    ) -> Result<Option<(usize, Vec<u8>)>, Error> {
+        if !unparsed.fill_to(1).await? {
+            return Ok(None);
+        }
+
+        let (object_count, byte_length) = unparsed
+            .parse(|input| Ok((encoding::read_len(input)?, encoding::read_len(input)?)))
+            .await?;
+
+        // Buffer the whole block first, rather than retrying the parse
+        // each time another chunk arrives.
+        let block_length = byte_length.checked_add(sync_marker.len()).ok_or(Error::BadEncoding)?;
+        if !unparsed.fill_to(block_length).await? {
+            return Err(Error::IO(io::ErrorKind::UnexpectedEof));
+        }
+
+        let (data, block_sync_marker) = unparsed
+            .parse(|input| {
+                let data = encoding::read_fixed(input, byte_length)?;
+                let mut block_sync_marker: SyncMarker = [0; 16];
+                Read::read_exact(input, &mut block_sync_marker)?;
+                Ok((data, block_sync_marker))
+            })
+            .await?;
+
+        if block_sync_marker != *sync_marker {
+            return Err(Error::BadEncoding);
+        }
+
+        Ok(Some((object_count, data)))
+    }
+
+    fn decode_block(&self, object_count: usize, data: Vec<u8>) -> Result<Vec<AvroValue<'a>>, Error> {
+        let block = match self.codec {
+            Codec::Null => data,
+            Codec::Deflate => {
+                let mut block = Vec::new();
+                DeflateDecoder::new(data.as_slice()).read_to_end(&mut block)?;
+                block
+            }
+            Codec::Snappy => {
+                let mut block = Vec::new();
+                read_snappy_block(&mut data.as_slice(), data.len(), self.options.lenient, &mut block)?;
+                block
+            }
+            #[cfg(feature = "gzip")]
+            Codec::Gzip => {
+                let mut block = Vec::new();
+                flate2::read::GzDecoder::new(data.as_slice()).read_to_end(&mut block)?;
+                block
+            }
+        };
+
+        let mut input = block.as_slice();
+        (0..object_count)
+            .map(|_| AvroDatafile::read_value(&mut input, self.schema.root(), self.schema, &self.options))
+            .collect()
+    }
+
+    /// Yields every value in the file. The stream ends after the first
+    /// error, since a block that fails to decode can't be skipped
+    /// reliably.
+    pub(crate) fn into_stream(self) -> impl Stream<Item = Result<AvroValue<'a>, Error>> + 'a
+    where
+        R: 'a,
+    {
+        let state = (self, VecDeque::new(), false);
+
+        stream::unfold(state, |(mut datafile, mut values, failed)| async move {
+            loop {
+                if let Some(value) = values.pop_front() {
+                    return Some((Ok(value), (datafile, values, failed)));
+                }
+
+                if failed {
+                    return None;
+                }
+
+                match datafile.next_block().await? {
+                    Ok(block) => values.extend(block),
+                    Err(e) => return Some((Err(e), (datafile, values, true))),
+                }
+            }
+        })
+    }
+}
+
+/// Bytes read from a stream but not yet parsed.
+struct UnparsedInput<R> {
+    reader: R,
+    buffer: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin> UnparsedInput<R> {
+    /// Runs a synchronous parser over the buffered bytes, reading more
+    /// from the stream and trying again whenever it runs out of input.
+    /// Only the bytes the successful parse used are removed.
+    async fn parse<T, F>(&mut self, parse: F) -> Result<T, Error>
+    where
+        F: Fn(&mut &[u8]) -> Result<T, Error>,
+    {
+        loop {
+            let mut input = self.buffer.as_slice();
+
+            match parse(&mut input) {
+                Ok(parsed) => {
+                    let consumed = self.buffer.len() - input.len();
+                    self.buffer.drain(..consumed);
+                    return Ok(parsed);
+                }
+                Err(Error::IO(io::ErrorKind::UnexpectedEof)) => {
+                    let wanted = self.buffer.len() + 1;
+                    if !self.fill_to(wanted).await? {
+                        return Err(Error::IO(io::ErrorKind::UnexpectedEof));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Reads until at least `len` bytes are buffered. Returns false if
+    /// the stream ended first.
+    async fn fill_to(&mut self, len: usize) -> Result<bool, Error> {
+        while self.buffer.len() < len {
+            let start = self.buffer.len();
+            self.buffer.resize(start + READ_CHUNK_SIZE, 0);
+
+            let read = self.reader.read(&mut self.buffer[start..]).await;
+            self.buffer.truncate(start + *read.as_ref().unwrap_or(&0));
+
+            if read? == 0 {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream::StreamExt;
+
+    #[tokio::test]
+    async fn read_datafiles_from_async_streams() {
+        for path in [
+            "test_cases/record.avro",
+            "test_cases/string_deflate.avro",
+            "test_cases/string_snappy.avro",
+            "test_cases/union_string.avro",
+        ]
+        .iter()
+        {
+            let mut schema_registry = SchemaRegistry::new();
+            let expected: Vec<_> = AvroDatafile::open(path, &mut schema_registry).unwrap().collect();
+
+            let file = tokio::fs::File::open(path).await.unwrap();
+            let mut schema_registry = SchemaRegistry::new();
+            let datafile = AsyncAvroDatafile::from_reader(file, &mut schema_registry)
+                .await
+                .unwrap();
+            let actual: Vec<_> = datafile.into_stream().collect().await;
+
+            assert_eq!(actual, expected, "{}", path);
+        }
+
+        let file = tokio::fs::File::open("test_cases/non_avro_file").await.unwrap();
+        let mut schema_registry = SchemaRegistry::new();
+        assert_eq!(
+            AsyncAvroDatafile::from_reader(file, &mut schema_registry).await.err(),
+            Some(Error::InvalidFormat)
+        );
+
+        #[cfg(feature = "gzip")]
+        {
+            let mut schema_registry = SchemaRegistry::new();
+            let expected: Vec<_> = AvroDatafile::open("test_cases/string_gzip.avro", &mut schema_registry)
+                .unwrap()
+                .collect();
+
+            let file = tokio::fs::File::open("test_cases/string_gzip.avro").await.unwrap();
+            let mut schema_registry = SchemaRegistry::new();
+            let datafile = AsyncAvroDatafile::from_reader(file, &mut schema_registry)
+                .await
+                .unwrap();
+            let actual: Vec<_> = datafile.into_stream().collect().await;
+
+            assert_eq!(actual.len(), 3);
+            assert_eq!(actual, expected);
+        }
+
+        let mut truncated = std::fs::read("test_cases/string.avro").unwrap();
+        truncated.truncate(truncated.len() - 4);
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AsyncAvroDatafile::from_reader(truncated.as_slice(), &mut schema_registry)
+            .await
+            .unwrap();
+        let actual: Vec<_> = datafile.into_stream().collect().await;
+        assert_eq!(actual, vec![Err(Error::IO(io::ErrorKind::UnexpectedEof))]);
+    }
+}
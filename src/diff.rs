@@ -0,0 +1,249 @@
+use crate::resolution::type_name;
+use crate::schema::{NamedType, NamedTypeId, Schema, SchemaType};
+use std::collections::HashSet;
+
+/// A single difference between two versions of a schema. Paths start
+/// from the root type and follow field names, with `[]` marking array
+/// items and map values, e.g. `user.addresses[].city`.
+#[derive(Debug, PartialEq)]
+pub(crate) enum SchemaChange {
+    FieldAdded { path: String, field: String },
+    FieldRemoved { path: String, field: String },
+    TypeChanged { path: String, old: String, new: String },
+    UnionBranchAdded { path: String, branch: String },
+    UnionBranchRemoved { path: String, branch: String },
+    EnumSymbolAdded { path: String, symbol: String },
+    EnumSymbolRemoved { path: String, symbol: String },
+    FixedSizeChanged { path: String, old: usize, new: usize },
+}
+
+/// Lists the differences between an old and a new version of a schema.
+/// Named types are matched by fullname and union branches by their
+/// type name, so renaming a type shows up as a type change rather than
+/// as changes to its contents.
+pub(crate) fn schema_diff(old: &Schema, new: &Schema) -> Vec<SchemaChange> {
+    let mut differ = Differ {
+        old,
+        new,
+        visited: HashSet::new(),
+        changes: Vec::new(),
+    };
+
+    let path = type_name(old, old.root());
+    differ.diff_type(old.root(), new.root(), &path);
+    differ.changes
+}
+
+struct Differ<'s> {
+    old: &'s Schema,
+    new: &'s Schema,
+    visited: HashSet<(NamedTypeId, NamedTypeId)>,
+    changes: Vec<SchemaChange>,
+}
+
+impl<'s> Differ<'s> {
+    fn diff_type(&mut self, old_type: &SchemaType, new_type: &SchemaType, path: &str) {
+        match (old_type, new_type) {
+            (SchemaType::Array(old_items), SchemaType::Array(new_items))
+            | (SchemaType::Map(old_items), SchemaType::Map(new_items)) => {
+                self.diff_type(old_items, new_items, &format!("{}[]", path));
+            }
+            (SchemaType::Union(old_branches), SchemaType::Union(new_branches)) => {
+                self.diff_union(old_branches, new_branches, path);
+            }
+            (SchemaType::Reference(old_id), SchemaType::Reference(new_id))
+                if self.old.named_type_fullname(*old_id) == self.new.named_type_fullname(*new_id) =>
+            {
+                self.diff_named_type(*old_id, *new_id, path);
+            }
+            (old_type, new_type) => {
+                let old_name = type_name(self.old, old_type);
+                let new_name = type_name(self.new, new_type);
+
+                if old_name != new_name {
+                    self.changes.push(SchemaChange::TypeChanged {
+                        path: path.to_string(),
+                        old: old_name,
+                        new: new_name,
+                    });
+                }
+            }
+        }
+    }
+
+    fn diff_union(&mut self, old_branches: &[SchemaType], new_branches: &[SchemaType], path: &str) {
+        let old_names: Vec<String> = old_branches.iter().map(|branch| type_name(self.old, branch)).collect();
+        let new_names: Vec<String> = new_branches.iter().map(|branch| type_name(self.new, branch)).collect();
+
+        for (old_branch, old_name) in old_branches.iter().zip(&old_names) {
+            match new_names.iter().position(|new_name| new_name == old_name) {
+                Some(position) => self.diff_type(old_branch, &new_branches[position], path),
+                None => self.changes.push(SchemaChange::UnionBranchRemoved {
+                    path: path.to_string(),
+                    branch: old_name.clone(),
+                }),
+            }
+        }
+
+        for new_name in new_names.iter().filter(|new_name| !old_names.contains(new_name)) {
+            self.changes.push(SchemaChange::UnionBranchAdded {
+                path: path.to_string(),
+                branch: new_name.clone(),
+            });
+        }
+    }
+
+    fn diff_named_type(&mut self, old_id: NamedTypeId, new_id: NamedTypeId, path: &str) {
+        // Recursive types would otherwise be compared forever.
+        if !self.visited.insert((old_id, new_id)) {
+            return;
+        }
+
+        match (self.old.resolve_named_type(old_id), self.new.resolve_named_type(new_id)) {
+            (NamedType::Record(old_fields), NamedType::Record(new_fields)) => {
+                for old_field in old_fields {
+                    match new_fields.iter().find(|new_field| new_field.name() == old_field.name()) {
+                        Some(new_field) => self.diff_type(
+                            old_field.schema_type(),
+                            new_field.schema_type(),
+                            &format!("{}.{}", path, old_field.name()),
+                        ),
+                        None => self.changes.push(SchemaChange::FieldRemoved {
+                            path: path.to_string(),
+                            field: old_field.name().to_string(),
+                        }),
+                    }
+                }
+
+                for new_field in new_fields {
+                    if !old_fields.iter().any(|old_field| old_field.name() == new_field.name()) {
+                        self.changes.push(SchemaChange::FieldAdded {
+                            path: path.to_string(),
+                            field: new_field.name().to_string(),
+                        });
+                    }
+                }
+            }
+            (NamedType::Enum(old_symbols), NamedType::Enum(new_symbols)) => {
+                for symbol in old_symbols.symbols() {
+                    if new_symbols.symbol_index(symbol).is_none() {
+                        self.changes.push(SchemaChange::EnumSymbolRemoved {
+                            path: path.to_string(),
+                            symbol: symbol.clone(),
+                        });
+                    }
+                }
+
+                for symbol in new_symbols.symbols() {
+                    if old_symbols.symbol_index(symbol).is_none() {
+                        self.changes.push(SchemaChange::EnumSymbolAdded {
+                            path: path.to_string(),
+                            symbol: symbol.clone(),
+                        });
+                    }
+                }
+            }
+            (NamedType::Fixed(old_size), NamedType::Fixed(new_size)) => {
+                if old_size != new_size {
+                    self.changes.push(SchemaChange::FixedSizeChanged {
+                        path: path.to_string(),
+                        old: *old_size,
+                        new: *new_size,
+                    });
+                }
+            }
+            _ => self.changes.push(SchemaChange::TypeChanged {
+                path: path.to_string(),
+                old: self.old.named_type_fullname(old_id).to_string(),
+                new: self.new.named_type_fullname(new_id).to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_record_with_added_field() {
+        let old = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"}
+               ]}"#,
+        )
+        .unwrap();
+
+        let new = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "age", "type": ["null", "int"], "default": null}
+               ]}"#,
+        )
+        .unwrap();
+
+        let expected = vec![SchemaChange::FieldAdded {
+            path: "user".to_string(),
+            field: "age".to_string(),
+        }];
+
+        assert_eq!(schema_diff(&old, &new), expected);
+        assert_eq!(schema_diff(&old, &old), vec![]);
+    }
+
+    #[test]
+    fn diff_nested_types() {
+        let old = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "nickname", "type": ["null", "string"]},
+                 {"name": "suit", "type": {"type": "enum", "name": "suit", "symbols": ["HEARTS", "CLUBS"]}},
+                 {"name": "friends", "type": {"type": "array", "items": "user"}},
+                 {"name": "token", "type": {"type": "fixed", "name": "token", "size": 4}}
+               ]}"#,
+        )
+        .unwrap();
+
+        let new = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "bytes"},
+                 {"name": "nickname", "type": ["null", "bytes"]},
+                 {"name": "suit", "type": {"type": "enum", "name": "suit", "symbols": ["HEARTS", "SPADES"]}},
+                 {"name": "friends", "type": {"type": "array", "items": "user"}},
+                 {"name": "token", "type": {"type": "fixed", "name": "token", "size": 8}}
+               ]}"#,
+        )
+        .unwrap();
+
+        let expected = vec![
+            SchemaChange::TypeChanged {
+                path: "user.email".to_string(),
+                old: "string".to_string(),
+                new: "bytes".to_string(),
+            },
+            SchemaChange::UnionBranchRemoved {
+                path: "user.nickname".to_string(),
+                branch: "string".to_string(),
+            },
+            SchemaChange::UnionBranchAdded {
+                path: "user.nickname".to_string(),
+                branch: "bytes".to_string(),
+            },
+            SchemaChange::EnumSymbolRemoved {
+                path: "user.suit".to_string(),
+                symbol: "CLUBS".to_string(),
+            },
+            SchemaChange::EnumSymbolAdded {
+                path: "user.suit".to_string(),
+                symbol: "SPADES".to_string(),
+            },
+            SchemaChange::FixedSizeChanged {
+                path: "user.token".to_string(),
+                old: 4,
+                new: 8,
+            },
+        ];
+
+        assert_eq!(schema_diff(&old, &new), expected);
+    }
+}
@@ -1,11 +1,28 @@
 use crate::Error;
 use std::collections::HashMap;
-use std::io::Read;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
 
+/// Reads a boolean, returning `Error::BadEncoding` for any byte other
+/// than 0 or 1.
 pub(crate) fn read_bool<R: Read>(reader: &mut R) -> Result<bool, Error> {
+    match read_byte(reader)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(Error::BadEncoding),
+    }
+}
+
+/// Reads a boolean, treating any byte other than 1 as false.
+pub(crate) fn read_bool_lenient<R: Read>(reader: &mut R) -> Result<bool, Error> {
     Ok(read_byte(reader)? == 1)
 }
 
+pub(crate) fn write_bool<W: Write>(writer: &mut W, value: bool) -> Result<(), Error> {
+    writer.write_all(&[value as u8])?;
+    Ok(())
+}
+
 pub(crate) fn read_float<R: Read>(reader: &mut R) -> Result<f32, Error> {
     let mut buffer: [u8; 4] = [0; 4];
     reader.read_exact(&mut buffer)?;
@@ -14,6 +31,11 @@ pub(crate) fn read_float<R: Read>(reader: &mut R) -> Result<f32, Error> {
     Ok(f32::from_bits(int))
 }
 
+pub(crate) fn write_float<W: Write>(writer: &mut W, value: f32) -> Result<(), Error> {
+    writer.write_all(&value.to_bits().to_le_bytes())?;
+    Ok(())
+}
+
 pub(crate) fn read_double<R: Read>(reader: &mut R) -> Result<f64, Error> {
     let mut buffer: [u8; 8] = [0; 8];
     reader.read_exact(&mut buffer)?;
@@ -22,8 +44,13 @@ pub(crate) fn read_double<R: Read>(reader: &mut R) -> Result<f64, Error> {
     Ok(f64::from_bits(int))
 }
 
+pub(crate) fn write_double<W: Write>(writer: &mut W, value: f64) -> Result<(), Error> {
+    writer.write_all(&value.to_bits().to_le_bytes())?;
+    Ok(())
+}
+
 pub(crate) fn read_long<R: Read>(reader: &mut R) -> Result<i64, Error> {
-    Ok(read_varint_long(reader).map(decode_zigzag_long)?)
+    read_varint_long(reader).map(decode_zigzag_long)
 }
 
 fn decode_zigzag_long(encoded_value: u64) -> i64 {
@@ -49,6 +76,30 @@ fn read_varint_long<R: Read>(reader: &mut R) -> Result<u64, Error> {
     Ok(accum)
 }
 
+pub(crate) fn write_long<W: Write>(writer: &mut W, value: i64) -> Result<(), Error> {
+    write_varint_long(writer, encode_zigzag_long(value))
+}
+
+fn encode_zigzag_long(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn write_varint_long<W: Write>(writer: &mut W, mut value: u64) -> Result<(), Error> {
+    while value > 0b0111_1111 {
+        writer.write_all(&[(value as u8 & 0b0111_1111) | 0b1000_0000])?;
+        value >>= 7;
+    }
+
+    writer.write_all(&[value as u8])?;
+    Ok(())
+}
+
+/// Reads a long that's used as a length or count, returning
+/// `Error::BadEncoding` if it's negative or doesn't fit in a `usize`.
+pub(crate) fn read_len<R: Read>(reader: &mut R) -> Result<usize, Error> {
+    usize::try_from(read_long(reader)?).map_err(|_| Error::BadEncoding)
+}
+
 fn read_byte<R: Read>(reader: &mut R) -> Result<u8, Error> {
     let mut buffer: [u8; 1] = [0];
     reader.read_exact(&mut buffer)?;
@@ -56,17 +107,54 @@ fn read_byte<R: Read>(reader: &mut R) -> Result<u8, Error> {
 }
 
 pub(crate) fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, Error> {
-    let byte_length = read_long(reader)? as usize;
-    let mut buffer = vec![0; byte_length];
-    reader.read_exact(&mut buffer)?;
-    Ok(buffer)
+    read_bytes_up_to(reader, usize::MAX)
+}
+
+/// Reads bytes like `read_bytes`, returning `Error::LimitExceeded`
+/// before allocating anything if there are more than `max_len` of them.
+pub(crate) fn read_bytes_up_to<R: Read>(reader: &mut R, max_len: usize) -> Result<Vec<u8>, Error> {
+    let byte_length = read_len_up_to(reader, "bytes", max_len)?;
+    read_fixed(reader, byte_length)
+}
+
+pub(crate) fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<(), Error> {
+    write_long(writer, bytes.len() as i64)?;
+    writer.write_all(bytes)?;
+    Ok(())
 }
 
 pub(crate) fn read_string<R: Read>(reader: &mut R) -> Result<String, Error> {
-    let byte_length = read_long(reader)? as usize;
-    let mut buffer = vec![0; byte_length];
-    reader.read_exact(&mut buffer)?;
-    String::from_utf8(buffer).map_err(|_| Error::BadEncoding)
+    read_string_up_to(reader, usize::MAX)
+}
+
+/// Reads a string like `read_string`, returning `Error::LimitExceeded`
+/// before allocating anything if it's longer than `max_len` bytes.
+pub(crate) fn read_string_up_to<R: Read>(reader: &mut R, max_len: usize) -> Result<String, Error> {
+    let byte_length = read_len_up_to(reader, "string", max_len)?;
+    String::from_utf8(read_fixed(reader, byte_length)?).map_err(|_| Error::BadEncoding)
+}
+
+pub(crate) fn write_string<W: Write>(writer: &mut W, value: &str) -> Result<(), Error> {
+    write_bytes(writer, value.as_bytes())
+}
+
+pub(crate) fn read_string_lossy<R: Read>(reader: &mut R) -> Result<String, Error> {
+    read_string_lossy_up_to(reader, usize::MAX)
+}
+
+pub(crate) fn read_string_lossy_up_to<R: Read>(reader: &mut R, max_len: usize) -> Result<String, Error> {
+    let byte_length = read_len_up_to(reader, "string", max_len)?;
+    Ok(String::from_utf8_lossy(&read_fixed(reader, byte_length)?).into_owned())
+}
+
+fn read_len_up_to<R: Read>(reader: &mut R, kind: &'static str, max_len: usize) -> Result<usize, Error> {
+    let len = read_len(reader)?;
+
+    if len > max_len {
+        return Err(Error::LimitExceeded { kind, len });
+    }
+
+    Ok(len)
 }
 
 pub(crate) fn read_fixed<R: Read>(reader: &mut R, length: usize) -> Result<Vec<u8>, Error> {
@@ -75,32 +163,71 @@ pub(crate) fn read_fixed<R: Read>(reader: &mut R, length: usize) -> Result<Vec<u
     Ok(buffer)
 }
 
-pub(crate) fn read_metadata<R: Read>(reader: &mut R) -> Result<HashMap<String, String>, Error> {
-    let mut metadata: HashMap<String, String> = HashMap::new();
-    let mut num_values = read_block_count(reader)?;
+/// Copies a fixed value of the given length straight into `out`
+/// instead of collecting it in memory, for fixed types large enough
+/// that holding the whole value is wasteful.
+pub(crate) fn read_fixed_into<R: Read, W: Write>(reader: &mut R, length: usize, out: &mut W) -> Result<(), Error> {
+    let copied = io::copy(&mut reader.take(length as u64), out)?;
+    if copied < length as u64 {
+        return Err(Error::IO(io::ErrorKind::UnexpectedEof));
+    }
+
+    Ok(())
+}
+
+/// Reads a metadata map, which ends at its first block of zero entries.
+/// Returns `Error::InvalidFormat` if a block before that can't be read
+/// as entries, which is what happens when the terminating block is
+/// missing and the bytes after the map are read as more entries, or
+/// `Error::IO` if the input ends first.
+pub(crate) fn read_metadata<R: Read>(reader: &mut R) -> Result<HashMap<String, Vec<u8>>, Error> {
+    let unterminated = |e| match e {
+        e @ Error::IO(_) => e,
+        _ => Error::InvalidFormat,
+    };
+
+    let mut metadata: HashMap<String, Vec<u8>> = HashMap::new();
+
+    loop {
+        let num_values = read_block_count(reader).map_err(unterminated)?;
+        if num_values == 0 {
+            return Ok(metadata);
+        }
 
-    while num_values > 0 {
         for _ in 0..num_values {
-            let key = read_string(reader)?;
-            let value = read_string(reader)?;
+            let key = read_string(reader).map_err(unterminated)?;
+            let value = read_bytes(reader).map_err(unterminated)?;
 
             metadata.insert(key, value);
         }
+    }
+}
+
+/// Writes the metadata entries as a single block in the given order,
+/// so the same entries always produce the same bytes.
+pub(crate) fn write_metadata<W: Write>(writer: &mut W, entries: &[(&str, &[u8])]) -> Result<(), Error> {
+    if !entries.is_empty() {
+        write_long(writer, entries.len() as i64)?;
 
-        num_values = read_block_count(reader)?;
+        for (key, value) in entries {
+            write_string(writer, key)?;
+            write_bytes(writer, value)?;
+        }
     }
 
-    Ok(metadata)
+    write_long(writer, 0)
 }
 
-fn read_block_count<R: Read>(reader: &mut R) -> Result<i64, Error> {
+/// Reads the item count at the start of an array or map block. A
+/// negative count is followed by the block's size in bytes, which
+/// isn't needed when decoding every item.
+pub(crate) fn read_block_count<R: Read>(reader: &mut R) -> Result<usize, Error> {
     let num_values = read_long(reader)?;
     if num_values.is_negative() {
         let _block_size_in_bytes = read_long(reader)?;
-        Ok(num_values.abs())
-    } else {
-        Ok(num_values)
     }
+
+    usize::try_from(num_values.unsigned_abs()).map_err(|_| Error::BadEncoding)
 }
 
 #[cfg(test)]
@@ -151,7 +278,55 @@ mod tests {
         for (expected_value, encoded_value) in examples.iter() {
             let decoded_value = decode_zigzag_long(*encoded_value);
             assert_eq!(decoded_value, *expected_value);
+            assert_eq!(encode_zigzag_long(*expected_value), *encoded_value);
         }
+
+        assert_eq!(encode_zigzag_long(i64::MAX), u64::MAX - 1);
+        assert_eq!(encode_zigzag_long(i64::MIN), u64::MAX);
+    }
+
+    #[test]
+    fn read_lengths() {
+        let input = vec![0x00, 0x02, 0x80, 0x01, 0x01];
+        let mut reader = input.as_slice();
+
+        assert_eq!(read_len(&mut reader), Ok(0));
+        assert_eq!(read_len(&mut reader), Ok(1));
+        assert_eq!(read_len(&mut reader), Ok(64));
+        assert_eq!(read_len(&mut reader), Err(Error::BadEncoding));
+
+        // A length of 2^32 only fits in a usize on 64-bit targets.
+        let mut input = Vec::new();
+        write_long(&mut input, 1 << 32).unwrap();
+
+        let expected = if cfg!(target_pointer_width = "64") {
+            Ok(1 << 32)
+        } else {
+            Err(Error::BadEncoding)
+        };
+        assert_eq!(read_len(&mut input.as_slice()), expected.map(|len: u64| len as usize));
+
+        // Negative lengths are rejected rather than read as huge ones.
+        let input = vec![0x03, 0x66, 0x6f, 0x6f];
+        assert_eq!(read_string(&mut input.as_slice()), Err(Error::BadEncoding));
+    }
+
+    #[test]
+    fn read_block_counts() {
+        // A negative count is followed by the block's size in bytes.
+        let input = vec![0x04, 0x03, 0x10, 0x00];
+        let mut reader = input.as_slice();
+
+        assert_eq!(read_block_count(&mut reader), Ok(2));
+        assert_eq!(read_block_count(&mut reader), Ok(2));
+        assert_eq!(read_block_count(&mut reader), Ok(0));
+
+        let mut input = Vec::new();
+        write_long(&mut input, i64::MIN).unwrap();
+        write_long(&mut input, 0).unwrap();
+
+        let expected = usize::try_from(i64::MIN.unsigned_abs()).map_err(|_| Error::BadEncoding);
+        assert_eq!(read_block_count(&mut input.as_slice()), expected);
     }
 
     #[test]
@@ -169,6 +344,42 @@ mod tests {
         assert_eq!(read_long(&mut reader), Err(Error::IO(ErrorKind::UnexpectedEof)));
     }
 
+    #[test]
+    fn write_longs() {
+        // Taken from the example table in the Avro 1.10.1 specification
+        let mut output = Vec::new();
+
+        for value in [0, -1, 1, -2, -64, 64].iter() {
+            write_long(&mut output, *value).unwrap();
+        }
+
+        assert_eq!(output, vec![0x00, 0x01, 0x02, 0x03, 0x7f, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn write_and_read_back_longs() {
+        let mut values = vec![i64::MIN, i64::MIN + 1, i64::MAX - 1, i64::MAX];
+        for shift in 0..63 {
+            let power = 1i64 << shift;
+            values.extend_from_slice(&[power - 1, power, power + 1, -power - 1, -power, -power + 1]);
+        }
+
+        let mut output = Vec::new();
+        for value in values.iter() {
+            write_long(&mut output, *value).unwrap();
+        }
+
+        let mut reader = output.as_slice();
+        for value in values.iter() {
+            assert_eq!(read_long(&mut reader), Ok(*value));
+        }
+        assert!(reader.is_empty());
+
+        let mut output = Vec::new();
+        write_long(&mut output, i64::MIN).unwrap();
+        assert_eq!(output, vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+    }
+
     #[test]
     fn read_floats() {
         #[rustfmt::skip]
@@ -202,11 +413,28 @@ mod tests {
         let mut reader = input.as_slice();
 
         assert_eq!(read_double(&mut reader), Ok(0.0));
-        assert_eq!(read_double(&mut reader), Ok(std::f64::MIN));
-        assert_eq!(read_double(&mut reader), Ok(std::f64::MAX));
+        assert_eq!(read_double(&mut reader), Ok(f64::MIN));
+        assert_eq!(read_double(&mut reader), Ok(f64::MAX));
         assert_eq!(read_double(&mut reader), Err(Error::IO(ErrorKind::UnexpectedEof)));
     }
 
+    #[test]
+    fn encode_floats_and_doubles_little_endian() {
+        // The specification encodes floats and doubles as little-endian
+        // IEEE 754 bits, whatever the host's byte order.
+        let float_bytes = [0x00, 0x00, 0x80, 0x3f];
+        let double_bytes = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x3f];
+
+        assert_eq!(read_float(&mut float_bytes.as_ref()), Ok(1.0));
+        assert_eq!(read_double(&mut double_bytes.as_ref()), Ok(1.0));
+
+        let mut output = Vec::new();
+        write_float(&mut output, 1.0).unwrap();
+        write_double(&mut output, 1.0).unwrap();
+        assert_eq!(output[..4], float_bytes);
+        assert_eq!(output[4..], double_bytes);
+    }
+
     #[test]
     fn read_bools() {
         let input = vec![0x00, 0x01, 0x00];
@@ -216,6 +444,10 @@ mod tests {
         assert_eq!(read_bool(&mut reader), Ok(true));
         assert_eq!(read_bool(&mut reader), Ok(false));
         assert_eq!(read_bool(&mut reader), Err(Error::IO(ErrorKind::UnexpectedEof)));
+
+        assert_eq!(read_bool(&mut [0x02].as_ref()), Err(Error::BadEncoding));
+        assert_eq!(read_bool_lenient(&mut [0x02].as_ref()), Ok(false));
+        assert_eq!(read_bool_lenient(&mut [0x01].as_ref()), Ok(true));
     }
 
     #[test]
@@ -242,6 +474,68 @@ mod tests {
         assert_eq!(read_string(&mut reader), Err(Error::IO(ErrorKind::UnexpectedEof)));
     }
 
+    #[test]
+    fn write_strings_bytes_and_bools() {
+        let mut output = Vec::new();
+        for value in ["foo", "☃☃", ""].iter() {
+            write_string(&mut output, value).unwrap();
+        }
+        assert_eq!(
+            output[..11],
+            [0x06, 0x66, 0x6f, 0x6f, 0x0c, 0xe2, 0x98, 0x83, 0xe2, 0x98, 0x83]
+        );
+
+        let mut reader = output.as_slice();
+        assert_eq!(read_string(&mut reader), Ok("foo".to_string()));
+        assert_eq!(read_string(&mut reader), Ok("☃☃".to_string()));
+        assert_eq!(read_string(&mut reader), Ok("".to_string()));
+        assert!(reader.is_empty());
+
+        let mut output = Vec::new();
+        write_bytes(&mut output, &[0x05, 0x07]).unwrap();
+        write_bool(&mut output, true).unwrap();
+        write_bool(&mut output, false).unwrap();
+        assert_eq!(output, vec![0x04, 0x05, 0x07, 0x01, 0x00]);
+
+        let mut reader = output.as_slice();
+        assert_eq!(read_bytes(&mut reader), Ok(vec![0x05, 0x07]));
+        assert_eq!(read_bool(&mut reader), Ok(true));
+        assert_eq!(read_bool(&mut reader), Ok(false));
+    }
+
+    #[test]
+    fn read_zero_length_bytes_and_strings() {
+        let input = vec![0x00, 0x02];
+        let mut reader = input.as_slice();
+
+        assert_eq!(read_bytes(&mut reader), Ok(vec![]));
+        assert_eq!(reader, &[0x02]);
+
+        let mut reader = input.as_slice();
+
+        assert_eq!(read_string(&mut reader), Ok("".to_string()));
+        assert_eq!(reader, &[0x02]);
+    }
+
+    #[test]
+    fn read_fixed_into_writer() {
+        let input = vec![0x01, 0x02, 0x03, 0x04];
+        let mut reader = input.as_slice();
+        let mut out = Vec::new();
+
+        assert_eq!(read_fixed_into(&mut reader, 3, &mut out), Ok(()));
+        assert_eq!(out, vec![0x01, 0x02, 0x03]);
+        assert_eq!(reader, &[0x04]);
+
+        let mut reader = input.as_slice();
+        let mut out = Vec::new();
+
+        assert_eq!(
+            read_fixed_into(&mut reader, 5, &mut out),
+            Err(Error::IO(ErrorKind::UnexpectedEof))
+        );
+    }
+
     #[test]
     fn read_metadata_map() {
         let input = vec![
@@ -261,8 +555,66 @@ mod tests {
 
         let metadata = read_metadata(&mut reader).unwrap();
         assert_eq!(metadata.len(), 3);
-        assert_eq!(metadata.get("foo"), Some(&"bar".to_string()));
-        assert_eq!(metadata.get("baz"), Some(&"bat".to_string()));
-        assert_eq!(metadata.get("hello"), Some(&"world".to_string()));
+        assert_eq!(metadata.get("foo"), Some(&b"bar".to_vec()));
+        assert_eq!(metadata.get("baz"), Some(&b"bat".to_vec()));
+        assert_eq!(metadata.get("hello"), Some(&b"world".to_vec()));
+    }
+
+    #[test]
+    fn read_metadata_map_split_across_blocks() {
+        let input = vec![
+            0x02, // 1 key value pair in this block
+            0x02, 0x61, // "a"
+            0x02, 0x31, // "1"
+            0x03, // 2 key value pairs in this block, encoded as -2 to also specify length
+            0x10, // block is 8 bytes long
+            0x02, 0x62, // "b"
+            0x02, 0x32, // "2"
+            0x02, 0x63, // "c"
+            0x02, 0x33, // "3"
+            0x02, // 1 key value pair in this block
+            0x02, 0x64, // "d"
+            0x02, 0x34, // "4"
+            0x00, // end with empty block
+            0xff, // first byte after the metadata
+        ];
+
+        let mut reader = input.as_slice();
+
+        let metadata = read_metadata(&mut reader).unwrap();
+        assert_eq!(metadata.len(), 4);
+        assert_eq!(metadata.get("a"), Some(&b"1".to_vec()));
+        assert_eq!(metadata.get("b"), Some(&b"2".to_vec()));
+        assert_eq!(metadata.get("c"), Some(&b"3".to_vec()));
+        assert_eq!(metadata.get("d"), Some(&b"4".to_vec()));
+        assert_eq!(reader, &[0xff]);
+    }
+
+    #[test]
+    fn reject_metadata_map_without_terminating_block() {
+        let input = vec![
+            0x02, // 1 key value pair in this block
+            0x02, 0x61, // "a"
+            0x02, 0x31, // "1"
+            0x61, 0x62, 0x63, // no empty block, so the next bytes are read as entries
+        ];
+        assert_eq!(read_metadata(&mut input.as_slice()), Err(Error::InvalidFormat));
+
+        let input = vec![0x02, 0x02, 0x61, 0x02, 0x31];
+        assert_eq!(
+            read_metadata(&mut input.as_slice()),
+            Err(Error::IO(ErrorKind::UnexpectedEof))
+        );
+    }
+
+    #[test]
+    fn write_metadata_map() {
+        let mut output = Vec::new();
+        write_metadata(&mut output, &[("foo", b"bar"), ("hello", b"world")]).unwrap();
+
+        let metadata = read_metadata(&mut output.as_slice()).unwrap();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata.get("foo"), Some(&b"bar".to_vec()));
+        assert_eq!(metadata.get("hello"), Some(&b"world".to_vec()));
     }
 }
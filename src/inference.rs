@@ -0,0 +1,267 @@
+use crate::schema::Schema;
+use crate::{AvroValue, Error};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Builds a schema that `value` can be written with, naming the root
+/// type `name`. This is a best-effort guess meant for prototyping, and
+/// a sample can only describe the values it contains:
+///
+/// - Record fields are sorted by name. Nested records, enums, and
+///   fixed types are named after their path, e.g. `user_address`.
+/// - Array items and map values take the merged type of every element.
+///   Records merge field by field, and a field that's null or absent
+///   in some elements becomes `["null", T]`. Elements of otherwise
+///   different types can't be merged, so the first one wins and the
+///   rest won't match the schema.
+/// - A null with nothing to merge with stays `"null"`, so a null field
+///   or an empty array (`{"type": "array", "items": "null"}`) can't
+///   hold anything else.
+/// - Enums only know the symbols that appear in the sample.
+///
+/// Names aren't validated, so a `name` or field name that isn't a
+/// valid Avro name ends up in the schema as is. `Error::InvalidFormat`
+/// is returned if the generated schema can't be parsed.
+pub(crate) fn infer_schema(value: &AvroValue, name: &str) -> Result<Schema, Error> {
+    let json = infer_type(value, name);
+    Schema::parse(&json.to_string()).map_err(|_| Error::InvalidFormat)
+}
+
+fn infer_type(value: &AvroValue, name: &str) -> Value {
+    match value {
+        AvroValue::Null => json!("null"),
+        AvroValue::Boolean(_) => json!("boolean"),
+        AvroValue::Int(_) => json!("int"),
+        AvroValue::Long(_) => json!("long"),
+        AvroValue::Float(_) => json!("float"),
+        AvroValue::Double(_) => json!("double"),
+        AvroValue::String(_) => json!("string"),
+        AvroValue::Bytes(_) => json!("bytes"),
+        AvroValue::TimestampMillis(_) => json!({"type": "long", "logicalType": "timestamp-millis"}),
+        AvroValue::TimestampMicros(_) => json!({"type": "long", "logicalType": "timestamp-micros"}),
+        AvroValue::Array(items) => json!({
+            "type": "array",
+            "items": infer_merged_type(items.iter(), name),
+        }),
+        AvroValue::Map(entries) => {
+            let mut keys: Vec<&String> = entries.keys().collect();
+            keys.sort();
+
+            json!({
+                "type": "map",
+                "values": infer_merged_type(keys.into_iter().map(|key| &entries[key]), name),
+            })
+        }
+        AvroValue::Enum(symbol) => json!({"type": "enum", "name": name, "symbols": [symbol]}),
+        AvroValue::EnumUnknown(_) => json!({"type": "enum", "name": name, "symbols": []}),
+        AvroValue::Fixed(bytes) => json!({"type": "fixed", "name": name, "size": bytes.len()}),
+        AvroValue::Record(fields) => {
+            let mut field_names: Vec<&str> = fields.keys().copied().collect();
+            field_names.sort_unstable();
+
+            let fields: Vec<Value> = field_names
+                .into_iter()
+                .map(|field_name| {
+                    let field_type = infer_type(&fields[field_name], &format!("{}_{}", name, field_name));
+                    json!({"name": field_name, "type": field_type})
+                })
+                .collect();
+
+            json!({"type": "record", "name": name, "fields": fields})
+        }
+    }
+}
+
+fn infer_merged_type<'v, 'a: 'v, I: Iterator<Item = &'v AvroValue<'a>>>(values: I, name: &str) -> Value {
+    values
+        .map(|value| infer_type(value, name))
+        .fold(None, |merged, value_type| match merged {
+            Some(merged) => Some(merge_types(merged, value_type)),
+            None => Some(value_type),
+        })
+        .unwrap_or_else(|| json!("null"))
+}
+
+fn merge_types(first: Value, second: Value) -> Value {
+    let (first_nullable, first) = split_nullable(first);
+    let (second_nullable, second) = split_nullable(second);
+
+    let merged = match (first, second) {
+        (Some(first), Some(second)) => Some(merge_non_null_types(first, second)),
+        (first, second) => first.or(second),
+    };
+
+    match merged {
+        Some(merged) if first_nullable || second_nullable => json!(["null", merged]),
+        Some(merged) => merged,
+        None => json!("null"),
+    }
+}
+
+/// Separates `"null"` and `["null", T]` into whether the type allows
+/// nulls and the non-null part, if there is one.
+fn split_nullable(schema_type: Value) -> (bool, Option<Value>) {
+    match schema_type {
+        Value::String(ref name) if name == "null" => (true, None),
+        Value::Array(mut branches) if branches.len() == 2 && branches[0] == "null" => (true, branches.pop()),
+        schema_type => (false, Some(schema_type)),
+    }
+}
+
+fn merge_non_null_types(first: Value, second: Value) -> Value {
+    if first == second {
+        return first;
+    }
+
+    match (first, second) {
+        (Value::Object(mut first), Value::Object(mut second)) if first.get("type") == second.get("type") => {
+            match first.get("type").and_then(Value::as_str) {
+                Some("record") => {
+                    let fields = merge_fields(first.remove("fields"), second.remove("fields"));
+                    first.insert("fields".to_string(), fields);
+                }
+                Some("enum") => {
+                    let mut symbols = take_array(first.remove("symbols"));
+                    for symbol in take_array(second.remove("symbols")) {
+                        if !symbols.contains(&symbol) {
+                            symbols.push(symbol);
+                        }
+                    }
+                    first.insert("symbols".to_string(), Value::Array(symbols));
+                }
+                Some("array") => merge_attribute(&mut first, &mut second, "items"),
+                Some("map") => merge_attribute(&mut first, &mut second, "values"),
+                _ => {}
+            }
+
+            Value::Object(first)
+        }
+        (first, _) => first,
+    }
+}
+
+fn merge_attribute(first: &mut Map<String, Value>, second: &mut Map<String, Value>, key: &str) {
+    let merged = match (first.remove(key), second.remove(key)) {
+        (Some(first), Some(second)) => merge_types(first, second),
+        (first, second) => first.or(second).unwrap_or_else(|| json!("null")),
+    };
+
+    first.insert(key.to_string(), merged);
+}
+
+fn merge_fields(first: Option<Value>, second: Option<Value>) -> Value {
+    let mut first: BTreeMap<String, Value> = field_types(first).into_iter().collect();
+    let mut second: BTreeMap<String, Value> = field_types(second).into_iter().collect();
+    let names: BTreeSet<String> = first.keys().chain(second.keys()).cloned().collect();
+
+    let fields = names
+        .into_iter()
+        .map(|name| {
+            let field_type = match (first.remove(&name), second.remove(&name)) {
+                (Some(first), Some(second)) => merge_types(first, second),
+                // A field that's absent from one of the records has to
+                // be written as null there.
+                (first, second) => merge_types(first.or(second).unwrap_or_else(|| json!("null")), json!("null")),
+            };
+
+            json!({"name": name, "type": field_type})
+        })
+        .collect();
+
+    Value::Array(fields)
+}
+
+fn field_types(fields: Option<Value>) -> Vec<(String, Value)> {
+    take_array(fields)
+        .into_iter()
+        .filter_map(|field| match field {
+            Value::Object(mut field) => {
+                let name = field.remove("name")?.as_str()?.to_string();
+                Some((name, field.remove("type")?))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn take_array(value: Option<Value>) -> Vec<Value> {
+    match value {
+        Some(Value::Array(values)) => values,
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn infer_schema_from_record() {
+        let mut address = HashMap::new();
+        address.insert("city", AvroValue::String("Newport Beach".to_string()));
+
+        let mut scores = HashMap::new();
+        scores.insert("math".to_string(), AvroValue::Double(92.5));
+
+        let mut user = HashMap::new();
+        user.insert("email", AvroValue::String("bloblaw@example.com".to_string()));
+        user.insert("age", AvroValue::Int(42));
+        user.insert("tags", AvroValue::Array(vec![AvroValue::String("lawyer".to_string())]));
+        user.insert("scores", AvroValue::Map(scores));
+        user.insert("address", AvroValue::Record(address));
+        user.insert("status", AvroValue::Enum("ACTIVE"));
+        user.insert("token", AvroValue::Fixed(vec![1, 2, 3, 4]));
+        user.insert("nickname", AvroValue::Null);
+        user.insert("aliases", AvroValue::Array(vec![]));
+
+        let expected = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "address", "type": {"type": "record", "name": "user_address", "fields": [
+                   {"name": "city", "type": "string"}
+                 ]}},
+                 {"name": "age", "type": "int"},
+                 {"name": "aliases", "type": {"type": "array", "items": "null"}},
+                 {"name": "email", "type": "string"},
+                 {"name": "nickname", "type": "null"},
+                 {"name": "scores", "type": {"type": "map", "values": "double"}},
+                 {"name": "status", "type": {"type": "enum", "name": "user_status", "symbols": ["ACTIVE"]}},
+                 {"name": "tags", "type": {"type": "array", "items": "string"}},
+                 {"name": "token", "type": {"type": "fixed", "name": "user_token", "size": 4}}
+               ]}"#,
+        )
+        .unwrap();
+
+        let actual = infer_schema(&AvroValue::Record(user), "user").unwrap();
+        assert_eq!(actual.to_json_sorted(), expected.to_json_sorted());
+    }
+
+    #[test]
+    fn merge_array_items_into_nullable_fields() {
+        let mut first = HashMap::new();
+        first.insert("email", AvroValue::String("bloblaw@example.com".to_string()));
+        first.insert("age", AvroValue::Int(42));
+        first.insert("suit", AvroValue::Enum("HEARTS"));
+
+        let mut second = HashMap::new();
+        second.insert("email", AvroValue::Null);
+        second.insert("nickname", AvroValue::String("G.O.B.".to_string()));
+        second.insert("age", AvroValue::Int(16));
+        second.insert("suit", AvroValue::Enum("SPADES"));
+
+        let users = AvroValue::Array(vec![AvroValue::Record(first), AvroValue::Record(second)]);
+
+        let expected = Schema::parse(
+            r#"{"type": "array", "items": {"type": "record", "name": "user", "fields": [
+                 {"name": "age", "type": "int"},
+                 {"name": "email", "type": ["null", "string"]},
+                 {"name": "nickname", "type": ["null", "string"]},
+                 {"name": "suit", "type": {"type": "enum", "name": "user_suit", "symbols": ["HEARTS", "SPADES"]}}
+               ]}}"#,
+        )
+        .unwrap();
+
+        let actual = infer_schema(&users, "user").unwrap();
+        assert_eq!(actual.to_json_sorted(), expected.to_json_sorted());
+    }
+}
@@ -0,0 +1,357 @@
+use crate::encoding;
+use crate::resolution::type_name;
+use crate::schema::{default_bytes, Field, NamedType, Schema, SchemaType};
+use crate::{default_value, is_value_of_type, AvroDatafile, AvroValue, Error, ReadOptions, DEFAULT_MAX_DEPTH};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Read;
+
+/// Decodes a value straight into its JSON encoding, following the same
+/// rules as `value_to_json` without building an `AvroValue` first.
+/// Primitive and fixed values are decoded by `AvroDatafile`, so the
+/// read options apply just as they do to `AvroValue`s.
+pub(crate) fn read_json_value<R: Read>(
+    reader: &mut R,
+    schema_type: &SchemaType,
+    schema: &Schema,
+    options: &ReadOptions,
+    depth: usize,
+) -> Result<Value, Error> {
+    if depth > options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH) {
+        return Err(Error::LimitExceeded {
+            kind: "depth",
+            len: depth,
+        });
+    }
+
+    match schema_type {
+        SchemaType::Union(branches) => {
+            let index = encoding::read_len(reader)?;
+            let branch = branches.get(index).ok_or(Error::InvalidFormat)?;
+
+            let value = read_json_value(reader, branch, schema, options, depth + 1)?;
+            Ok(union_to_json(value, branch, schema))
+        }
+        SchemaType::Array(item_type) => {
+            let mut items = Vec::new();
+            let mut num_values = encoding::read_block_count(reader)?;
+
+            while num_values > 0 {
+                for _ in 0..num_values {
+                    items.push(read_json_value(reader, item_type, schema, options, depth + 1)?);
+                }
+
+                num_values = encoding::read_block_count(reader)?;
+            }
+
+            Ok(Value::Array(items))
+        }
+        SchemaType::Map(value_type) => {
+            let mut entries = Map::new();
+            let mut num_values = encoding::read_block_count(reader)?;
+
+            while num_values > 0 {
+                for _ in 0..num_values {
+                    let key = encoding::read_string(reader)?;
+                    let value = read_json_value(reader, value_type, schema, options, depth + 1)?;
+
+                    entries.insert(key, value);
+                }
+
+                num_values = encoding::read_block_count(reader)?;
+            }
+
+            Ok(Value::Object(entries))
+        }
+        SchemaType::Reference(id) => match schema.resolve_named_type(*id) {
+            NamedType::Fixed(size) => Ok(primitive_to_json(AvroDatafile::read_fixed(reader, *size, options)?)),
+            NamedType::Enum(symbols) => Ok(Value::from(AvroDatafile::read_enum_value(reader, symbols)?)),
+            NamedType::Record(fields) => {
+                let mut field_values = Map::new();
+
+                for (index, field) in fields.iter().enumerate() {
+                    let value = read_json_value(reader, field.schema_type(), schema, options, depth + 1)
+                        .map_err(|e| e.in_field(index, field.name()))?;
+                    field_values.insert(field.name().to_string(), value);
+                }
+
+                Ok(Value::Object(field_values))
+            }
+        },
+        primitive_type => Ok(primitive_to_json(AvroDatafile::read_primitive(
+            reader,
+            primitive_type,
+            options,
+        )?)),
+    }
+}
+
+/// Converts a value decoded by `AvroDatafile::read_primitive` or
+/// `AvroDatafile::read_fixed`, so the options those honor, such as
+/// `bytes_as_base64`, apply to JSON the same way.
+fn primitive_to_json(value: AvroValue) -> Value {
+    match value {
+        AvroValue::Null => Value::Null,
+        AvroValue::Boolean(value) => Value::Bool(value),
+        AvroValue::Int(value) => Value::from(value),
+        AvroValue::Long(value) | AvroValue::TimestampMillis(value) | AvroValue::TimestampMicros(value) => {
+            Value::from(value)
+        }
+        AvroValue::Float(value) => Value::from(value),
+        AvroValue::Double(value) => Value::from(value),
+        AvroValue::Bytes(value) | AvroValue::Fixed(value) => bytes_to_json(&value),
+        AvroValue::String(value) => Value::String(value),
+        value => unreachable!("{:?} isn't a primitive value", value),
+    }
+}
+
+/// Converts a value into the specification's JSON encoding for the
+/// given type. Bytes and fixed values become strings whose code points
+/// are the byte values, and non-null union values are wrapped in an
+/// object keyed by the branch's type name, e.g. `{"string": "foo"}`.
+/// Returns `Error::SchemaMismatch` if the value doesn't fit the type.
+pub(crate) fn value_to_json(value: &AvroValue, schema_type: &SchemaType, schema: &Schema) -> Result<Value, Error> {
+    match (value, schema_type) {
+        (value, SchemaType::Union(branches)) => {
+            let branch = branches
+                .iter()
+                .find(|branch| is_value_of_type(value, branch, schema))
+                .ok_or(Error::SchemaMismatch)?;
+
+            Ok(union_to_json(value_to_json(value, branch, schema)?, branch, schema))
+        }
+        (AvroValue::Null, SchemaType::Null) => Ok(Value::Null),
+        (AvroValue::Boolean(value), SchemaType::Boolean) => Ok(Value::Bool(*value)),
+        (AvroValue::Int(value), SchemaType::Int) => Ok(Value::from(*value)),
+        (AvroValue::Long(value), SchemaType::Long)
+        | (AvroValue::TimestampMillis(value), SchemaType::TimestampMillis)
+        | (AvroValue::TimestampMicros(value), SchemaType::TimestampMicros) => Ok(Value::from(*value)),
+        (AvroValue::Float(value), SchemaType::Float) => Ok(Value::from(*value)),
+        (AvroValue::Double(value), SchemaType::Double) => Ok(Value::from(*value)),
+        (AvroValue::Bytes(value), SchemaType::Bytes) => Ok(bytes_to_json(value)),
+        (AvroValue::String(value), SchemaType::String) => Ok(Value::String(value.clone())),
+        (AvroValue::Array(items), SchemaType::Array(item_type)) => items
+            .iter()
+            .map(|item| value_to_json(item, item_type, schema))
+            .collect::<Result<_, Error>>()
+            .map(Value::Array),
+        (AvroValue::Map(entries), SchemaType::Map(value_type)) => entries
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), value_to_json(value, value_type, schema)?)))
+            .collect::<Result<_, Error>>()
+            .map(Value::Object),
+        (value, SchemaType::Reference(id)) => match (value, schema.resolve_named_type(*id)) {
+            (AvroValue::Fixed(value), NamedType::Fixed(size)) if value.len() == *size => Ok(bytes_to_json(value)),
+            (AvroValue::Enum(symbol), NamedType::Enum(symbols)) if symbols.symbol_index(symbol).is_some() => {
+                Ok(Value::from(*symbol))
+            }
+            (AvroValue::Record(field_values), NamedType::Record(fields)) => fields
+                .iter()
+                .map(|field| {
+                    let value = field_values.get(field.name()).ok_or(Error::SchemaMismatch)?;
+                    Ok((
+                        field.name().to_string(),
+                        value_to_json(value, field.schema_type(), schema)?,
+                    ))
+                })
+                .collect::<Result<_, Error>>()
+                .map(Value::Object),
+            _ => Err(Error::SchemaMismatch),
+        },
+        _ => Err(Error::SchemaMismatch),
+    }
+}
+
+/// Converts a top-level record's fields, using the type from
+/// `field_overrides` for any field that has one. Fields missing from
+/// the record, as in a salvaged partial record, are left out.
+pub(crate) fn record_to_json(
+    field_values: &HashMap<&str, AvroValue>,
+    fields: &[Field],
+    field_overrides: Option<&HashMap<String, SchemaType>>,
+    schema: &Schema,
+) -> Result<Value, Error> {
+    let mut json_fields = Map::new();
+
+    for field in fields {
+        if let Some(value) = field_values.get(field.name()) {
+            let field_type = field_overrides
+                .and_then(|overrides| overrides.get(field.name()))
+                .unwrap_or_else(|| field.schema_type());
+
+            json_fields.insert(field.name().to_string(), value_to_json(value, field_type, schema)?);
+        }
+    }
+
+    Ok(Value::Object(json_fields))
+}
+
+/// Converts a value in the specification's JSON encoding into an
+/// `AvroValue` of the given type, reversing `value_to_json`. Record
+/// fields missing from the JSON take their defaults. Returns
+/// `Error::SchemaMismatch` if the JSON doesn't fit the type.
+pub(crate) fn json_to_value<'a>(
+    json: &Value,
+    schema_type: &'a SchemaType,
+    schema: &'a Schema,
+) -> Result<AvroValue<'a>, Error> {
+    match (schema_type, json) {
+        (SchemaType::Null, Value::Null) => Ok(AvroValue::Null),
+        (SchemaType::Boolean, Value::Bool(value)) => Ok(AvroValue::Boolean(*value)),
+        (SchemaType::Int, Value::Number(value)) => value
+            .as_i64()
+            .and_then(|value| i32::try_from(value).ok())
+            .map(AvroValue::Int)
+            .ok_or(Error::SchemaMismatch),
+        (SchemaType::Long, Value::Number(value)) => value.as_i64().map(AvroValue::Long).ok_or(Error::SchemaMismatch),
+        (SchemaType::TimestampMillis, Value::Number(value)) => value
+            .as_i64()
+            .map(AvroValue::TimestampMillis)
+            .ok_or(Error::SchemaMismatch),
+        (SchemaType::TimestampMicros, Value::Number(value)) => value
+            .as_i64()
+            .map(AvroValue::TimestampMicros)
+            .ok_or(Error::SchemaMismatch),
+        (SchemaType::Float, Value::Number(value)) => value
+            .as_f64()
+            .map(|value| AvroValue::Float(value as f32))
+            .ok_or(Error::SchemaMismatch),
+        (SchemaType::Double, Value::Number(value)) => {
+            value.as_f64().map(AvroValue::Double).ok_or(Error::SchemaMismatch)
+        }
+        (SchemaType::Bytes, Value::String(value)) => {
+            default_bytes(value).map(AvroValue::Bytes).ok_or(Error::SchemaMismatch)
+        }
+        (SchemaType::String, Value::String(value)) => Ok(AvroValue::String(value.clone())),
+        (SchemaType::Union(branches), Value::Null) if branches.contains(&SchemaType::Null) => Ok(AvroValue::Null),
+        (SchemaType::Union(branches), Value::Object(wrapper)) if wrapper.len() == 1 => {
+            let (branch_name, value) = wrapper.iter().next().unwrap();
+            let branch = branches
+                .iter()
+                .find(|branch| **branch != SchemaType::Null && union_branch_name(branch, schema) == *branch_name)
+                .ok_or(Error::SchemaMismatch)?;
+
+            json_to_value(value, branch, schema)
+        }
+        (SchemaType::Array(item_type), Value::Array(items)) => items
+            .iter()
+            .map(|item| json_to_value(item, item_type, schema))
+            .collect::<Result<_, Error>>()
+            .map(AvroValue::Array),
+        (SchemaType::Map(value_type), Value::Object(entries)) => entries
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), json_to_value(value, value_type, schema)?)))
+            .collect::<Result<_, Error>>()
+            .map(AvroValue::Map),
+        (SchemaType::Reference(id), json) => match (schema.resolve_named_type(*id), json) {
+            (NamedType::Fixed(size), Value::String(value)) => default_bytes(value)
+                .filter(|bytes| bytes.len() == *size)
+                .map(AvroValue::Fixed)
+                .ok_or(Error::SchemaMismatch),
+            (NamedType::Enum(symbols), Value::String(value)) => symbols
+                .symbol_index(value)
+                .and_then(|index| symbols.symbol(index))
+                .map(AvroValue::Enum)
+                .ok_or(Error::SchemaMismatch),
+            (NamedType::Record(fields), Value::Object(field_values)) => {
+                if field_values
+                    .keys()
+                    .any(|name| !fields.iter().any(|field| field.name() == name))
+                {
+                    return Err(Error::SchemaMismatch);
+                }
+
+                let mut values = HashMap::with_capacity(fields.len());
+
+                for field in fields {
+                    let value = match (field_values.get(field.name()), field.default()) {
+                        (Some(value), _) => json_to_value(value, field.schema_type(), schema)?,
+                        (None, Some(default)) => default_value(default, field.schema_type(), schema)?,
+                        (None, None) => return Err(Error::SchemaMismatch),
+                    };
+
+                    values.insert(field.name(), value);
+                }
+
+                Ok(AvroValue::Record(values))
+            }
+            _ => Err(Error::SchemaMismatch),
+        },
+        _ => Err(Error::SchemaMismatch),
+    }
+}
+
+fn bytes_to_json(bytes: &[u8]) -> Value {
+    Value::String(bytes.iter().map(|byte| char::from(*byte)).collect())
+}
+
+fn union_to_json(value: Value, branch: &SchemaType, schema: &Schema) -> Value {
+    match branch {
+        SchemaType::Null => Value::Null,
+        branch => {
+            let mut wrapper = Map::new();
+            wrapper.insert(union_branch_name(branch, schema), value);
+            Value::Object(wrapper)
+        }
+    }
+}
+
+/// Logical types are named after their underlying type in the JSON
+/// encoding, since the specification only names them in schemas.
+fn union_branch_name(branch: &SchemaType, schema: &Schema) -> String {
+    match branch {
+        SchemaType::TimestampMillis | SchemaType::TimestampMicros => "long".to_string(),
+        branch => type_name(schema, branch),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn convert_values_to_json() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "user", "namespace": "com.example", "fields": [
+                 {"name": "nickname", "type": ["null", "string"]},
+                 {"name": "token", "type": ["null", {"type": "fixed", "name": "token", "size": 2}]},
+                 {"name": "scores", "type": {"type": "array", "items": "int"}}
+               ]}"#,
+        )
+        .unwrap();
+
+        let mut fields = std::collections::HashMap::new();
+        fields.insert("nickname", AvroValue::String("al".to_string()));
+        fields.insert("token", AvroValue::Fixed(vec![0x00, 0xff]));
+        fields.insert("scores", AvroValue::Array(vec![AvroValue::Int(1), AvroValue::Int(2)]));
+        let value = AvroValue::Record(fields);
+
+        let expected = json!({
+            "nickname": {"string": "al"},
+            "token": {"com.example.token": "\u{0}\u{ff}"},
+            "scores": [1, 2],
+        });
+
+        assert_eq!(value_to_json(&value, schema.root(), &schema), Ok(expected.clone()));
+
+        let encoded = crate::encode(&value, &schema).unwrap();
+        let decoded = read_json_value(&mut encoded.as_slice(), schema.root(), &schema, &ReadOptions::new(), 0);
+        assert_eq!(decoded, Ok(expected));
+
+        assert_eq!(
+            value_to_json(&AvroValue::Int(1), &SchemaType::String, &schema),
+            Err(Error::SchemaMismatch)
+        );
+
+        let json = value_to_json(&value, schema.root(), &schema).unwrap();
+        assert_eq!(json_to_value(&json, schema.root(), &schema), Ok(value));
+
+        let invalid_json = json!({"nickname": "al", "token": null, "scores": []});
+        assert_eq!(
+            json_to_value(&invalid_json, schema.root(), &schema),
+            Err(Error::SchemaMismatch)
+        );
+    }
+}
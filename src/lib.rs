@@ -1,14 +1,35 @@
 #![allow(dead_code)]
 
+#[cfg(feature = "tokio")]
+mod async_datafile;
+mod diff;
 mod encoding;
+mod inference;
+mod json;
+mod pretty;
+mod resolution;
 mod schema;
 
-use flate2::bufread::DeflateDecoder;
-use schema::{Field, NamedType, Schema, SchemaType};
-use std::collections::HashMap;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+#[cfg(feature = "gzip")]
+use flate2::bufread::GzDecoder;
+use flate2::write::DeflateEncoder;
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+use flate2::{Compression, Decompress, FlushDecompress, Status};
+use resolution::{ResolutionError, Resolver};
+use schema::{EnumSymbols, Field, NamedType, RootKind, Schema, SchemaType};
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Read};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::sync::Arc;
 
 #[derive(PartialEq, Debug)]
 enum AvroValue<'a> {
@@ -20,19 +41,336 @@ enum AvroValue<'a> {
     Double(f64),
     String(String),
     Bytes(Vec<u8>),
+    /// Milliseconds since the Unix epoch.
+    TimestampMillis(i64),
+    /// Microseconds since the Unix epoch.
+    TimestampMicros(i64),
     Array(Vec<AvroValue<'a>>),
     Map(HashMap<String, AvroValue<'a>>),
     Enum(&'a str),
+    /// A writer's enum symbol that the reader's enum doesn't have,
+    /// identified by its index in the writer's symbols. Only produced
+    /// when reading with `ReadOptions::keep_unknown_enum_symbols`.
+    EnumUnknown(u32),
     Fixed(Vec<u8>),
     Record(HashMap<&'a str, AvroValue<'a>>),
 }
 
+impl<'a> AvroValue<'a> {
+    /// The name of the Avro type the value holds, e.g. `"long"` or
+    /// `"record"`, for describing values in errors.
+    fn type_name(&self) -> &'static str {
+        match self {
+            AvroValue::Null => "null",
+            AvroValue::Boolean(_) => "boolean",
+            AvroValue::Int(_) => "int",
+            AvroValue::Long(_) => "long",
+            AvroValue::Float(_) => "float",
+            AvroValue::Double(_) => "double",
+            AvroValue::String(_) => "string",
+            AvroValue::Bytes(_) => "bytes",
+            AvroValue::TimestampMillis(_) => "timestamp-millis",
+            AvroValue::TimestampMicros(_) => "timestamp-micros",
+            AvroValue::Array(_) => "array",
+            AvroValue::Map(_) => "map",
+            AvroValue::Enum(_) | AvroValue::EnumUnknown(_) => "enum",
+            AvroValue::Fixed(_) => "fixed",
+            AvroValue::Record(_) => "record",
+        }
+    }
+
+    /// Converts a map's values into `T`, e.g. into a `HashMap<String,
+    /// i64>`. Returns `Error::ValueMismatch` if this isn't a map or if
+    /// any of its values can't be converted.
+    fn into_string_map<T: TryFrom<AvroValue<'a>, Error = Error>>(self) -> Result<HashMap<String, T>, Error> {
+        match self {
+            AvroValue::Map(entries) => entries
+                .into_iter()
+                .map(|(key, value)| Ok((key, T::try_from(value)?)))
+                .collect(),
+            value => Err(value.mismatch("map")),
+        }
+    }
+
+    fn mismatch(&self, expected: &str) -> Error {
+        Error::ValueMismatch {
+            expected: expected.to_string(),
+            found: self.type_name(),
+        }
+    }
+
+    /// Converts the value into one that doesn't borrow from its schema.
+    /// Field names and enum symbols come from `interner`, so values
+    /// converted with the same interner share them.
+    fn into_owned(self, interner: &mut NameInterner) -> OwnedValue {
+        match self {
+            AvroValue::Null => OwnedValue::Null,
+            AvroValue::Boolean(value) => OwnedValue::Boolean(value),
+            AvroValue::Int(value) => OwnedValue::Int(value),
+            AvroValue::Long(value) => OwnedValue::Long(value),
+            AvroValue::Float(value) => OwnedValue::Float(value),
+            AvroValue::Double(value) => OwnedValue::Double(value),
+            AvroValue::String(value) => OwnedValue::String(value),
+            AvroValue::Bytes(value) => OwnedValue::Bytes(value),
+            AvroValue::TimestampMillis(value) => OwnedValue::TimestampMillis(value),
+            AvroValue::TimestampMicros(value) => OwnedValue::TimestampMicros(value),
+            AvroValue::Array(items) => {
+                OwnedValue::Array(items.into_iter().map(|item| item.into_owned(interner)).collect())
+            }
+            AvroValue::Map(entries) => OwnedValue::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into_owned(interner)))
+                    .collect(),
+            ),
+            AvroValue::Enum(symbol) => OwnedValue::Enum(interner.intern(symbol)),
+            AvroValue::EnumUnknown(index) => OwnedValue::EnumUnknown(index),
+            AvroValue::Fixed(value) => OwnedValue::Fixed(value),
+            AvroValue::Record(fields) => OwnedValue::Record(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| (interner.intern(name), value.into_owned(interner)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// An `AvroValue` that doesn't borrow from its schema, for keeping
+/// values around after the schema registry is gone. Record field names
+/// and enum symbols are shared `Arc<str>`s rather than one allocation
+/// per value.
+#[derive(PartialEq, Debug)]
+enum OwnedValue {
+    Null,
+    Boolean(bool),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    TimestampMillis(i64),
+    TimestampMicros(i64),
+    Array(Vec<OwnedValue>),
+    Map(HashMap<String, OwnedValue>),
+    Enum(Arc<str>),
+    EnumUnknown(u32),
+    Fixed(Vec<u8>),
+    Record(HashMap<Arc<str>, OwnedValue>),
+}
+
+/// Hands out one shared `Arc<str>` per distinct name.
+#[derive(Debug, Default)]
+struct NameInterner {
+    names: HashSet<Arc<str>>,
+}
+
+impl NameInterner {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn intern(&mut self, name: &str) -> Arc<str> {
+        if let Some(interned) = self.names.get(name) {
+            return Arc::clone(interned);
+        }
+
+        let interned: Arc<str> = Arc::from(name);
+        self.names.insert(Arc::clone(&interned));
+        interned
+    }
+}
+
+// Conversions into Rust types accept the values the matching Avro type
+// can be promoted from, so an int converts into an i64 but not the other
+// way around.
+
+impl<'a> TryFrom<AvroValue<'a>> for bool {
+    type Error = Error;
+
+    fn try_from(value: AvroValue<'a>) -> Result<Self, Error> {
+        match value {
+            AvroValue::Boolean(value) => Ok(value),
+            value => Err(value.mismatch("boolean")),
+        }
+    }
+}
+
+impl<'a> TryFrom<AvroValue<'a>> for i32 {
+    type Error = Error;
+
+    fn try_from(value: AvroValue<'a>) -> Result<Self, Error> {
+        match value {
+            AvroValue::Int(value) => Ok(value),
+            value => Err(value.mismatch("int")),
+        }
+    }
+}
+
+impl<'a> TryFrom<AvroValue<'a>> for i64 {
+    type Error = Error;
+
+    fn try_from(value: AvroValue<'a>) -> Result<Self, Error> {
+        match value {
+            AvroValue::Int(value) => Ok(value as i64),
+            AvroValue::Long(value) => Ok(value),
+            value => Err(value.mismatch("long")),
+        }
+    }
+}
+
+impl<'a> TryFrom<AvroValue<'a>> for f32 {
+    type Error = Error;
+
+    fn try_from(value: AvroValue<'a>) -> Result<Self, Error> {
+        match value {
+            AvroValue::Int(value) => Ok(value as f32),
+            AvroValue::Long(value) => Ok(value as f32),
+            AvroValue::Float(value) => Ok(value),
+            value => Err(value.mismatch("float")),
+        }
+    }
+}
+
+impl<'a> TryFrom<AvroValue<'a>> for f64 {
+    type Error = Error;
+
+    fn try_from(value: AvroValue<'a>) -> Result<Self, Error> {
+        match value {
+            AvroValue::Int(value) => Ok(value as f64),
+            AvroValue::Long(value) => Ok(value as f64),
+            AvroValue::Float(value) => Ok(value as f64),
+            AvroValue::Double(value) => Ok(value),
+            value => Err(value.mismatch("double")),
+        }
+    }
+}
+
+impl<'a> TryFrom<AvroValue<'a>> for String {
+    type Error = Error;
+
+    fn try_from(value: AvroValue<'a>) -> Result<Self, Error> {
+        match value {
+            AvroValue::String(value) => Ok(value),
+            value => Err(value.mismatch("string")),
+        }
+    }
+}
+
+impl<'a> TryFrom<AvroValue<'a>> for Vec<u8> {
+    type Error = Error;
+
+    fn try_from(value: AvroValue<'a>) -> Result<Self, Error> {
+        match value {
+            AvroValue::Bytes(value) | AvroValue::Fixed(value) => Ok(value),
+            value => Err(value.mismatch("bytes")),
+        }
+    }
+}
+
+/// Converts fixed or bytes values of exactly the right length into
+/// arrays, for common sizes like UUIDs (16), SHA-1 hashes (20), and
+/// SHA-256 hashes (32).
+macro_rules! impl_try_from_value_for_byte_array {
+    ($($size:expr),*) => {
+        $(
+            impl<'a, 'v> TryFrom<&'v AvroValue<'a>> for [u8; $size] {
+                type Error = Error;
+
+                fn try_from(value: &'v AvroValue<'a>) -> Result<Self, Error> {
+                    match value {
+                        AvroValue::Bytes(bytes) | AvroValue::Fixed(bytes) => {
+                            <[u8; $size]>::try_from(bytes.as_slice()).map_err(|_| Error::LengthMismatch {
+                                expected: $size,
+                                found: bytes.len(),
+                            })
+                        }
+                        value => Err(value.mismatch("fixed")),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_value_for_byte_array!(16, 20, 32);
+
 #[derive(PartialEq, Debug)]
 enum Error {
     IO(io::ErrorKind),
     InvalidFormat,
     BadEncoding,
     UnsupportedCodec,
+    Resolution(ResolutionError),
+    SchemaMismatch,
+    ValueMismatch {
+        expected: String,
+        found: &'static str,
+    },
+    /// A value is nested deeper or is longer than the read options
+    /// allow. `kind` is the limit that was hit, one of `"depth"`,
+    /// `"string"`, or `"bytes"`, and `len` is the depth or length found.
+    LimitExceeded {
+        kind: &'static str,
+        len: usize,
+    },
+    /// The data ended partway through a record, while reading the named
+    /// field. When records are nested, this is the innermost field.
+    TruncatedRecord {
+        field: String,
+    },
+    /// A fixed or bytes value was converted into a byte array of a
+    /// different length.
+    LengthMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// A value decoded for a type is outside that type's range, e.g. an
+    /// `int` that doesn't fit in 32 bits.
+    IntegerOverflow {
+        declared: &'static str,
+        value: i64,
+    },
+    /// A record doesn't have exactly the fields it was expected to.
+    /// `missing` are expected fields it doesn't have and `unexpected`
+    /// are fields it has that weren't expected, both sorted.
+    FieldsMismatch {
+        missing: Vec<String>,
+        unexpected: Vec<String>,
+    },
+    /// A data block's object count doesn't match its data. `expected`
+    /// is the count from the block's header and `found` is how many
+    /// objects were decoded before the data ran out. If every object
+    /// was decoded but data was left over, `found` equals `expected`
+    /// and `unread_bytes` is the size of what's left, uncompressed.
+    BlockInconsistent {
+        expected: u64,
+        found: u64,
+        unread_bytes: u64,
+    },
+    NoMatchingUnionBranch,
+    ChecksumMismatch,
+    /// A line of JSON input isn't valid JSON or doesn't match the
+    /// schema. Lines are numbered from 1.
+    InvalidJsonLine {
+        line: u64,
+    },
+}
+
+impl Error {
+    /// Reports running out of data while decoding a record's field as a
+    /// `TruncatedRecord` naming the field. Running out during the first
+    /// field stays an `UnexpectedEof`, since the data may have ended
+    /// cleanly before the record.
+    fn in_field(self, field_index: usize, field_name: &str) -> Error {
+        match self {
+            Error::IO(io::ErrorKind::UnexpectedEof) if field_index > 0 => Error::TruncatedRecord {
+                field: field_name.to_string(),
+            },
+            e => e,
+        }
+    }
 }
 
 impl From<io::Error> for Error {
@@ -59,426 +397,5046 @@ impl SchemaRegistry {
     }
 }
 
+const MAGIC: [u8; 4] = [b'O', b'b', b'j', 1];
+
 type SyncMarker = [u8; 16];
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum Codec {
     Null,
     Deflate,
+    Snappy,
+    /// Blocks compressed as gzip members, header and trailer included.
+    /// This isn't a codec in the specification, but some tools write
+    /// `"gzip"` anyway, so it's supported behind the `gzip` feature.
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+impl Codec {
+    /// Looks up the codec named by a file's `avro.codec` metadata.
+    /// Codecs other implementations support but this library doesn't,
+    /// like `"bzip2"`, `"xz"`, or `"zstandard"`, return `None`.
+    fn from_metadata(name: &str) -> Option<Codec> {
+        match name {
+            "null" => Some(Codec::Null),
+            "deflate" => Some(Codec::Deflate),
+            "snappy" => Some(Codec::Snappy),
+            #[cfg(feature = "gzip")]
+            "gzip" => Some(Codec::Gzip),
+            _ => None,
+        }
+    }
+
+    /// Looks up the codec in a file's header metadata, which defaults
+    /// to `Codec::Null` if `avro.codec` isn't set.
+    fn from_header(metadata: &HashMap<String, Vec<u8>>) -> Result<Codec, Error> {
+        match metadata.get("avro.codec") {
+            Some(codec) => std::str::from_utf8(codec)
+                .ok()
+                .and_then(Codec::from_metadata)
+                .ok_or(Error::UnsupportedCodec),
+            None => Ok(Codec::Null),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Codec::Null => "null",
+            Codec::Deflate => "deflate",
+            Codec::Snappy => "snappy",
+            #[cfg(feature = "gzip")]
+            Codec::Gzip => "gzip",
+        }
+    }
+}
+
+/// Options that control how values are decoded from a datafile. Use
+/// the builder methods to combine them, e.g.
+/// `ReadOptions::new().limit(10).lossy_utf8(true)`.
+#[derive(Debug, Default)]
+struct ReadOptions<'a> {
+    /// Schema used to resolve values written with the file's schema.
+    /// Decoded values take the shape of the reader's schema.
+    reader_schema: Option<&'a Schema>,
+
+    /// Reader types for top-level record fields. Values are decoded
+    /// with the writer's type and then promoted to the reader's type,
+    /// which is a lighter-weight alternative to a full reader schema.
+    field_overrides: HashMap<String, SchemaType>,
+
+    /// Stop iterating after this many values have been read.
+    limit: Option<u64>,
+
+    /// Replace invalid UTF-8 sequences in strings with U+FFFD instead
+    /// of returning an error.
+    lossy_utf8: bool,
+
+    /// After a value fails to decode, skip the rest of its data block
+    /// and keep reading from the next one. A failed value usually
+    /// leaves the reader in the middle of the encoded data, so the
+    /// next block is found by scanning for the sync marker.
+    continue_on_error: bool,
+
+    /// After a block fails to decompress or one of its values fails to
+    /// decode, skip the rest of the block using its byte length and
+    /// keep reading from the next one. Unlike `continue_on_error`, this
+    /// doesn't scan for the sync marker, so the next block is only read
+    /// if a sync marker follows the skipped bytes.
+    skip_bad_blocks: bool,
+
+    /// Skip up to this many bytes of junk, such as a byte order mark,
+    /// before the magic bytes instead of requiring the file to start
+    /// with them. See `AvroDatafile::header_offset`.
+    scan_for_magic: Option<usize>,
+
+    /// If the data ends partway through a top-level record, return the
+    /// fields that were read in full as the last value instead of
+    /// failing with an `UnexpectedEof` error. This is meant for
+    /// salvaging files that were truncated while being written, and
+    /// doesn't apply when reading with a reader schema.
+    allow_partial_last_record: bool,
+
+    /// Decode bytes and fixed values as `AvroValue::String`s holding
+    /// their standard base64 encoding instead of as raw bytes. Fixed
+    /// values streamed into a `large_fixed_sink` are unaffected.
+    bytes_as_base64: bool,
+
+    /// When a reader schema's enum doesn't have the writer's symbol and
+    /// has no default to fall back on, return the symbol as
+    /// `AvroValue::EnumUnknown` instead of failing with
+    /// `ResolutionError::UnknownSymbol`.
+    keep_unknown_enum_symbols: bool,
+
+    /// Accept files that break the specification in ways some writers
+    /// are known to, rather than returning an error. Currently this
+    /// allows snappy blocks that are missing their CRC32 checksum, and
+    /// booleans encoded as bytes other than 0 or 1, which are read as
+    /// false.
+    lenient: bool,
+
+    /// Stream fixed values at least this large into a sink instead of
+    /// holding them in memory.
+    large_fixed_sink: Option<LargeFixedSink<'a>>,
+
+    /// How deeply values can be nested inside each other before
+    /// decoding fails with `Error::LimitExceeded`. Defaults to
+    /// `DEFAULT_MAX_DEPTH`.
+    max_depth: Option<usize>,
+
+    /// The longest string value, in bytes, that can be decoded. Longer
+    /// ones fail with `Error::LimitExceeded` before they're allocated.
+    /// Map keys aren't limited.
+    max_string_len: Option<usize>,
+
+    /// The longest bytes value that can be decoded, like
+    /// `max_string_len`. Fixed values have a length set by the schema
+    /// and aren't limited.
+    max_bytes_len: Option<usize>,
+}
+
+const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Destination for fixed values that are too large to keep in memory.
+/// Each value is copied into the writer as it's decoded and appears in
+/// the decoded value as an empty `AvroValue::Fixed`, so the sink sees
+/// the values back to back in the order they're read.
+struct LargeFixedSink<'a> {
+    threshold: usize,
+    writer: RefCell<Box<dyn Write + 'a>>,
+}
+
+impl<'a> fmt::Debug for LargeFixedSink<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LargeFixedSink")
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+impl<'a> ReadOptions<'a> {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn reader_schema(mut self, reader_schema: &'a Schema) -> Self {
+        self.reader_schema = Some(reader_schema);
+        self
+    }
+
+    fn field_override(mut self, field_name: &str, reader_type: SchemaType) -> Self {
+        self.field_overrides.insert(field_name.to_string(), reader_type);
+        self
+    }
+
+    fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn lossy_utf8(mut self, lossy_utf8: bool) -> Self {
+        self.lossy_utf8 = lossy_utf8;
+        self
+    }
+
+    fn continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self
+    }
+
+    fn skip_bad_blocks(mut self, skip_bad_blocks: bool) -> Self {
+        self.skip_bad_blocks = skip_bad_blocks;
+        self
+    }
+
+    fn scan_for_magic(mut self, max_prefix: usize) -> Self {
+        self.scan_for_magic = Some(max_prefix);
+        self
+    }
+
+    fn allow_partial_last_record(mut self, allow_partial_last_record: bool) -> Self {
+        self.allow_partial_last_record = allow_partial_last_record;
+        self
+    }
+
+    fn bytes_as_base64(mut self, bytes_as_base64: bool) -> Self {
+        self.bytes_as_base64 = bytes_as_base64;
+        self
+    }
+
+    fn keep_unknown_enum_symbols(mut self, keep_unknown_enum_symbols: bool) -> Self {
+        self.keep_unknown_enum_symbols = keep_unknown_enum_symbols;
+        self
+    }
+
+    fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    fn max_string_len(mut self, max_string_len: usize) -> Self {
+        self.max_string_len = Some(max_string_len);
+        self
+    }
+
+    fn max_bytes_len(mut self, max_bytes_len: usize) -> Self {
+        self.max_bytes_len = Some(max_bytes_len);
+        self
+    }
+
+    fn large_fixed_sink<W: Write + 'a>(mut self, threshold: usize, writer: W) -> Self {
+        self.large_fixed_sink = Some(LargeFixedSink {
+            threshold,
+            writer: RefCell::new(Box::new(writer)),
+        });
+        self
+    }
+}
+
+/// Counters describing how much of a datafile has been read so far.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Stats {
+    /// Data blocks started, including the one currently being read.
+    blocks: u64,
+
+    /// Data block bytes consumed as they're stored in the file, before
+    /// decompression. Headers and sync markers aren't included.
+    bytes: u64,
+
+    /// Objects read from data blocks, including any that failed to
+    /// decode.
+    objects: u64,
 }
 
 #[derive(Debug)]
-struct AvroDatafile<'a> {
+struct AvroDatafile<'a, R = BufReader<File>> {
     schema: &'a Schema,
+    schema_json: String,
     sync_marker: SyncMarker,
-    position: Option<ReaderPosition<BufReader<File>>>,
+    position: Option<ReaderPosition<R>>,
     codec: Codec,
-}
-
-impl<'a> AvroDatafile<'a> {
-    fn open<P: AsRef<Path>>(path: P, schema_registry: &'a mut SchemaRegistry) -> Result<Self, Error> {
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
+    resolver: Option<Resolver<'a>>,
+    options: ReadOptions<'a>,
+    stats: Stats,
+    recycled: RecycledBlockState,
+    /// The error a partial last record was salvaged from, until the
+    /// block reader checks that the input really did end there.
+    partial_record_error: RefCell<Option<Error>>,
+    header_offset: u64,
 
-        let mut header = [0; 4];
-        reader.read_exact(&mut header)?;
+    /// Whether the input ended right after a block's sync marker, and
+    /// whether any read has failed, see `finished_cleanly`.
+    reached_end: bool,
+    failed: bool,
+}
 
-        if header != [b'O', b'b', b'j', 1] {
-            return Err(Error::InvalidFormat);
-        }
+impl<'a, R: BufRead> AvroDatafile<'a, R> {
+    fn from_reader(reader: R, schema_registry: &'a mut SchemaRegistry) -> Result<Self, Error> {
+        Self::from_reader_with_options(reader, schema_registry, ReadOptions::default())
+    }
 
-        let metadata = encoding::read_metadata(&mut reader)?;
-        let schema_str = metadata.get("avro.schema").ok_or(Error::InvalidFormat)?;
-        let schema = Schema::parse(&schema_str).map_err(|_| Error::InvalidFormat)?;
+    fn from_reader_with_options(
+        mut reader: R,
+        schema_registry: &'a mut SchemaRegistry,
+        options: ReadOptions<'a>,
+    ) -> Result<Self, Error> {
+        let header_offset = skip_to_magic(&mut reader, options.scan_for_magic.unwrap_or(0))?;
+        let metadata = read_metadata_after_magic(&mut reader)?;
+        let schema_bytes = metadata.get("avro.schema").ok_or(Error::InvalidFormat)?;
+        let schema_str = std::str::from_utf8(schema_bytes).map_err(|_| Error::InvalidFormat)?;
+        let schema = Schema::parse(schema_str).map_err(|_| Error::InvalidFormat)?;
         let schema = schema_registry.register(schema);
+        let schema_json = schema_str.to_string();
 
-        let codec = match metadata.get("avro.codec") {
-            Some(codec) => match codec.as_ref() {
-                "deflate" => Codec::Deflate,
-                "null" => Codec::Null,
-                _ => return Err(Error::UnsupportedCodec),
-            },
-            None => Codec::Null,
+        let resolver = match options.reader_schema {
+            Some(reader_schema) => Some(Resolver::new(schema, reader_schema)?),
+            None => None,
         };
 
+        let codec = Codec::from_header(&metadata)?;
+
         let mut sync_marker: SyncMarker = [0; 16];
         reader.read_exact(&mut sync_marker)?;
 
         Ok(Self {
             schema,
+            schema_json,
             sync_marker,
             position: Some(ReaderPosition::StartOfDataBlock { reader }),
             codec,
+            resolver,
+            options,
+            stats: Stats::default(),
+            recycled: RecycledBlockState::default(),
+            partial_record_error: RefCell::new(None),
+            header_offset: header_offset as u64,
+            reached_end: false,
+            failed: false,
         })
     }
 
-    fn read_value<R: Read>(
-        reader: &mut R,
-        schema_type: &'a SchemaType,
-        schema: &'a Schema,
-    ) -> Result<AvroValue<'a>, Error> {
-        match schema_type {
-            SchemaType::Null => Ok(AvroValue::Null),
-            SchemaType::Boolean => Ok(AvroValue::Boolean(encoding::read_bool(reader)?)),
-            SchemaType::Int => Ok(AvroValue::Int(encoding::read_long(reader)? as i32)),
-            SchemaType::Long => Ok(AvroValue::Long(encoding::read_long(reader)?)),
-            SchemaType::Float => Ok(AvroValue::Float(encoding::read_float(reader)?)),
-            SchemaType::Double => Ok(AvroValue::Double(encoding::read_double(reader)?)),
-            SchemaType::Bytes => Ok(AvroValue::Bytes(encoding::read_bytes(reader)?)),
-            SchemaType::String => Ok(AvroValue::String(encoding::read_string(reader)?)),
-            SchemaType::Union(types) => Ok(Self::read_union(reader, types, schema)?),
-            SchemaType::Array(item_type) => Ok(AvroValue::Array(Self::read_array(reader, item_type, schema)?)),
-            SchemaType::Map(value_type) => Ok(AvroValue::Map(Self::read_map(reader, value_type, schema)?)),
-            SchemaType::Reference(id) => {
-                let schema_type = schema.resolve_named_type(*id);
+    /// The schema exactly as it's embedded in the file's header.
+    fn schema_json_raw(&self) -> &str {
+        &self.schema_json
+    }
 
-                match schema_type {
-                    NamedType::Enum(values) => Ok(AvroValue::Enum(Self::read_enum_value(reader, &values)?)),
-                    NamedType::Fixed(size) => Ok(AvroValue::Fixed(encoding::read_fixed(reader, *size)?)),
-                    NamedType::Record(fields) => Ok(AvroValue::Record(Self::read_fields(reader, fields, schema)?)),
-                }
-            }
-        }
+    /// The schema in Parsing Canonical Form, which doesn't depend on how
+    /// the writer formatted it.
+    fn schema_json_canonical(&self) -> String {
+        self.schema.canonical_form()
     }
 
-    fn read_union<R: Read>(
-        reader: &mut R,
-        possible_types: &'a [SchemaType],
-        schema: &'a Schema,
-    ) -> Result<AvroValue<'a>, Error> {
-        let index = encoding::read_long(reader)?;
+    /// Where the magic bytes start in the input. This is 0 unless
+    /// `ReadOptions::scan_for_magic` skipped a prefix, in which case
+    /// it should be added to offsets counted from the start of the
+    /// header to get offsets in the input.
+    fn header_offset(&self) -> u64 {
+        self.header_offset
+    }
 
-        if index >= 0 && (index as usize) < possible_types.len() {
-            Self::read_value(reader, &possible_types[index as usize], schema)
-        } else {
-            Err(Error::InvalidFormat)
+    fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// The number of uncompressed bytes in the current block, which is
+    /// the block the last value was read from. This is `None` before
+    /// the first block is read, and for deflate and gzip blocks since
+    /// they're decompressed as the values are read.
+    fn current_block_uncompressed_len(&self) -> Option<u64> {
+        match &self.position {
+            Some(ReaderPosition::InDataBlock {
+                byte_length, reader, ..
+            }) => match reader {
+                DataBlockReader::NoCodec(_) => Some(*byte_length),
+                DataBlockReader::Snappy { block, .. } => Some(block.get_ref().len() as u64),
+                DataBlockReader::Deflate(_) => None,
+                #[cfg(feature = "gzip")]
+                DataBlockReader::Gzip(_) => None,
+            },
+            _ => None,
         }
     }
 
-    fn read_array<R: Read>(
-        reader: &mut R,
-        item_type: &'a SchemaType,
-        schema: &'a Schema,
-    ) -> Result<Vec<AvroValue<'a>>, Error> {
-        let mut num_values = encoding::read_long(reader)?;
-        let mut values = Vec::with_capacity(num_values as usize);
+    /// Decodes every value in the next data block. If some of the
+    /// current block's values were already read with `next`, only the
+    /// rest of that block is returned.
+    fn next_block(&mut self) -> Option<Result<Vec<AvroValue<'a>>, Error>> {
+        let mut values = Vec::new();
 
-        while num_values != 0 {
-            for _ in 0..num_values {
-                values.push(Self::read_value(reader, item_type, schema)?);
+        loop {
+            match self.next() {
+                Some(Ok(value)) => values.push(value),
+                Some(Err(e)) => return Some(Err(e)),
+                None if values.is_empty() => return None,
+                None => return Some(Ok(values)),
             }
 
-            num_values = encoding::read_long(reader)?;
+            if let Some(ReaderPosition::InDataBlock {
+                remaining_object_count: 0,
+                ..
+            }) = self.position
+            {
+                return Some(Ok(values));
+            }
         }
+    }
 
-        Ok(values)
+    /// Yields each data block's values newest first, with the blocks
+    /// themselves still in file order. Values can only be decoded
+    /// forwards, so each block is decoded in full and then reversed,
+    /// holding a whole block's values in memory at once. To reverse a
+    /// whole file, collect the blocks and reverse those too.
+    fn blocks_reversed(mut self) -> impl Iterator<Item = Result<Vec<AvroValue<'a>>, Error>> + 'a
+    where
+        R: 'a,
+    {
+        std::iter::from_fn(move || {
+            self.next_block().map(|block| {
+                block.map(|mut values| {
+                    values.reverse();
+                    values
+                })
+            })
+        })
     }
 
-    fn read_map<R: Read>(
-        reader: &mut R,
-        value_type: &'a SchemaType,
-        schema: &'a Schema,
-    ) -> Result<HashMap<String, AvroValue<'a>>, Error> {
-        // TODO: handle negative num values
-        let mut num_values = encoding::read_long(reader)?;
-        let mut entries: HashMap<String, AvroValue<'a>> = HashMap::with_capacity(num_values as usize);
+    /// Groups values into batches of `size`, reading across block
+    /// boundaries, so the last batch may be smaller. When a value fails
+    /// to decode, the values read before it are returned as a shorter
+    /// batch and the error is returned on the following call.
+    fn batches(mut self, size: usize) -> impl Iterator<Item = Result<Vec<AvroValue<'a>>, Error>> + 'a
+    where
+        R: 'a,
+    {
+        assert!(size > 0, "batch size must be greater than zero");
+        let mut pending_error = None;
 
-        while num_values > 0 {
-            for _ in 0..num_values {
-                let key = encoding::read_string(reader)?;
-                let value = Self::read_value(reader, value_type, schema)?;
+        std::iter::from_fn(move || {
+            if let Some(e) = pending_error.take() {
+                return Some(Err(e));
+            }
 
-                entries.insert(key, value);
+            let mut batch = Vec::with_capacity(size);
+
+            while batch.len() < size {
+                match self.next() {
+                    Some(Ok(value)) => batch.push(value),
+                    Some(Err(e)) if batch.is_empty() => return Some(Err(e)),
+                    Some(Err(e)) => {
+                        pending_error = Some(e);
+                        break;
+                    }
+                    None => break,
+                }
             }
 
-            num_values = encoding::read_long(reader)?;
-        }
+            if batch.is_empty() {
+                None
+            } else {
+                Some(Ok(batch))
+            }
+        })
+    }
 
-        Ok(entries)
+    /// Returns the encoded bytes of each value without decoding them
+    /// into an `AvroValue`. The bytes are in the file's schema and can
+    /// be copied into another file with `AvroDatafileWriter::append_raw`.
+    fn raw_records(mut self) -> impl Iterator<Item = Result<Vec<u8>, Error>> + 'a
+    where
+        R: 'a,
+    {
+        std::iter::from_fn(move || {
+            self.next_object(|datafile, reader| {
+                let mut recorder = RecordingReader::new(reader);
+                AvroDatafile::read_value(
+                    &mut recorder,
+                    datafile.schema.root(),
+                    datafile.schema,
+                    &datafile.options,
+                )?;
+                Ok(recorder.into_recorded())
+            })
+        })
     }
 
-    fn read_enum_value<R: Read>(reader: &mut R, values: &'a [String]) -> Result<&'a str, Error> {
-        let index = encoding::read_long(reader)?;
+    /// Pairs each value with the number of bytes it took up in its
+    /// decompressed data block, e.g. for building an index of value
+    /// offsets within each block.
+    fn with_lengths(mut self) -> impl Iterator<Item = Result<(AvroValue<'a>, usize), Error>> + 'a
+    where
+        R: 'a,
+    {
+        std::iter::from_fn(move || {
+            self.next_object(|datafile, reader| {
+                let mut counter = CountingReader::new(reader);
+                let value = datafile.read_root_value(&mut counter)?;
+                Ok((value, counter.bytes_read()))
+            })
+        })
+    }
 
-        if index >= 0 && (index as usize) < values.len() {
-            Ok(values[index as usize].as_ref())
-        } else {
-            Err(Error::BadEncoding)
-        }
+    /// Decodes each value into an `OwnedValue`, sharing field names and
+    /// enum symbols across all of the values.
+    fn into_owned_iter(self) -> impl Iterator<Item = Result<OwnedValue, Error>> + 'a
+    where
+        R: 'a,
+    {
+        let mut interner = NameInterner::new();
+        self.map(move |value| value.map(|value| value.into_owned(&mut interner)))
     }
 
-    fn read_fields<R: Read>(
-        reader: &mut R,
-        fields: &'a [Field],
-        schema: &'a Schema,
-    ) -> Result<HashMap<&'a str, AvroValue<'a>>, Error> {
-        let mut field_values = HashMap::with_capacity(fields.len());
+    /// Decodes every record in the file and gathers each field's values
+    /// into its own column, in file order, keyed by field name. Returns
+    /// `Error::SchemaMismatch` if the schema isn't a record, and stops
+    /// at the first value that fails to decode.
+    fn into_columns(self) -> Result<HashMap<String, Vec<AvroValue<'a>>>, Error> {
+        let schema = self.options.reader_schema.unwrap_or(self.schema);
+        let fields = match schema.root() {
+            SchemaType::Reference(id) => match schema.resolve_named_type(*id) {
+                NamedType::Record(fields) => fields,
+                _ => return Err(Error::SchemaMismatch),
+            },
+            _ => return Err(Error::SchemaMismatch),
+        };
+
+        let mut columns: Vec<(&str, Vec<AvroValue<'a>>)> =
+            fields.iter().map(|field| (field.name(), Vec::new())).collect();
+
+        for value in self {
+            let mut field_values = match value? {
+                AvroValue::Record(field_values) => field_values,
+                _ => return Err(Error::SchemaMismatch),
+            };
+
+            for (name, column) in columns.iter_mut() {
+                column.push(field_values.remove(*name).ok_or(Error::SchemaMismatch)?);
+            }
+        }
+
+        Ok(columns
+            .into_iter()
+            .map(|(name, column)| (name.to_string(), column))
+            .collect())
+    }
+
+    /// Decodes each value straight into its JSON encoding, skipping the
+    /// intermediate `AvroValue`. With a reader schema, field overrides,
+    /// or `allow_partial_last_record`, values are decoded as usual and
+    /// then converted, since those only apply to `AvroValue`s.
+    fn into_json_iter(mut self) -> impl Iterator<Item = Result<serde_json::Value, Error>> + 'a
+    where
+        R: 'a,
+    {
+        std::iter::from_fn(move || self.next_object(|datafile, reader| datafile.read_root_json(reader)))
+    }
+
+    /// Reads just the keys of each value when the file's schema is a
+    /// map, skipping over the map's values without decoding them.
+    /// Returns `Error::SchemaMismatch` for any other schema.
+    fn into_map_keys_iter(mut self) -> impl Iterator<Item = Result<Vec<String>, Error>> + 'a
+    where
+        R: 'a,
+    {
+        std::iter::from_fn(move || {
+            self.next_object(|datafile, reader| match datafile.schema.root() {
+                SchemaType::Map(value_type) => read_map_keys(reader, value_type, datafile.schema, &datafile.options),
+                _ => Err(Error::SchemaMismatch),
+            })
+        })
+    }
+
+    fn read_root_value<D: Read>(&self, reader: &mut D) -> Result<AvroValue<'a>, Error> {
+        if let Some(resolver) = self.resolver {
+            return resolver.read_value(reader, &self.options);
+        }
+
+        let schema = self.schema;
+
+        if let SchemaType::Reference(id) = schema.root() {
+            if let NamedType::Record(fields) = schema.resolve_named_type(*id) {
+                if !self.options.field_overrides.is_empty() || self.options.allow_partial_last_record {
+                    return Ok(AvroValue::Record(self.read_fields_with_overrides(reader, fields)?));
+                }
+            }
+        }
+
+        AvroDatafile::read_value(reader, schema.root(), schema, &self.options)
+    }
+
+    fn read_root_json<D: Read>(&self, reader: &mut D) -> Result<serde_json::Value, Error> {
+        if self.resolver.is_none() && self.options.field_overrides.is_empty() && !self.options.allow_partial_last_record
+        {
+            return json::read_json_value(reader, self.schema.root(), self.schema, &self.options, 0);
+        }
+
+        let schema = self.options.reader_schema.unwrap_or(self.schema);
+        let value = self.read_root_value(reader)?;
+
+        match (&value, schema.root()) {
+            (AvroValue::Record(field_values), SchemaType::Reference(id)) => match schema.resolve_named_type(*id) {
+                NamedType::Record(fields) => {
+                    // Overrides are ignored when resolving, just as in
+                    // `read_root_value`.
+                    let overrides = match self.resolver {
+                        Some(_) => None,
+                        None => Some(&self.options.field_overrides),
+                    };
+
+                    json::record_to_json(field_values, fields, overrides, schema)
+                }
+                _ => json::value_to_json(&value, schema.root(), schema),
+            },
+            _ => json::value_to_json(&value, schema.root(), schema),
+        }
+    }
+
+    fn read_fields_with_overrides<D: Read>(
+        &self,
+        reader: &mut D,
+        fields: &'a [Field],
+    ) -> Result<HashMap<&'a str, AvroValue<'a>>, Error> {
+        let mut field_values = HashMap::with_capacity(fields.len());
+
+        for (index, field) in fields.iter().enumerate() {
+            let mut value =
+                match AvroDatafile::read_nested_value(reader, field.schema_type(), self.schema, &self.options, 1) {
+                    Ok(value) => value,
+                    // The data ended partway through the record, so keep
+                    // the fields read so far. Whether the input itself
+                    // ended is only known to the block reader, which
+                    // falls back to the error if it didn't.
+                    Err(e @ Error::IO(io::ErrorKind::UnexpectedEof)) | Err(e @ Error::TruncatedRecord { .. })
+                        if self.options.allow_partial_last_record && !field_values.is_empty() =>
+                    {
+                        *self.partial_record_error.borrow_mut() = Some(e.in_field(index, field.name()));
+                        break;
+                    }
+                    Err(e) => return Err(e.in_field(index, field.name())),
+                };
+
+            if let Some(reader_type) = self.options.field_overrides.get(field.name()) {
+                if reader_type != field.schema_type() {
+                    value = resolution::promote(value, reader_type)?;
+                }
+            }
 
-        for field in fields {
-            let value = Self::read_value(reader, field.schema_type(), schema)?;
             field_values.insert(field.name(), value);
         }
 
         Ok(field_values)
     }
+
+    /// Whether iteration has ended because every block was read and
+    /// the last sync marker matched. This is false while there's still
+    /// more to read, after any error (even if reading carried on past
+    /// it), when `ReadOptions::limit` stopped it early, and after a
+    /// partial last record.
+    fn finished_cleanly(&self) -> bool {
+        self.reached_end && !self.failed
+    }
+
+    fn next_object<T, F>(&mut self, read_object: F) -> Option<Result<T, Error>>
+    where
+        F: Fn(&Self, &mut DataBlockReader<R>) -> Result<T, Error>,
+    {
+        let object = self.read_next_object(read_object);
+        if let Some(Err(_)) = object {
+            self.failed = true;
+        }
+
+        object
+    }
+
+    fn read_next_object<T, F>(&mut self, read_object: F) -> Option<Result<T, Error>>
+    where
+        F: Fn(&Self, &mut DataBlockReader<R>) -> Result<T, Error>,
+    {
+        if let Some(limit) = self.options.limit {
+            if self.stats.objects >= limit {
+                return None;
+            }
+        }
+
+        loop {
+            // We use an Option for position so we can take ownership of
+            // the reader using `take`. This is necessary when we're
+            // starting or finishing a datablock and we need to convert
+            // the reader to the appropriate codec.
+            match self.position.take() {
+                Some(ReaderPosition::StartOfDataBlock { mut reader }) => {
+                    let objects_in_block = match encoding::read_len(&mut reader) {
+                        Ok(object_count) => object_count as u64,
+                        Err(Error::IO(io::ErrorKind::UnexpectedEof)) => {
+                            self.reached_end = true;
+                            return None;
+                        }
+                        Err(e) => return Some(Err(e)),
+                    };
+
+                    let byte_length = match encoding::read_len(&mut reader) {
+                        Ok(byte_length) => byte_length,
+                        Err(e) => return Some(Err(e)),
+                    };
+
+                    self.stats.blocks += 1;
+                    self.stats.bytes += byte_length as u64;
+
+                    let data_block_reader = match self.codec {
+                        Codec::Null => DataBlockReader::NoCodec(reader.take(byte_length as u64)),
+                        Codec::Deflate => DataBlockReader::Deflate(DeflateBlock {
+                            inner: reader.take(byte_length as u64),
+                            state: self.recycled.take_inflater(),
+                        }),
+                        #[cfg(feature = "gzip")]
+                        Codec::Gzip => DataBlockReader::Gzip(GzDecoder::new(reader.take(byte_length as u64))),
+                        Codec::Snappy => match read_snappy_block(
+                            &mut reader,
+                            byte_length,
+                            self.options.lenient,
+                            &mut self.recycled.snappy_block,
+                        ) {
+                            Ok(()) => DataBlockReader::Snappy {
+                                inner: reader,
+                                block: io::Cursor::new(std::mem::take(&mut self.recycled.snappy_block)),
+                            },
+                            Err(e) => {
+                                // The whole block was read before it was
+                                // decompressed, so its sync marker is next.
+                                if self.options.skip_bad_blocks {
+                                    if let Ok(reader) = self.read_sync_marker(reader) {
+                                        self.position = Some(ReaderPosition::StartOfDataBlock { reader });
+                                    }
+                                }
+
+                                return Some(Err(e));
+                            }
+                        },
+                    };
+
+                    self.position = Some(ReaderPosition::InDataBlock {
+                        object_count: objects_in_block,
+                        remaining_object_count: objects_in_block,
+                        byte_length: byte_length as u64,
+                        reader: data_block_reader,
+                    });
+                }
+                Some(ReaderPosition::InDataBlock {
+                    object_count,
+                    remaining_object_count,
+                    byte_length,
+                    mut reader,
+                }) => {
+                    if remaining_object_count > 0 {
+                        let mut value = read_object(self, &mut reader);
+
+                        if let Some(error) = self.partial_record_error.borrow_mut().take() {
+                            // Only the final record of a file that was cut
+                            // short is salvaged, so iteration stops after
+                            // it. A block that merely holds less data than
+                            // its object count is an error as usual.
+                            if reader.input_exhausted() {
+                                self.stats.objects += 1;
+                                return Some(value);
+                            }
+
+                            value = Err(error);
+                        }
+
+                        let value = match value {
+                            // The block's data ran out cleanly, so its
+                            // object count was more than it holds.
+                            Err(Error::IO(io::ErrorKind::UnexpectedEof)) if reader.data_exhausted() => {
+                                Err(Error::BlockInconsistent {
+                                    expected: object_count,
+                                    found: object_count - remaining_object_count,
+                                    unread_bytes: 0,
+                                })
+                            }
+                            value => value,
+                        };
+                        self.stats.objects += 1;
+
+                        if value.is_err() && self.options.skip_bad_blocks {
+                            if let Ok(reader) = reader.skip_rest_of_block().and_then(|r| self.read_sync_marker(r)) {
+                                self.position = Some(ReaderPosition::StartOfDataBlock { reader });
+                            }
+
+                            return Some(value);
+                        }
+
+                        if value.is_err() && self.options.continue_on_error {
+                            let mut reader = reader.inner();
+
+                            if skip_to_sync_marker(&mut reader, &self.sync_marker).is_ok() {
+                                self.position = Some(ReaderPosition::StartOfDataBlock { reader });
+                            }
+
+                            return Some(value);
+                        }
+
+                        self.position = Some(ReaderPosition::InDataBlock {
+                            object_count,
+                            remaining_object_count: remaining_object_count - 1,
+                            byte_length,
+                            reader,
+                        });
+                        return Some(value);
+                    } else {
+                        // Every object was read, so anything left in the
+                        // block means its object count was too small.
+                        // Reading to the end also checks the trailer of
+                        // gzip blocks.
+                        let unread_bytes = match io::copy(&mut reader, &mut io::sink()) {
+                            Ok(unread_bytes) => unread_bytes,
+                            Err(e) => return Some(Err(e.into())),
+                        };
+
+                        if unread_bytes > 0 {
+                            if self.options.skip_bad_blocks || self.options.continue_on_error {
+                                if let Ok(reader) = reader.skip_rest_of_block().and_then(|r| self.read_sync_marker(r)) {
+                                    self.position = Some(ReaderPosition::StartOfDataBlock { reader });
+                                }
+                            }
+
+                            return Some(Err(Error::BlockInconsistent {
+                                expected: object_count,
+                                found: object_count,
+                                unread_bytes,
+                            }));
+                        }
+
+                        let reader = reader.recycle(&mut self.recycled);
+
+                        match self.read_sync_marker(reader) {
+                            Ok(reader) => self.position = Some(ReaderPosition::StartOfDataBlock { reader }),
+                            Err(e) => return Some(Err(e)),
+                        }
+                    }
+                }
+                // TODO throw an error, shouldn't get here
+                None => return None,
+            }
+        }
+    }
+
+    /// Reads the sync marker at the end of a block, returning
+    /// `Error::BadEncoding` if it isn't the file's sync marker.
+    fn read_sync_marker(&self, mut reader: R) -> Result<R, Error> {
+        let mut sync_marker: SyncMarker = [0; 16];
+        reader.read_exact(&mut sync_marker)?;
+
+        if sync_marker != self.sync_marker {
+            return Err(Error::BadEncoding);
+        }
+
+        Ok(reader)
+    }
 }
 
-#[derive(Debug)]
-enum ReaderPosition<R> {
-    StartOfDataBlock {
-        reader: R,
-    },
-    InDataBlock {
-        remaining_object_count: u64,
-        reader: DataBlockReader<R>,
-    },
+impl<'a> AvroDatafile<'a> {
+    fn open<P: AsRef<Path>>(path: P, schema_registry: &'a mut SchemaRegistry) -> Result<Self, Error> {
+        Self::open_with_options(path, schema_registry, ReadOptions::default())
+    }
+
+    fn open_with_options<P: AsRef<Path>>(
+        path: P,
+        schema_registry: &'a mut SchemaRegistry,
+        options: ReadOptions<'a>,
+    ) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        Self::from_reader_with_options(BufReader::new(file), schema_registry, options)
+    }
+
+    /// Opens the datafile like `open`, but returns
+    /// `Error::SchemaMismatch` if the file wasn't written with a schema
+    /// structurally equal to `expected`.
+    fn open_expecting<P: AsRef<Path>>(
+        path: P,
+        schema_registry: &'a mut SchemaRegistry,
+        expected: &Schema,
+    ) -> Result<Self, Error> {
+        let datafile = Self::open(path, schema_registry)?;
+
+        if datafile.schema.structurally_eq(expected) {
+            Ok(datafile)
+        } else {
+            Err(Error::SchemaMismatch)
+        }
+    }
+
+    /// Opens the datafile like `open`, but checks that the schema is a
+    /// record whose field names are exactly `fields`, in any order.
+    /// Returns `Error::FieldsMismatch` listing the differences if not,
+    /// or `Error::SchemaMismatch` if the schema isn't a record.
+    fn open_with_expected_fields<P: AsRef<Path>>(
+        path: P,
+        schema_registry: &'a mut SchemaRegistry,
+        fields: &[&str],
+    ) -> Result<Self, Error> {
+        let datafile = Self::open(path, schema_registry)?;
+
+        let actual_fields: HashSet<&str> = match datafile.schema.root() {
+            SchemaType::Reference(id) => match datafile.schema.resolve_named_type(*id) {
+                NamedType::Record(fields) => fields.iter().map(Field::name).collect(),
+                _ => return Err(Error::SchemaMismatch),
+            },
+            _ => return Err(Error::SchemaMismatch),
+        };
+        let expected_fields: HashSet<&str> = fields.iter().copied().collect();
+
+        let sorted_difference = |a: &HashSet<&str>, b: &HashSet<&str>| {
+            let mut names: Vec<String> = a.difference(b).map(|name| name.to_string()).collect();
+            names.sort();
+            names
+        };
+
+        let missing = sorted_difference(&expected_fields, &actual_fields);
+        let unexpected = sorted_difference(&actual_fields, &expected_fields);
+
+        if missing.is_empty() && unexpected.is_empty() {
+            Ok(datafile)
+        } else {
+            Err(Error::FieldsMismatch { missing, unexpected })
+        }
+    }
+
+    /// Reads the last `n` values in a datafile without decoding the
+    /// whole file. Blocks only link forward, so this seeks to a point
+    /// near the end, scans ahead for the sync marker that ends a block,
+    /// and decodes from there. If that doesn't turn up `n` values it
+    /// starts again twice as far from the end, until it reaches the
+    /// first block.
+    ///
+    /// The scan trusts the first match of the sync marker it finds.
+    /// Nothing stops those 16 bytes from also appearing inside a block's
+    /// data, in which case decoding starts mid-block and fails or, very
+    /// rarely, returns garbage. Files are also assumed not to change
+    /// while they're read.
+    fn tail<P: AsRef<Path>>(
+        path: P,
+        schema_registry: &'a mut SchemaRegistry,
+        n: usize,
+    ) -> Result<Vec<AvroValue<'a>>, Error> {
+        const INITIAL_WINDOW: u64 = 64 * 1024;
+
+        let path = path.as_ref();
+        let mut datafile = Self::open(path, schema_registry)?;
+
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+
+        let data_start = match datafile.position.as_mut() {
+            Some(ReaderPosition::StartOfDataBlock { reader }) => reader.stream_position()?,
+            _ => return Err(Error::InvalidFormat),
+        };
+        let file_length = std::fs::metadata(path)?.len();
+        let mut window = INITIAL_WINDOW;
+
+        loop {
+            let start = file_length.saturating_sub(window).max(data_start);
+            let mut reader = BufReader::new(File::open(path)?);
+            reader.seek(SeekFrom::Start(start))?;
+
+            let found_block = start == data_start
+                || match skip_to_sync_marker(&mut reader, &datafile.sync_marker) {
+                    Ok(()) => true,
+                    Err(Error::IO(io::ErrorKind::UnexpectedEof)) => false,
+                    Err(e) => return Err(e),
+                };
+
+            let mut values = VecDeque::with_capacity(n);
+
+            if found_block {
+                datafile.position = Some(ReaderPosition::StartOfDataBlock { reader });
+
+                for value in &mut datafile {
+                    if values.len() == n {
+                        values.pop_front();
+                    }
+                    values.push_back(value?);
+                }
+            }
+
+            if values.len() == n || start == data_start {
+                return Ok(values.into());
+            }
+
+            window *= 2;
+        }
+    }
+
+    fn read_value<R: Read>(
+        reader: &mut R,
+        schema_type: &'a SchemaType,
+        schema: &'a Schema,
+        options: &ReadOptions,
+    ) -> Result<AvroValue<'a>, Error> {
+        Self::read_nested_value(reader, schema_type, schema, options, 0)
+    }
+
+    /// Reads a value that's nested `depth` levels inside the value
+    /// being decoded. Each level is another recursive call, so data
+    /// nested deeper than the options allow (e.g. a very long chain of
+    /// recursive records) is rejected before it can overflow the stack.
+    fn read_nested_value<R: Read>(
+        reader: &mut R,
+        schema_type: &'a SchemaType,
+        schema: &'a Schema,
+        options: &ReadOptions,
+        depth: usize,
+    ) -> Result<AvroValue<'a>, Error> {
+        if depth > options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH) {
+            return Err(Error::LimitExceeded {
+                kind: "depth",
+                len: depth,
+            });
+        }
+
+        match schema_type {
+            SchemaType::Union(types) => Ok(Self::read_union(reader, types, schema, options, depth)?),
+            SchemaType::Array(item_type) => Ok(AvroValue::Array(Self::read_array(
+                reader, item_type, schema, options, depth,
+            )?)),
+            SchemaType::Map(value_type) => Ok(AvroValue::Map(Self::read_map(
+                reader, value_type, schema, options, depth,
+            )?)),
+            SchemaType::Reference(id) => {
+                let schema_type = schema.resolve_named_type(*id);
+
+                match schema_type {
+                    NamedType::Enum(values) => Ok(AvroValue::Enum(Self::read_enum_value(reader, values)?)),
+                    NamedType::Fixed(size) => Self::read_fixed(reader, *size, options),
+                    NamedType::Record(fields) => Ok(AvroValue::Record(Self::read_fields(
+                        reader, fields, schema, options, depth,
+                    )?)),
+                }
+            }
+            primitive_type => Self::read_primitive(reader, primitive_type, options),
+        }
+    }
+
+    /// Decodes a value of a type that doesn't nest any others. It's kept
+    /// out of `read_nested_value` so that each level of nesting only
+    /// needs a small stack frame.
+    fn read_primitive<R: Read>(
+        reader: &mut R,
+        schema_type: &SchemaType,
+        options: &ReadOptions,
+    ) -> Result<AvroValue<'a>, Error> {
+        match schema_type {
+            SchemaType::Null => Ok(AvroValue::Null),
+            SchemaType::Boolean if options.lenient => Ok(AvroValue::Boolean(encoding::read_bool_lenient(reader)?)),
+            SchemaType::Boolean => Ok(AvroValue::Boolean(encoding::read_bool(reader)?)),
+            SchemaType::Int => {
+                let value = encoding::read_long(reader)?;
+                let value = i32::try_from(value).map_err(|_| Error::IntegerOverflow { declared: "int", value })?;
+                Ok(AvroValue::Int(value))
+            }
+            SchemaType::Long => Ok(AvroValue::Long(encoding::read_long(reader)?)),
+            SchemaType::TimestampMillis => Ok(AvroValue::TimestampMillis(encoding::read_long(reader)?)),
+            SchemaType::TimestampMicros => Ok(AvroValue::TimestampMicros(encoding::read_long(reader)?)),
+            SchemaType::Float => Ok(AvroValue::Float(encoding::read_float(reader)?)),
+            SchemaType::Double => Ok(AvroValue::Double(encoding::read_double(reader)?)),
+            SchemaType::Bytes => {
+                let bytes = encoding::read_bytes_up_to(reader, options.max_bytes_len.unwrap_or(usize::MAX))?;
+
+                if options.bytes_as_base64 {
+                    Ok(AvroValue::String(BASE64.encode(bytes)))
+                } else {
+                    Ok(AvroValue::Bytes(bytes))
+                }
+            }
+            SchemaType::String => {
+                let max_len = options.max_string_len.unwrap_or(usize::MAX);
+
+                if options.lossy_utf8 {
+                    Ok(AvroValue::String(encoding::read_string_lossy_up_to(reader, max_len)?))
+                } else {
+                    Ok(AvroValue::String(encoding::read_string_up_to(reader, max_len)?))
+                }
+            }
+            SchemaType::Union(_) | SchemaType::Array(_) | SchemaType::Map(_) | SchemaType::Reference(_) => {
+                unreachable!("{} isn't a primitive type", schema_type.kind_name())
+            }
+        }
+    }
+
+    fn read_fixed<R: Read>(reader: &mut R, size: usize, options: &ReadOptions) -> Result<AvroValue<'a>, Error> {
+        match &options.large_fixed_sink {
+            Some(sink) if size >= sink.threshold => {
+                encoding::read_fixed_into(reader, size, &mut *sink.writer.borrow_mut())?;
+                Ok(AvroValue::Fixed(Vec::new()))
+            }
+            _ if options.bytes_as_base64 => Ok(AvroValue::String(BASE64.encode(encoding::read_fixed(reader, size)?))),
+            _ => Ok(AvroValue::Fixed(encoding::read_fixed(reader, size)?)),
+        }
+    }
+
+    fn read_union<R: Read>(
+        reader: &mut R,
+        possible_types: &'a [SchemaType],
+        schema: &'a Schema,
+        options: &ReadOptions,
+        depth: usize,
+    ) -> Result<AvroValue<'a>, Error> {
+        // Reading the index as a length rejects negative indexes and
+        // ones too large for a usize before they're compared.
+        let index = encoding::read_len(reader)?;
+        let branch = possible_types.get(index).ok_or(Error::InvalidFormat)?;
+
+        Self::read_nested_value(reader, branch, schema, options, depth + 1)
+    }
+
+    fn read_array<R: Read>(
+        reader: &mut R,
+        item_type: &'a SchemaType,
+        schema: &'a Schema,
+        options: &ReadOptions,
+        depth: usize,
+    ) -> Result<Vec<AvroValue<'a>>, Error> {
+        let mut num_values = encoding::read_block_count(reader)?;
+        let mut values = Vec::with_capacity(num_values);
+
+        while num_values > 0 {
+            for _ in 0..num_values {
+                values.push(Self::read_nested_value(reader, item_type, schema, options, depth + 1)?);
+            }
+
+            num_values = encoding::read_block_count(reader)?;
+        }
+
+        Ok(values)
+    }
+
+    fn read_map<R: Read>(
+        reader: &mut R,
+        value_type: &'a SchemaType,
+        schema: &'a Schema,
+        options: &ReadOptions,
+        depth: usize,
+    ) -> Result<HashMap<String, AvroValue<'a>>, Error> {
+        let mut num_values = encoding::read_block_count(reader)?;
+        let mut entries: HashMap<String, AvroValue<'a>> = HashMap::with_capacity(num_values);
+
+        while num_values > 0 {
+            for _ in 0..num_values {
+                let key = encoding::read_string(reader)?;
+                let value = Self::read_nested_value(reader, value_type, schema, options, depth + 1)?;
+
+                entries.insert(key, value);
+            }
+
+            num_values = encoding::read_block_count(reader)?;
+        }
+
+        Ok(entries)
+    }
+
+    fn read_enum_value<R: Read>(reader: &mut R, symbols: &'a EnumSymbols) -> Result<&'a str, Error> {
+        let index = encoding::read_long(reader)?;
+
+        u32::try_from(index)
+            .ok()
+            .and_then(|index| symbols.symbol(index))
+            .ok_or(Error::BadEncoding)
+    }
+
+    fn read_fields<R: Read>(
+        reader: &mut R,
+        fields: &'a [Field],
+        schema: &'a Schema,
+        options: &ReadOptions,
+        depth: usize,
+    ) -> Result<HashMap<&'a str, AvroValue<'a>>, Error> {
+        let mut field_values = HashMap::with_capacity(fields.len());
+
+        for (index, field) in fields.iter().enumerate() {
+            let value = Self::read_nested_value(reader, field.schema_type(), schema, options, depth + 1)
+                .map_err(|e| e.in_field(index, field.name()))?;
+            field_values.insert(field.name(), value);
+        }
+
+        Ok(field_values)
+    }
+}
+
+/// Parses arbitrary bytes as a schema for the `parse_schema` fuzz
+/// target, which checks that parsing returns an error rather than
+/// panicking. Nothing else is public yet, so this is only built when
+/// fuzzing.
+#[cfg(fuzzing)]
+pub fn fuzz_parse_schema(data: &[u8]) {
+    if let Ok(schema_str) = std::str::from_utf8(data) {
+        let _ = Schema::parse(schema_str);
+    }
+}
+
+/// Decompresses raw deflate blocks one after another the way the
+/// datafile reader does, recycling the decoder state between them, for
+/// the `deflate_blocks` benchmark. Returns the total decompressed
+/// length. Only built with the `bench` feature.
+#[cfg(feature = "bench")]
+pub fn bench_decompress_deflate_blocks(blocks: &[Vec<u8>]) -> io::Result<u64> {
+    let mut recycled = RecycledBlockState::default();
+    let mut len = 0;
+
+    for block in blocks {
+        let mut decoder = DeflateBlock {
+            inner: block.as_slice(),
+            state: recycled.take_inflater(),
+        };
+        len += io::copy(&mut decoder, &mut io::sink())?;
+        recycled.inflater = Some(decoder.state);
+    }
+
+    Ok(len)
 }
 
-#[derive(Debug)]
-enum DataBlockReader<R> {
-    Deflate(DeflateDecoder<io::Take<R>>),
-    NoCodec(io::Take<R>),
-}
+/// Copies the records in a datafile into a new file that only has the
+/// given fields, using the same codec. The other fields are skipped
+/// while reading rather than decoded. Returns the number of records
+/// written, or `Error::SchemaMismatch` if the file's schema isn't a
+/// record with all of the fields.
+fn reproject<P: AsRef<Path>, Q: AsRef<Path>>(input_path: P, output_path: Q, fields: &[&str]) -> Result<u64, Error> {
+    let mut writer_schema_registry = SchemaRegistry::new();
+    let writer_datafile = AvroDatafile::open(&input_path, &mut writer_schema_registry)?;
+    let projected_schema = writer_datafile
+        .schema
+        .project(fields)
+        .map_err(|_| Error::SchemaMismatch)?;
+    let codec = writer_datafile.codec;
+
+    let mut schema_registry = SchemaRegistry::new();
+    let options = ReadOptions::new().reader_schema(&projected_schema);
+    let datafile = AvroDatafile::open_with_options(&input_path, &mut schema_registry, options)?;
+
+    let mut writer = AvroDatafileWriter::create(output_path, &projected_schema, codec)?;
+    let mut record_count = 0;
+
+    for value in datafile {
+        writer.append(&value?)?;
+        record_count += 1;
+    }
+
+    writer.finish()?;
+    Ok(record_count)
+}
+
+/// Concatenates the records in several datafiles into one file, which
+/// is written with the first input's codec. Blocks from inputs with
+/// that codec are copied as they are, and the records of any others
+/// are recompressed, see `copy_datafiles`. Returns the number of
+/// records written, or `Error::SchemaMismatch` if there are no inputs
+/// or their schemas aren't all structurally equal.
+fn concat_files<P: AsRef<Path>, Q: AsRef<Path>>(inputs: &[P], output: Q) -> Result<u64, Error> {
+    let first_input = inputs.first().ok_or(Error::SchemaMismatch)?;
+    let mut reader = BufReader::new(File::open(first_input)?);
+    let codec = Codec::from_header(&read_header_metadata(&mut reader)?)?;
+
+    copy_datafiles(inputs, output, codec)
+}
+
+/// Rewrites a datafile with a different codec, keeping its schema and
+/// values, see `copy_datafiles`. Returns the number of records written.
+fn transcode<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q, new_codec: Codec) -> Result<u64, Error> {
+    copy_datafiles(&[input], output, new_codec)
+}
+
+/// Copies the records in each of `inputs`, in order, into one file
+/// written with `codec`. An input that already uses `codec` has its
+/// blocks copied without decompressing them, and only their sync
+/// markers replaced. Otherwise its records are copied with
+/// `raw_records`, so their encoding is unchanged and only the blocks
+/// are decompressed and recompressed. Returns the number of records
+/// written, or `Error::SchemaMismatch` if there are no inputs or their
+/// schemas aren't all structurally equal. The schemas are checked
+/// before the output file is created.
+fn copy_datafiles<P: AsRef<Path>, Q: AsRef<Path>>(inputs: &[P], output: Q, codec: Codec) -> Result<u64, Error> {
+    let (first_input, other_inputs) = inputs.split_first().ok_or(Error::SchemaMismatch)?;
+
+    let mut schema_registry = SchemaRegistry::new();
+    let schema = AvroDatafile::open(first_input, &mut schema_registry)?.schema;
+
+    for input in other_inputs {
+        let mut input_schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open(input, &mut input_schema_registry)?;
+
+        if !datafile.schema.structurally_eq(schema) {
+            return Err(Error::SchemaMismatch);
+        }
+    }
+
+    let mut writer = AvroDatafileWriter::create(output, schema, codec)?;
+    let mut record_count = 0;
+
+    for input in inputs {
+        let mut input_schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open(input, &mut input_schema_registry)?;
+
+        if datafile.codec == codec {
+            for block in raw_blocks(BufReader::new(File::open(input)?))? {
+                let block = block?;
+                writer.append_raw_block(&block)?;
+                record_count += block.object_count;
+            }
+        } else {
+            for raw_record in datafile.raw_records() {
+                writer.append_raw(&raw_record?)?;
+                record_count += 1;
+            }
+        }
+    }
+
+    writer.finish()?;
+    Ok(record_count)
+}
+
+/// Writes newline-delimited JSON values, in the specification's JSON
+/// encoding, into a datafile. Blank lines are skipped. Returns the
+/// number of values written, or `Error::InvalidJsonLine` for the first
+/// line that can't be parsed or doesn't match the schema.
+fn ndjson_to_avro<R: BufRead, W: Write>(input: R, schema: &Schema, out: W, codec: Codec) -> Result<u64, Error> {
+    let mut writer = AvroDatafileWriter::new(out, schema, codec);
+    let mut value_count = 0;
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let line_number = index as u64 + 1;
+        let json: serde_json::Value =
+            serde_json::from_str(&line).map_err(|_| Error::InvalidJsonLine { line: line_number })?;
+        let value = json::json_to_value(&json, schema.root(), schema)
+            .map_err(|_| Error::InvalidJsonLine { line: line_number })?;
+
+        writer.append(&value)?;
+        value_count += 1;
+    }
+
+    writer.finish()?;
+    Ok(value_count)
+}
+
+/// Reads every value in a datafile into `OwnedValue`s, so the caller
+/// doesn't need a `SchemaRegistry` to hold the schema.
+fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<OwnedValue>, Error> {
+    let mut schema_registry = SchemaRegistry::new();
+    let datafile = AvroDatafile::open(path, &mut schema_registry)?;
+
+    datafile.into_owned_iter().collect()
+}
+
+/// Reads the header and metadata map of an Avro datafile without
+/// parsing the schema or any of the data blocks.
+fn read_file_metadata<P: AsRef<Path>>(path: P) -> Result<HashMap<String, Vec<u8>>, Error> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    read_header_metadata(&mut reader)
+}
+
+/// Counts the data blocks in a datafile by reading each block's
+/// header and seeking past its data, without decompressing or decoding
+/// any values. Each block's sync marker is still checked.
+fn count_blocks<P: AsRef<Path>>(path: P) -> Result<u64, Error> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    let mut block_count = 0;
+    scan_blocks(&mut reader, seek_past_block, |_, _| block_count += 1)?;
+
+    Ok(block_count)
+}
+
+/// Counts the records in a datafile by adding up the object count in
+/// each block's header. Block data is skipped by seeking past it, so
+/// nothing is decompressed or decoded. Each block's sync marker is
+/// still checked.
+fn count_records<P: AsRef<Path>>(path: P) -> Result<u64, Error> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    count_records_with(&mut reader, seek_past_block)
+}
+
+/// Skips a block's data for `scan_blocks` by seeking past it.
+fn seek_past_block(reader: &mut BufReader<File>, byte_length: usize) -> Result<(), Error> {
+    let offset = i64::try_from(byte_length).map_err(|_| Error::BadEncoding)?;
+    reader.seek_relative(offset)?;
+    Ok(())
+}
+
+/// Counts records like `count_records`, for readers that can't seek.
+/// Block data is skipped by copying it into `io::sink`.
+fn count_records_from_reader<R: Read>(mut reader: R) -> Result<u64, Error> {
+    count_records_with(&mut reader, |reader, byte_length| {
+        let skipped = io::copy(&mut reader.take(byte_length as u64), &mut io::sink())?;
+        if skipped < byte_length as u64 {
+            return Err(Error::IO(io::ErrorKind::UnexpectedEof));
+        }
+
+        Ok(())
+    })
+}
+
+fn count_records_with<R: Read, F>(reader: &mut R, skip: F) -> Result<u64, Error>
+where
+    F: FnMut(&mut R, usize) -> Result<(), Error>,
+{
+    let mut record_count = 0;
+    scan_blocks(reader, skip, |object_count, _| record_count += object_count as u64)?;
+
+    Ok(record_count)
+}
+
+/// Walks a datafile's block headers, calling `visit` with each block's
+/// object count and byte length and `skip` to move past its data, then
+/// returns the header metadata. Each block's sync marker is checked.
+fn scan_blocks<R: Read, F, V>(reader: &mut R, mut skip: F, mut visit: V) -> Result<HashMap<String, Vec<u8>>, Error>
+where
+    F: FnMut(&mut R, usize) -> Result<(), Error>,
+    V: FnMut(usize, usize),
+{
+    let metadata = read_header_metadata(reader)?;
+
+    let mut sync_marker: SyncMarker = [0; 16];
+    reader.read_exact(&mut sync_marker)?;
+
+    loop {
+        let object_count = match encoding::read_len(reader) {
+            Ok(object_count) => object_count,
+            Err(Error::IO(io::ErrorKind::UnexpectedEof)) => return Ok(metadata),
+            Err(e) => return Err(e),
+        };
+
+        let byte_length = encoding::read_len(reader)?;
+        skip(reader, byte_length)?;
+
+        let mut block_sync_marker: SyncMarker = [0; 16];
+        reader.read_exact(&mut block_sync_marker)?;
+
+        if block_sync_marker != sync_marker {
+            return Err(Error::BadEncoding);
+        }
+
+        visit(object_count, byte_length);
+    }
+}
+
+/// An overview of a datafile's contents and layout, see
+/// `summarize_file`.
+#[derive(Debug, Clone, PartialEq)]
+struct FileSummary {
+    root_kind: RootKind,
+    codec: Codec,
+    blocks: u64,
+    records: u64,
+
+    /// Sizes of the data blocks as they're stored in the file, before
+    /// decompression. The minimum and maximum are zero if the file has
+    /// no blocks.
+    min_block_bytes: u64,
+    max_block_bytes: u64,
+    total_block_bytes: u64,
+
+    /// Every `logicalType` used in the schema, sorted.
+    logical_types: Vec<String>,
+}
+
+impl FileSummary {
+    fn avg_block_bytes(&self) -> u64 {
+        self.total_block_bytes.checked_div(self.blocks).unwrap_or(0)
+    }
+}
+
+impl fmt::Display for FileSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let logical_types = if self.logical_types.is_empty() {
+            "none".to_string()
+        } else {
+            self.logical_types.join(", ")
+        };
+
+        writeln!(f, "root: {}", self.root_kind.name())?;
+        writeln!(f, "codec: {}", self.codec.name())?;
+        writeln!(f, "blocks: {}", self.blocks)?;
+        writeln!(f, "records: {}", self.records)?;
+        writeln!(
+            f,
+            "block bytes: min {}, avg {}, max {}",
+            self.min_block_bytes,
+            self.avg_block_bytes(),
+            self.max_block_bytes
+        )?;
+        writeln!(f, "logical types: {}", logical_types)
+    }
+}
+
+/// Summarizes a datafile from its header and block headers. Block data
+/// is skipped by seeking past it, so nothing is decompressed or
+/// decoded.
+fn summarize_file<P: AsRef<Path>>(path: P) -> Result<FileSummary, Error> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    let mut blocks = 0;
+    let mut records = 0;
+    let mut min_block_bytes = u64::MAX;
+    let mut max_block_bytes = 0;
+    let mut total_block_bytes = 0;
+
+    let metadata = scan_blocks(&mut reader, seek_past_block, |object_count, byte_length| {
+        blocks += 1;
+        records += object_count as u64;
+        min_block_bytes = min_block_bytes.min(byte_length as u64);
+        max_block_bytes = max_block_bytes.max(byte_length as u64);
+        total_block_bytes += byte_length as u64;
+    })?;
+
+    let schema_bytes = metadata.get("avro.schema").ok_or(Error::InvalidFormat)?;
+    let schema_str = std::str::from_utf8(schema_bytes).map_err(|_| Error::InvalidFormat)?;
+    let schema = Schema::parse(schema_str).map_err(|_| Error::InvalidFormat)?;
+
+    let mut logical_types: Vec<String> = schema.logical_types().into_iter().map(str::to_string).collect();
+    logical_types.sort();
+
+    Ok(FileSummary {
+        root_kind: schema.root_kind(),
+        codec: Codec::from_header(&metadata)?,
+        blocks,
+        records,
+        min_block_bytes: if blocks == 0 { 0 } else { min_block_bytes },
+        max_block_bytes,
+        total_block_bytes,
+        logical_types,
+    })
+}
+
+/// The size of one data block before and after decompression, see
+/// `block_stats`. The lengths are equal for the null codec.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BlockStats {
+    /// The block's length as stored in the file. For snappy this
+    /// includes the 4 byte checksum.
+    compressed_len: u64,
+    uncompressed_len: u64,
+    object_count: u64,
+}
+
+/// Reads a datafile's header and then yields the stats of each data
+/// block in file order, for comparing how well a codec compresses the
+/// data. Blocks are decompressed to measure them, but their values
+/// aren't decoded. Iteration stops after the first error.
+fn block_stats<R: Read>(mut reader: R) -> Result<impl Iterator<Item = Result<BlockStats, Error>>, Error> {
+    let metadata = read_header_metadata(&mut reader)?;
+    let codec = Codec::from_header(&metadata)?;
+
+    let mut sync_marker: SyncMarker = [0; 16];
+    reader.read_exact(&mut sync_marker)?;
+
+    let mut block = Vec::new();
+    let mut failed = false;
+
+    Ok(std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+
+        let stats = read_block_stats(&mut reader, codec, &sync_marker, &mut block).transpose();
+        failed = matches!(stats, Some(Err(_)));
+        stats
+    }))
+}
+
+/// Reads the next block for `block_stats`, or returns `None` at the
+/// end of the file. `block` is scratch space for snappy blocks.
+fn read_block_stats<R: Read>(
+    reader: &mut R,
+    codec: Codec,
+    sync_marker: &SyncMarker,
+    block: &mut Vec<u8>,
+) -> Result<Option<BlockStats>, Error> {
+    let RawBlock { object_count, data } = match read_raw_block(reader, sync_marker)? {
+        Some(raw_block) => raw_block,
+        None => return Ok(None),
+    };
+
+    let uncompressed_len = match codec {
+        Codec::Null => data.len() as u64,
+        Codec::Deflate => {
+            let mut decoder = DeflateBlock {
+                inner: data.as_slice(),
+                state: Decompress::new(false),
+            };
+            io::copy(&mut decoder, &mut io::sink())?
+        }
+        Codec::Snappy => {
+            read_snappy_block(&mut data.as_slice(), data.len(), false, block)?;
+            block.len() as u64
+        }
+        #[cfg(feature = "gzip")]
+        Codec::Gzip => io::copy(&mut GzDecoder::new(data.as_slice()), &mut io::sink())?,
+    };
+
+    Ok(Some(BlockStats {
+        compressed_len: data.len() as u64,
+        uncompressed_len,
+        object_count,
+    }))
+}
+
+/// A data block as it's stored in the file, with its data still
+/// compressed with the file's codec.
+#[derive(Debug, Clone, PartialEq)]
+struct RawBlock {
+    object_count: u64,
+    data: Vec<u8>,
+}
+
+/// Reads a datafile's header and then yields each data block without
+/// decompressing it, e.g. for copying blocks into another file with
+/// the same codec. Each block's sync marker is checked. Iteration stops
+/// after the first error.
+fn raw_blocks<R: Read>(mut reader: R) -> Result<impl Iterator<Item = Result<RawBlock, Error>>, Error> {
+    read_header_metadata(&mut reader)?;
+
+    let mut sync_marker: SyncMarker = [0; 16];
+    reader.read_exact(&mut sync_marker)?;
+
+    let mut failed = false;
+
+    Ok(std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+
+        let block = read_raw_block(&mut reader, &sync_marker).transpose();
+        failed = matches!(block, Some(Err(_)));
+        block
+    }))
+}
+
+/// Reads the next block and its sync marker, or returns `None` at the
+/// end of the file.
+fn read_raw_block<R: Read>(reader: &mut R, sync_marker: &SyncMarker) -> Result<Option<RawBlock>, Error> {
+    let object_count = match encoding::read_len(reader) {
+        Ok(object_count) => object_count as u64,
+        Err(Error::IO(io::ErrorKind::UnexpectedEof)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let byte_length = encoding::read_len(reader)?;
+    let data = encoding::read_fixed(reader, byte_length)?;
+
+    let mut block_sync_marker: SyncMarker = [0; 16];
+    reader.read_exact(&mut block_sync_marker)?;
+
+    if block_sync_marker != *sync_marker {
+        return Err(Error::BadEncoding);
+    }
+
+    Ok(Some(RawBlock { object_count, data }))
+}
+
+fn read_header_metadata<R: Read>(reader: &mut R) -> Result<HashMap<String, Vec<u8>>, Error> {
+    skip_to_magic(reader, 0)?;
+    read_metadata_after_magic(reader)
+}
+
+/// Reads up to and including the magic bytes, allowing up to
+/// `max_prefix` bytes of anything else before them. Returns the number
+/// of bytes skipped, or `Error::InvalidFormat` if the magic wasn't
+/// found in time.
+fn skip_to_magic<R: Read>(reader: &mut R, max_prefix: usize) -> Result<usize, Error> {
+    let mut window = [0; 4];
+    reader.read_exact(&mut window)?;
+
+    let mut skipped = 0;
+
+    while window != MAGIC {
+        if skipped == max_prefix {
+            return Err(Error::InvalidFormat);
+        }
+
+        window.rotate_left(1);
+        reader.read_exact(&mut window[3..])?;
+        skipped += 1;
+    }
+
+    Ok(skipped)
+}
+
+fn read_metadata_after_magic<R: Read>(reader: &mut R) -> Result<HashMap<String, Vec<u8>>, Error> {
+    encoding::read_metadata(reader)
+}
+
+/// Reads and decompresses a snappy block into `block`, replacing its
+/// contents but reusing its allocation.
+fn read_snappy_block<R: Read>(
+    reader: &mut R,
+    byte_length: usize,
+    lenient: bool,
+    block: &mut Vec<u8>,
+) -> Result<(), Error> {
+    const CHECKSUM_LENGTH: usize = 4;
+
+    let data = encoding::read_fixed(reader, byte_length)?;
+
+    let checked_block = if data.len() < CHECKSUM_LENGTH {
+        Err(Error::BadEncoding)
+    } else {
+        let (compressed, checksum) = data.split_at(data.len() - CHECKSUM_LENGTH);
+
+        decompress_snappy(compressed, block).and_then(|()| {
+            if crc32fast::hash(block).to_be_bytes() == checksum {
+                Ok(())
+            } else {
+                Err(Error::ChecksumMismatch)
+            }
+        })
+    };
+
+    match checked_block {
+        // Some writers leave out the checksum, so the whole block is
+        // compressed data. This isn't allowed by the specification and
+        // is only accepted in lenient mode.
+        Err(e) if lenient => decompress_snappy(&data, block).map_err(|_| e),
+        checked_block => checked_block,
+    }
+}
+
+fn decompress_snappy(compressed: &[u8], block: &mut Vec<u8>) -> Result<(), Error> {
+    let length = snap::raw::decompress_len(compressed).map_err(|_| Error::BadEncoding)?;
+    block.clear();
+    block.resize(length, 0);
+
+    snap::raw::Decoder::new()
+        .decompress(compressed, block)
+        .map_err(|_| Error::BadEncoding)?;
+    Ok(())
+}
+
+/// Reads until just past the next occurrence of the sync marker.
+fn skip_to_sync_marker<R: Read>(reader: &mut R, sync_marker: &SyncMarker) -> Result<(), Error> {
+    let mut window: Vec<u8> = Vec::with_capacity(sync_marker.len());
+    let mut byte = [0; 1];
+
+    loop {
+        reader.read_exact(&mut byte)?;
+
+        if window.len() == sync_marker.len() {
+            window.remove(0);
+        }
+        window.push(byte[0]);
+
+        if window == sync_marker {
+            return Ok(());
+        }
+    }
+}
+
+/// Passes reads through to the wrapped reader while keeping a copy of
+/// every byte that was read.
+struct RecordingReader<'r, R> {
+    inner: &'r mut R,
+    recorded: Vec<u8>,
+}
+
+impl<'r, R: Read> RecordingReader<'r, R> {
+    fn new(inner: &'r mut R) -> Self {
+        Self {
+            inner,
+            recorded: Vec::new(),
+        }
+    }
+
+    fn into_recorded(self) -> Vec<u8> {
+        self.recorded
+    }
+}
+
+impl<'r, R: Read> Read for RecordingReader<'r, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.recorded.extend_from_slice(&buf[..bytes_read]);
+        Ok(bytes_read)
+    }
+}
+
+/// Counts the bytes read through it, without keeping them.
+struct CountingReader<'r, R> {
+    inner: &'r mut R,
+    bytes_read: usize,
+}
+
+impl<'r, R: Read> CountingReader<'r, R> {
+    fn new(inner: &'r mut R) -> Self {
+        Self { inner, bytes_read: 0 }
+    }
+
+    fn bytes_read(&self) -> usize {
+        self.bytes_read
+    }
+}
+
+impl<'r, R: Read> Read for CountingReader<'r, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.bytes_read += bytes_read;
+        Ok(bytes_read)
+    }
+}
+
+#[derive(Debug)]
+enum ReaderPosition<R> {
+    StartOfDataBlock {
+        reader: R,
+    },
+    InDataBlock {
+        object_count: u64,
+        remaining_object_count: u64,
+        byte_length: u64,
+        reader: DataBlockReader<R>,
+    },
+}
+
+#[derive(Debug)]
+enum DataBlockReader<R> {
+    Deflate(DeflateBlock<io::Take<R>>),
+    // Snappy blocks end with a checksum of the uncompressed data, so the
+    // whole block is decompressed up front instead of streamed.
+    Snappy {
+        inner: R,
+        block: io::Cursor<Vec<u8>>,
+    },
+    #[cfg(feature = "gzip")]
+    Gzip(GzDecoder<io::Take<R>>),
+    NoCodec(io::Take<R>),
+}
+
+impl<R> DataBlockReader<R> {
+    fn inner(self) -> R {
+        match self {
+            Self::Deflate(decoder) => decoder.inner.into_inner(),
+            Self::Snappy { inner, .. } => inner,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(decoder) => decoder.into_inner().into_inner(),
+            Self::NoCodec(reader) => reader.into_inner(),
+        }
+    }
+
+    /// Returns the inner reader like `inner`, keeping the block's
+    /// decompression state and buffer so the next block can reuse them.
+    fn recycle(self, recycled: &mut RecycledBlockState) -> R {
+        match self {
+            Self::Deflate(decoder) => {
+                recycled.inflater = Some(decoder.state);
+                decoder.inner.into_inner()
+            }
+            Self::Snappy { inner, block } => {
+                recycled.snappy_block = block.into_inner();
+                inner
+            }
+            #[cfg(feature = "gzip")]
+            Self::Gzip(decoder) => decoder.into_inner().into_inner(),
+            Self::NoCodec(reader) => reader.into_inner(),
+        }
+    }
+
+    /// Whether the input the block is read from has ended, rather than
+    /// only the block's own data.
+    fn input_exhausted(&mut self) -> bool
+    where
+        R: BufRead,
+    {
+        let inner = match self {
+            Self::Deflate(decoder) => decoder.inner.get_mut(),
+            Self::Snappy { inner, .. } => inner,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(decoder) => decoder.get_mut().get_mut(),
+            Self::NoCodec(reader) => reader.get_mut(),
+        };
+
+        matches!(inner.fill_buf(), Ok(buffer) if buffer.is_empty())
+    }
+
+    /// Whether all of the block's data has been read. For compressed
+    /// blocks this means all of the compressed data was consumed.
+    fn data_exhausted(&self) -> bool {
+        match self {
+            Self::Deflate(decoder) => decoder.inner.limit() == 0,
+            Self::Snappy { block, .. } => block.position() >= block.get_ref().len() as u64,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(decoder) => decoder.get_ref().limit() == 0,
+            Self::NoCodec(reader) => reader.limit() == 0,
+        }
+    }
+
+    /// Skips the block's data that hasn't been read yet, leaving the
+    /// inner reader just before the block's sync marker.
+    fn skip_rest_of_block(self) -> Result<R, Error>
+    where
+        R: Read,
+    {
+        let mut remaining = match self {
+            Self::Deflate(decoder) => decoder.inner,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(decoder) => decoder.into_inner(),
+            Self::NoCodec(reader) => reader,
+            // Snappy blocks are read in full before they're decoded.
+            Self::Snappy { inner, .. } => return Ok(inner),
+        };
+
+        io::copy(&mut remaining, &mut io::sink())?;
+        if remaining.limit() > 0 {
+            return Err(Error::IO(io::ErrorKind::UnexpectedEof));
+        }
+
+        Ok(remaining.into_inner())
+    }
+}
+
+/// Decompression state left over from earlier data blocks. Reading a
+/// large file otherwise allocates a new deflate window or snappy buffer
+/// for every block.
+#[derive(Debug, Default)]
+struct RecycledBlockState {
+    inflater: Option<Decompress>,
+    snappy_block: Vec<u8>,
+}
+
+impl RecycledBlockState {
+    fn take_inflater(&mut self) -> Decompress {
+        match self.inflater.take() {
+            Some(mut inflater) => {
+                inflater.reset(false);
+                inflater
+            }
+            None => Decompress::new(false),
+        }
+    }
+}
+
+/// Streams a raw deflate block like `flate2::bufread::DeflateDecoder`,
+/// but owns its `Decompress` state so it can be handed on to the next
+/// block.
+#[derive(Debug)]
+struct DeflateBlock<R> {
+    inner: R,
+    state: Decompress,
+}
+
+impl<R: BufRead> Read for DeflateBlock<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let input = self.inner.fill_buf()?;
+            let eof = input.is_empty();
+            let total_in = self.state.total_in();
+            let total_out = self.state.total_out();
+            let flush = if eof {
+                FlushDecompress::Finish
+            } else {
+                FlushDecompress::None
+            };
+
+            let status = self.state.decompress(input, buf, flush);
+            let read = (self.state.total_out() - total_out) as usize;
+            let consumed = (self.state.total_in() - total_in) as usize;
+            self.inner.consume(consumed);
+
+            match status {
+                // Returning zero bytes would look like the end of the
+                // block, so keep going until there's output or input
+                // runs out.
+                Ok(Status::Ok) | Ok(Status::BufError) if read == 0 && !buf.is_empty() => {
+                    if eof {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "incomplete deflate stream",
+                        ));
+                    }
+                }
+                Ok(_) => return Ok(read),
+                Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "corrupt deflate stream")),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for DataBlockReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Deflate(decoder) => decoder.read(buf),
+            Self::Snappy { block, .. } => block.read(buf),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(decoder) => decoder.read(buf),
+            Self::NoCodec(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl<'a, R: BufRead> Iterator for AvroDatafile<'a, R> {
+    type Item = Result<AvroValue<'a>, Error>;
+
+    fn next(&mut self) -> Option<Result<AvroValue<'a>, Error>> {
+        self.next_object(|datafile, reader| datafile.read_root_value(reader))
+    }
+}
+
+const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Writes values to an Avro datafile. Encoded values are buffered into
+/// a data block, which is compressed and written once it grows past
+/// the block size or when the writer is flushed. The header is written
+/// along with the first block.
+#[derive(Debug)]
+struct AvroDatafileWriter<'a, W: Write> {
+    writer: W,
+    schema: &'a Schema,
+    codec: Codec,
+    sync_marker: SyncMarker,
+    header_written: bool,
+    block: Vec<u8>,
+    block_object_count: u64,
+    block_size: usize,
+}
+
+impl<'a> AvroDatafileWriter<'a, BufWriter<File>> {
+    /// Creates the file at `path`, replacing it if it already exists.
+    /// As with `new`, the header is written along with the first block.
+    fn create<P: AsRef<Path>>(path: P, schema: &'a Schema, codec: Codec) -> Result<Self, Error> {
+        let file = File::create(path)?;
+        Ok(Self::new(BufWriter::new(file), schema, codec))
+    }
+}
+
+impl<'a, W: Write> AvroDatafileWriter<'a, W> {
+    fn new(writer: W, schema: &'a Schema, codec: Codec) -> Self {
+        Self {
+            writer,
+            schema,
+            codec,
+            sync_marker: random_sync_marker(),
+            header_written: false,
+            block: Vec::new(),
+            block_object_count: 0,
+            block_size: DEFAULT_BLOCK_SIZE,
+        }
+    }
+
+    /// Uses the given sync marker instead of a random one, so writing
+    /// the same values always produces the same bytes.
+    fn with_sync_marker(mut self, sync_marker: SyncMarker) -> Self {
+        self.sync_marker = sync_marker;
+        self
+    }
+
+    /// Writes a block once its encoded values reach `block_size` bytes,
+    /// before compression, instead of the default 64 KiB.
+    fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    fn append(&mut self, value: &AvroValue) -> Result<(), Error> {
+        let encoded = encode(value, self.schema)?;
+        self.append_raw(&encoded)
+    }
+
+    /// Appends a value that has already been encoded with the writer's
+    /// schema. The bytes aren't validated, so it's up to the caller to
+    /// make sure they match the schema.
+    fn append_raw(&mut self, encoded: &[u8]) -> Result<(), Error> {
+        self.block.extend_from_slice(encoded);
+        self.block_object_count += 1;
+
+        if self.block.len() >= self.block_size {
+            self.write_block()?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends a block whose data is already compressed with the
+    /// writer's codec, such as one from `raw_blocks`, after writing out
+    /// any values appended before it. As with `append_raw`, the data
+    /// isn't validated.
+    fn append_raw_block(&mut self, block: &RawBlock) -> Result<(), Error> {
+        self.write_block()?;
+        self.write_compressed_block(block.object_count, &block.data)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.write_block()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<W, Error> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self) -> Result<(), Error> {
+        if self.header_written {
+            return Ok(());
+        }
+
+        write_header(
+            &mut self.writer,
+            self.schema,
+            self.codec,
+            self.sync_marker,
+            &HashMap::new(),
+        )?;
+
+        self.header_written = true;
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> Result<(), Error> {
+        self.write_header()?;
+
+        if self.block_object_count == 0 {
+            return Ok(());
+        }
+
+        let compressed_block = compress_block(self.codec, &self.block)?;
+        self.write_compressed_block(self.block_object_count, &compressed_block)?;
+
+        self.block.clear();
+        self.block_object_count = 0;
+        Ok(())
+    }
+
+    fn write_compressed_block(&mut self, object_count: u64, compressed_block: &[u8]) -> Result<(), Error> {
+        encoding::write_long(&mut self.writer, object_count as i64)?;
+        encoding::write_long(&mut self.writer, compressed_block.len() as i64)?;
+        self.writer.write_all(compressed_block)?;
+        self.writer.write_all(&self.sync_marker)?;
+        Ok(())
+    }
+}
+
+/// Converts a field's JSON default into a value of the field's type,
+/// following the specification's rules for defaults. A union's default
+/// is a value of its first branch, and bytes and fixed defaults are
+/// strings whose code points are the byte values. Returns
+/// `Error::SchemaMismatch` if the default doesn't fit the type.
+fn default_value<'a>(
+    default: &serde_json::Value,
+    schema_type: &'a SchemaType,
+    schema: &'a Schema,
+) -> Result<AvroValue<'a>, Error> {
+    nested_default_value(default, schema_type, schema, 0)
+}
+
+fn nested_default_value<'a>(
+    default: &serde_json::Value,
+    schema_type: &'a SchemaType,
+    schema: &'a Schema,
+    depth: usize,
+) -> Result<AvroValue<'a>, Error> {
+    use serde_json::Value;
+
+    // A record's default can leave out fields that have defaults of
+    // their own, which could otherwise refer back to the record forever.
+    if depth > DEFAULT_MAX_DEPTH {
+        return Err(Error::LimitExceeded {
+            kind: "depth",
+            len: depth,
+        });
+    }
+
+    let value = match (schema_type, default) {
+        (SchemaType::Null, Value::Null) => Some(AvroValue::Null),
+        (SchemaType::Boolean, Value::Bool(value)) => Some(AvroValue::Boolean(*value)),
+        (SchemaType::Int, Value::Number(value)) => value
+            .as_i64()
+            .and_then(|value| i32::try_from(value).ok())
+            .map(AvroValue::Int),
+        (SchemaType::Long, Value::Number(value)) => value.as_i64().map(AvroValue::Long),
+        (SchemaType::TimestampMillis, Value::Number(value)) => value.as_i64().map(AvroValue::TimestampMillis),
+        (SchemaType::TimestampMicros, Value::Number(value)) => value.as_i64().map(AvroValue::TimestampMicros),
+        (SchemaType::Float, Value::Number(value)) => value.as_f64().map(|value| AvroValue::Float(value as f32)),
+        (SchemaType::Double, Value::Number(value)) => value.as_f64().map(AvroValue::Double),
+        (SchemaType::Bytes, Value::String(value)) => schema::default_bytes(value).map(AvroValue::Bytes),
+        (SchemaType::String, Value::String(value)) => Some(AvroValue::String(value.clone())),
+        (SchemaType::Array(item_type), Value::Array(items)) => {
+            return items
+                .iter()
+                .map(|item| nested_default_value(item, item_type, schema, depth + 1))
+                .collect::<Result<_, Error>>()
+                .map(AvroValue::Array);
+        }
+        (SchemaType::Map(value_type), Value::Object(entries)) => {
+            return entries
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), nested_default_value(value, value_type, schema, depth + 1)?)))
+                .collect::<Result<_, Error>>()
+                .map(AvroValue::Map);
+        }
+        (SchemaType::Union(branches), default) => match branches.first() {
+            Some(first_branch) => return nested_default_value(default, first_branch, schema, depth + 1),
+            None => None,
+        },
+        (SchemaType::Reference(id), default) => match (schema.resolve_named_type(*id), default) {
+            (NamedType::Fixed(size), Value::String(value)) => schema::default_bytes(value)
+                .filter(|bytes| bytes.len() == *size)
+                .map(AvroValue::Fixed),
+            (NamedType::Enum(symbols), Value::String(value)) => symbols
+                .symbol_index(value)
+                .and_then(|index| symbols.symbol(index))
+                .map(AvroValue::Enum),
+            (NamedType::Record(fields), Value::Object(field_defaults)) => {
+                return fields
+                    .iter()
+                    .map(
+                        |field| match field_defaults.get(field.name()).or_else(|| field.default()) {
+                            Some(field_default) => Ok((
+                                field.name(),
+                                nested_default_value(field_default, field.schema_type(), schema, depth + 1)?,
+                            )),
+                            None => Err(Error::SchemaMismatch),
+                        },
+                    )
+                    .collect::<Result<_, Error>>()
+                    .map(AvroValue::Record);
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+
+    value.ok_or(Error::SchemaMismatch)
+}
+
+/// Reads a map's keys in the order they're encoded, skipping over each
+/// value instead of decoding it.
+fn read_map_keys<R: Read>(
+    reader: &mut R,
+    value_type: &SchemaType,
+    schema: &Schema,
+    options: &ReadOptions,
+) -> Result<Vec<String>, Error> {
+    let mut num_values = encoding::read_block_count(reader)?;
+    // The count comes from the file, so don't trust it for more than a
+    // modest preallocation.
+    let mut keys = Vec::with_capacity(num_values.min(1024));
+
+    while num_values > 0 {
+        for _ in 0..num_values {
+            keys.push(encoding::read_string(reader)?);
+            skip_value(reader, value_type, schema, options, 1)?;
+        }
+
+        num_values = encoding::read_block_count(reader)?;
+    }
+
+    Ok(keys)
+}
+
+/// Reads past a value without decoding it. Array and map blocks that
+/// were written with their size in bytes are skipped in one go.
+fn skip_value<R: Read>(
+    reader: &mut R,
+    schema_type: &SchemaType,
+    schema: &Schema,
+    options: &ReadOptions,
+    depth: usize,
+) -> Result<(), Error> {
+    if depth > options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH) {
+        return Err(Error::LimitExceeded {
+            kind: "depth",
+            len: depth,
+        });
+    }
+
+    let skip_bytes = |reader: &mut R, length| encoding::read_fixed_into(reader, length, &mut io::sink());
+
+    match schema_type {
+        SchemaType::Null => Ok(()),
+        SchemaType::Boolean => skip_bytes(reader, 1),
+        SchemaType::Int | SchemaType::Long | SchemaType::TimestampMillis | SchemaType::TimestampMicros => {
+            encoding::read_long(reader).map(|_| ())
+        }
+        SchemaType::Float => skip_bytes(reader, 4),
+        SchemaType::Double => skip_bytes(reader, 8),
+        SchemaType::Bytes | SchemaType::String => {
+            let length = encoding::read_len(reader)?;
+            skip_bytes(reader, length)
+        }
+        SchemaType::Union(branches) => {
+            // Matches `read_union`, so a bad index is reported the same
+            // way whether or not the value is decoded.
+            let index = encoding::read_len(reader)?;
+            let branch = branches.get(index).ok_or(Error::InvalidFormat)?;
+
+            skip_value(reader, branch, schema, options, depth + 1)
+        }
+        SchemaType::Array(_) | SchemaType::Map(_) => loop {
+            let num_values = encoding::read_long(reader)?;
+
+            if num_values == 0 {
+                return Ok(());
+            }
+
+            if num_values < 0 {
+                let block_size = encoding::read_len(reader)?;
+                skip_bytes(reader, block_size)?;
+                continue;
+            }
+
+            for _ in 0..num_values {
+                match schema_type {
+                    SchemaType::Array(item_type) => skip_value(reader, item_type, schema, options, depth + 1)?,
+                    SchemaType::Map(value_type) => {
+                        let key_length = encoding::read_len(reader)?;
+                        skip_bytes(reader, key_length)?;
+                        skip_value(reader, value_type, schema, options, depth + 1)?;
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        },
+        SchemaType::Reference(id) => match schema.resolve_named_type(*id) {
+            NamedType::Fixed(size) => skip_bytes(reader, *size),
+            NamedType::Enum(_) => encoding::read_long(reader).map(|_| ()),
+            NamedType::Record(fields) => fields
+                .iter()
+                .try_for_each(|field| skip_value(reader, field.schema_type(), schema, options, depth + 1)),
+        },
+    }
+}
+
+/// Encodes a single value with the schema, without any of the datafile
+/// framing. Record fields that are missing from the value are written
+/// with their default. Returns `Error::ValueMismatch` if a value isn't
+/// the type the schema expects, `Error::SchemaMismatch` if it's the
+/// right type but doesn't fit, e.g. a fixed value of the wrong length
+/// or an enum symbol the schema doesn't list, and
+/// `Error::NoMatchingUnionBranch` if a union has no branch the value
+/// fits.
+fn encode(value: &AvroValue, schema: &Schema) -> Result<Vec<u8>, Error> {
+    let mut encoded = Vec::new();
+    write_value(&mut encoded, value, schema.root(), schema)?;
+    Ok(encoded)
+}
+
+/// Decodes a single value encoded with the schema, the inverse of
+/// `encode`. Any bytes after the value are ignored.
+fn decode<'a>(encoded: &[u8], schema: &'a Schema) -> Result<AvroValue<'a>, Error> {
+    decode_counted(encoded, schema).map(|(value, _)| value)
+}
+
+/// Like `decode`, but also returns how many bytes the value took up,
+/// so values packed back to back in one buffer can be decoded in turn.
+fn decode_counted<'a>(encoded: &[u8], schema: &'a Schema) -> Result<(AvroValue<'a>, usize), Error> {
+    let mut remaining = encoded;
+    let value = AvroDatafile::read_value(&mut remaining, schema.root(), schema, &ReadOptions::default())?;
+
+    Ok((value, encoded.len() - remaining.len()))
+}
+
+fn write_value<W: Write>(
+    writer: &mut W,
+    value: &AvroValue,
+    schema_type: &SchemaType,
+    schema: &Schema,
+) -> Result<(), Error> {
+    match (value, schema_type) {
+        (AvroValue::Null, SchemaType::Null) => Ok(()),
+        (AvroValue::Boolean(value), SchemaType::Boolean) => encoding::write_bool(writer, *value),
+        (AvroValue::Int(value), SchemaType::Int) => encoding::write_long(writer, *value as i64),
+        (AvroValue::Long(value), SchemaType::Long)
+        | (AvroValue::TimestampMillis(value), SchemaType::TimestampMillis)
+        | (AvroValue::TimestampMicros(value), SchemaType::TimestampMicros) => encoding::write_long(writer, *value),
+        (AvroValue::Float(value), SchemaType::Float) => encoding::write_float(writer, *value),
+        (AvroValue::Double(value), SchemaType::Double) => encoding::write_double(writer, *value),
+        (AvroValue::Bytes(value), SchemaType::Bytes) => encoding::write_bytes(writer, value),
+        (AvroValue::String(value), SchemaType::String) => encoding::write_string(writer, value),
+        (value, SchemaType::Union(branches)) => {
+            let index = branches
+                .iter()
+                .position(|branch| is_value_of_type(value, branch, schema))
+                .ok_or(Error::NoMatchingUnionBranch)?;
+
+            encoding::write_long(writer, index as i64)?;
+            write_value(writer, value, &branches[index], schema)
+        }
+        (AvroValue::Array(items), SchemaType::Array(item_type)) => {
+            if !items.is_empty() {
+                encoding::write_long(writer, items.len() as i64)?;
+
+                for item in items {
+                    write_value(writer, item, item_type, schema)?;
+                }
+            }
+
+            encoding::write_long(writer, 0)
+        }
+        (AvroValue::Map(entries), SchemaType::Map(value_type)) => {
+            if !entries.is_empty() {
+                encoding::write_long(writer, entries.len() as i64)?;
+
+                for (key, value) in entries {
+                    encoding::write_string(writer, key)?;
+                    write_value(writer, value, value_type, schema)?;
+                }
+            }
+
+            encoding::write_long(writer, 0)
+        }
+        (value, SchemaType::Reference(id)) => match (value, schema.resolve_named_type(*id)) {
+            (AvroValue::Enum(symbol), NamedType::Enum(symbols)) => {
+                let index = symbols.symbol_index(symbol).ok_or(Error::SchemaMismatch)?;
+
+                encoding::write_long(writer, index as i64)
+            }
+            (AvroValue::Fixed(bytes), NamedType::Fixed(size)) if bytes.len() == *size => {
+                writer.write_all(bytes)?;
+                Ok(())
+            }
+            (AvroValue::Record(field_values), NamedType::Record(fields))
+                if field_values
+                    .keys()
+                    .all(|name| fields.iter().any(|field| field.name() == *name)) =>
+            {
+                for field in fields {
+                    match (field_values.get(field.name()), field.default()) {
+                        (Some(field_value), _) => write_value(writer, field_value, field.schema_type(), schema)?,
+                        (None, Some(default)) => {
+                            let default = default_value(default, field.schema_type(), schema)?;
+                            write_value(writer, &default, field.schema_type(), schema)?
+                        }
+                        (None, None) => return Err(Error::SchemaMismatch),
+                    }
+                }
+
+                Ok(())
+            }
+            (AvroValue::Fixed(_), NamedType::Fixed(_)) | (AvroValue::Record(_), NamedType::Record(_)) => {
+                Err(Error::SchemaMismatch)
+            }
+            (value, _) => Err(Error::ValueMismatch {
+                expected: resolution::type_name(schema, schema_type),
+                found: value.type_name(),
+            }),
+        },
+        (value, schema_type) => Err(Error::ValueMismatch {
+            expected: resolution::type_name(schema, schema_type),
+            found: value.type_name(),
+        }),
+    }
+}
+
+/// Checks whether the value can be written as the given type, so a
+/// union can pick its branch. Named types are compared with what the
+/// reference resolves to: a fixed value must be the fixed type's size,
+/// an enum symbol must be one of the enum's symbols, and a record's
+/// fields must all be fields of the record type. Other values are only
+/// checked for the right kind, without looking at their contents.
+fn is_value_of_type(value: &AvroValue, schema_type: &SchemaType, schema: &Schema) -> bool {
+    match (value, schema_type) {
+        (value, SchemaType::Reference(id)) => match (value, schema.resolve_named_type(*id)) {
+            (AvroValue::Enum(symbol), NamedType::Enum(symbols)) => symbols.symbol_index(symbol).is_some(),
+            (AvroValue::Fixed(bytes), NamedType::Fixed(size)) => bytes.len() == *size,
+            (AvroValue::Record(field_values), NamedType::Record(fields)) => field_values
+                .keys()
+                .all(|name| fields.iter().any(|field| field.name() == *name)),
+            _ => false,
+        },
+        (value, schema_type) => matches!(
+            (value, schema_type),
+            (AvroValue::Null, SchemaType::Null)
+                | (AvroValue::Boolean(_), SchemaType::Boolean)
+                | (AvroValue::Int(_), SchemaType::Int)
+                | (AvroValue::Long(_), SchemaType::Long)
+                | (AvroValue::TimestampMillis(_), SchemaType::TimestampMillis)
+                | (AvroValue::TimestampMicros(_), SchemaType::TimestampMicros)
+                | (AvroValue::Float(_), SchemaType::Float)
+                | (AvroValue::Double(_), SchemaType::Double)
+                | (AvroValue::Bytes(_), SchemaType::Bytes)
+                | (AvroValue::String(_), SchemaType::String)
+                | (AvroValue::Array(_), SchemaType::Array(_))
+                | (AvroValue::Map(_), SchemaType::Map(_))
+        ),
+    }
+}
+
+/// Writes a datafile header: the magic bytes, the metadata map with
+/// `avro.schema` and `avro.codec` followed by `extra_metadata` sorted by
+/// key, and the sync marker. Extra entries for `avro.schema` or
+/// `avro.codec` are left out, since the header already has them.
+/// Data blocks written after it must use the same codec and sync
+/// marker.
+fn write_header<W: Write>(
+    writer: &mut W,
+    schema: &Schema,
+    codec: Codec,
+    sync_marker: SyncMarker,
+    extra_metadata: &HashMap<String, Vec<u8>>,
+) -> Result<(), Error> {
+    let schema_json = schema.to_json();
+
+    let mut entries = vec![
+        ("avro.schema", schema_json.as_bytes()),
+        ("avro.codec", codec.name().as_bytes()),
+    ];
+
+    let mut extra_entries: Vec<(&str, &[u8])> = extra_metadata
+        .iter()
+        .filter(|(key, _)| *key != "avro.schema" && *key != "avro.codec")
+        .map(|(key, value)| (key.as_str(), value.as_slice()))
+        .collect();
+    extra_entries.sort();
+    entries.extend(extra_entries);
+
+    writer.write_all(&MAGIC)?;
+    encoding::write_metadata(writer, &entries)?;
+    writer.write_all(&sync_marker)?;
+    Ok(())
+}
+
+fn compress_block(codec: Codec, block: &[u8]) -> Result<Vec<u8>, Error> {
+    match codec {
+        Codec::Null => Ok(block.to_vec()),
+        Codec::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(block)?;
+            Ok(encoder.finish()?)
+        }
+        Codec::Snappy => {
+            let mut compressed = snap::raw::Encoder::new()
+                .compress_vec(block)
+                .map_err(|_| Error::BadEncoding)?;
+            compressed.extend_from_slice(&crc32fast::hash(block).to_be_bytes());
+            Ok(compressed)
+        }
+        #[cfg(feature = "gzip")]
+        Codec::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(block)?;
+            Ok(encoder.finish()?)
+        }
+    }
+}
+
+fn random_sync_marker() -> SyncMarker {
+    // Each RandomState is seeded with random keys, which is enough
+    // randomness for a sync marker without pulling in a dependency.
+    let mut sync_marker: SyncMarker = [0; 16];
+
+    for chunk in sync_marker.chunks_mut(8) {
+        let random = RandomState::new().build_hasher().finish();
+        chunk.copy_from_slice(&random.to_le_bytes());
+    }
+
+    sync_marker
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use schema::RootKind;
+    use std::ops::Deref;
+    use std::path::PathBuf;
+
+    /// A file path in the temp directory that's removed when it goes
+    /// out of scope, so a failing test doesn't leave the file behind.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("lancaster_{}_{}.avro", name, std::process::id())))
+        }
+    }
+
+    impl Deref for TempPath {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempPath {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn read_raw_blocks<P: AsRef<Path>>(path: P) -> Vec<RawBlock> {
+        raw_blocks(BufReader::new(File::open(path).unwrap()))
+            .unwrap()
+            .collect::<Result<_, Error>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn reads_datafiles() {
+        let examples = [
+            ("test_cases/null.avro", vec![AvroValue::Null, AvroValue::Null]),
+            (
+                "test_cases/boolean.avro",
+                vec![AvroValue::Boolean(true), AvroValue::Boolean(false)],
+            ),
+            (
+                "test_cases/int.avro",
+                vec![
+                    AvroValue::Int(42),
+                    AvroValue::Int(-100),
+                    AvroValue::Int(0),
+                    AvroValue::Int(2147483647),
+                    AvroValue::Int(-2147483648),
+                ],
+            ),
+            (
+                "test_cases/long.avro",
+                vec![
+                    AvroValue::Long(42),
+                    AvroValue::Long(-100),
+                    AvroValue::Long(0),
+                    AvroValue::Long(-9223372036854775808),
+                    AvroValue::Long(9223372036854775807),
+                ],
+            ),
+            (
+                "test_cases/float.avro",
+                vec![
+                    AvroValue::Float(std::f32::consts::PI),
+                    AvroValue::Float(0.0),
+                    AvroValue::Float(3.402_823_5E38),
+                    AvroValue::Float(-3.402_823_5E38),
+                ],
+            ),
+            (
+                "test_cases/double.avro",
+                vec![
+                    AvroValue::Double(0.0),
+                    AvroValue::Double(f64::MAX),
+                    AvroValue::Double(f64::MIN),
+                ],
+            ),
+            (
+                "test_cases/string.avro",
+                vec![
+                    AvroValue::String("foo".to_string()),
+                    AvroValue::String("bar".to_string()),
+                    AvroValue::String("".to_string()),
+                    AvroValue::String("\u{263A}".to_string()),
+                ],
+            ),
+            (
+                "test_cases/bytes.avro",
+                vec![AvroValue::Bytes(vec![1, 2, 3]), AvroValue::Bytes(vec![0xff, 0x01])],
+            ),
+            ("test_cases/union.avro", vec![AvroValue::Null, AvroValue::Boolean(true)]),
+            (
+                "test_cases/union_string.avro",
+                vec![
+                    AvroValue::Null,
+                    AvroValue::String("foo".to_string()),
+                    AvroValue::Null,
+                    AvroValue::String("bar".to_string()),
+                ],
+            ),
+            (
+                "test_cases/array.avro",
+                vec![
+                    AvroValue::Array(vec![AvroValue::Int(1), AvroValue::Int(2), AvroValue::Int(3)]),
+                    AvroValue::Array(vec![AvroValue::Int(-10), AvroValue::Int(-20)]),
+                ],
+            ),
+            (
+                "test_cases/enum.avro",
+                vec![
+                    AvroValue::Enum("clubs"),
+                    AvroValue::Enum("hearts"),
+                    AvroValue::Enum("spades"),
+                ],
+            ),
+            (
+                "test_cases/fixed.avro",
+                vec![AvroValue::Fixed(vec![1, 2, 3, 4]), AvroValue::Fixed(vec![5, 6, 7, 8])],
+            ),
+        ];
+
+        for (filename, expected_values) in examples.iter() {
+            let mut schema_registry = SchemaRegistry::new();
+            let datafile = AvroDatafile::open(filename, &mut schema_registry).unwrap();
+            let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+            assert_eq!(actual_values, *expected_values);
+        }
+    }
+
+    #[test]
+    fn name_value_types() {
+        let examples = [
+            (AvroValue::Null, "null"),
+            (AvroValue::Boolean(true), "boolean"),
+            (AvroValue::Int(42), "int"),
+            (AvroValue::Long(42), "long"),
+            (AvroValue::Float(1.5), "float"),
+            (AvroValue::Double(1.5), "double"),
+            (AvroValue::String("foo".to_string()), "string"),
+            (AvroValue::Bytes(vec![0x66]), "bytes"),
+            (AvroValue::Array(vec![]), "array"),
+            (AvroValue::Map(HashMap::new()), "map"),
+            (AvroValue::Enum("hearts"), "enum"),
+            (AvroValue::Fixed(vec![0; 4]), "fixed"),
+            (AvroValue::Record(HashMap::new()), "record"),
+        ];
+
+        for (value, expected) in examples.iter() {
+            assert_eq!(value.type_name(), *expected);
+        }
+    }
+
+    #[test]
+    fn read_ints_with_five_byte_encoding() {
+        let input = vec![
+            0xfe, 0xff, 0xff, 0xff, 0x0f, // 2147483647
+            0xff, 0xff, 0xff, 0xff, 0x0f, // -2147483648
+        ];
+        let mut reader = input.as_slice();
+
+        let schema = Schema::parse(r#""int""#).unwrap();
+        let options = ReadOptions::new();
+
+        assert_eq!(
+            AvroDatafile::read_value(&mut reader, schema.root(), &schema, &options),
+            Ok(AvroValue::Int(2147483647))
+        );
+        assert_eq!(
+            AvroDatafile::read_value(&mut reader, schema.root(), &schema, &options),
+            Ok(AvroValue::Int(-2147483648))
+        );
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn read_maps_from_file() {
+        // There isn't an easy way to define hashmap literals in the
+        // previous test, so pulling this out as a separate test.
+        let mut first = HashMap::new();
+        first.insert("foo".to_string(), AvroValue::Int(1));
+        first.insert("bar".to_string(), AvroValue::Int(2));
+
+        let mut second = HashMap::new();
+        second.insert("hi".to_string(), AvroValue::Int(-1));
+
+        let expected_values = vec![AvroValue::Map(first), AvroValue::Map(second)];
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/map.avro", &mut schema_registry).unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        assert_eq!(actual_values, expected_values);
+    }
+
+    #[test]
+    fn read_map_keys_without_values() {
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/map.avro", &mut schema_registry).unwrap();
+        let mut keys: Vec<Vec<String>> = datafile.into_map_keys_iter().collect::<Result<_, Error>>().unwrap();
+        keys.iter_mut().for_each(|keys| keys.sort());
+
+        assert_eq!(
+            keys,
+            vec![vec!["bar".to_string(), "foo".to_string()], vec!["hi".to_string()]]
+        );
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/record.avro", &mut schema_registry).unwrap();
+        assert_eq!(datafile.into_map_keys_iter().next(), Some(Err(Error::SchemaMismatch)));
+    }
+
+    #[test]
+    fn skip_values_without_decoding() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "event", "fields": [
+                 {"name": "tags", "type": {"type": "map", "values": ["null", "string"]}},
+                 {"name": "scores", "type": {"type": "array", "items": "double"}},
+                 {"name": "id", "type": {"type": "fixed", "name": "id", "size": 4}},
+                 {"name": "ok", "type": "boolean"}
+               ]}"#,
+        )
+        .unwrap();
+
+        let mut tags = HashMap::new();
+        tags.insert("a".to_string(), AvroValue::Null);
+        tags.insert("b".to_string(), AvroValue::String("bee".to_string()));
+
+        let mut fields = HashMap::new();
+        fields.insert("tags", AvroValue::Map(tags));
+        fields.insert(
+            "scores",
+            AvroValue::Array(vec![AvroValue::Double(1.5), AvroValue::Double(-2.0)]),
+        );
+        fields.insert("id", AvroValue::Fixed(vec![1, 2, 3, 4]));
+        fields.insert("ok", AvroValue::Boolean(true));
+
+        let mut encoded = encode(&AvroValue::Record(fields), &schema).unwrap();
+        encoded.push(0xff);
+
+        let mut reader = encoded.as_slice();
+        assert_eq!(
+            skip_value(&mut reader, schema.root(), &schema, &ReadOptions::new(), 0),
+            Ok(())
+        );
+        assert_eq!(reader, &[0xff]);
+
+        // An array block of two ints written with a negative count, then
+        // its size in bytes.
+        let array_type = SchemaType::Array(Box::new(SchemaType::Int));
+        let mut reader: &[u8] = &[0x03, 0x04, 0x02, 0x04, 0x00];
+        assert_eq!(
+            skip_value(&mut reader, &array_type, &schema, &ReadOptions::new(), 0),
+            Ok(())
+        );
+        assert!(reader.is_empty());
+
+        // A union index past the last branch is the same error whether
+        // the value is skipped or decoded.
+        let union_schema = Schema::parse(r#"["null", "string"]"#).unwrap();
+        let mut reader: &[u8] = &[0x04];
+        assert_eq!(
+            skip_value(&mut reader, union_schema.root(), &union_schema, &ReadOptions::new(), 0),
+            Err(Error::InvalidFormat)
+        );
+        assert_eq!(decode(&[0x04], &union_schema), Err(Error::InvalidFormat));
+    }
+
+    #[test]
+    fn convert_maps_into_typed_hashmaps() {
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/map.avro", &mut schema_registry).unwrap();
+        let actual: Vec<HashMap<String, i64>> = datafile
+            .map(|value| value.and_then(AvroValue::into_string_map))
+            .collect::<Result<_, Error>>()
+            .unwrap();
+
+        let mut first = HashMap::new();
+        first.insert("foo".to_string(), 1);
+        first.insert("bar".to_string(), 2);
+
+        let mut second = HashMap::new();
+        second.insert("hi".to_string(), -1);
+
+        assert_eq!(actual, vec![first, second]);
+
+        let mut entries = HashMap::new();
+        entries.insert("foo".to_string(), AvroValue::String("one".to_string()));
+
+        assert_eq!(
+            AvroValue::Map(entries).into_string_map::<i64>(),
+            Err(Error::ValueMismatch {
+                expected: "long".to_string(),
+                found: "string",
+            })
+        );
+        assert_eq!(
+            AvroValue::Long(1).into_string_map::<i64>(),
+            Err(Error::ValueMismatch {
+                expected: "map".to_string(),
+                found: "long",
+            })
+        );
+    }
+
+    #[test]
+    fn convert_fixed_values_into_byte_arrays() {
+        let uuid: Vec<u8> = (0..16).collect();
+
+        assert_eq!(
+            <[u8; 16]>::try_from(&AvroValue::Fixed(uuid.clone())),
+            Ok([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15])
+        );
+        assert_eq!(<[u8; 32]>::try_from(&AvroValue::Bytes(vec![7; 32])), Ok([7; 32]));
+        assert_eq!(
+            <[u8; 16]>::try_from(&AvroValue::Fixed(vec![1, 2, 3, 4])),
+            Err(Error::LengthMismatch { expected: 16, found: 4 })
+        );
+        assert_eq!(
+            <[u8; 20]>::try_from(&AvroValue::Fixed(uuid)),
+            Err(Error::LengthMismatch {
+                expected: 20,
+                found: 16
+            })
+        );
+        assert_eq!(
+            <[u8; 16]>::try_from(&AvroValue::String("uuid".to_string())),
+            Err(Error::ValueMismatch {
+                expected: "fixed".to_string(),
+                found: "string",
+            })
+        );
+    }
+
+    #[test]
+    fn look_up_codecs_from_metadata() {
+        let examples = [
+            ("null", Some(Codec::Null)),
+            ("deflate", Some(Codec::Deflate)),
+            ("snappy", Some(Codec::Snappy)),
+            ("bzip2", None),
+            ("xz", None),
+            ("zstandard", None),
+            ("Deflate", None),
+        ];
+
+        for (name, expected) in examples.iter() {
+            assert_eq!(Codec::from_metadata(name), *expected);
+        }
+
+        for codec in [Codec::Null, Codec::Deflate, Codec::Snappy].iter() {
+            assert_eq!(Codec::from_metadata(codec.name()), Some(*codec));
+        }
+    }
+
+    #[test]
+    fn read_records_from_file() {
+        let mut first = HashMap::new();
+        first.insert("email", AvroValue::String("bloblaw@example.com".to_string()));
+        first.insert("age", AvroValue::Int(42));
+
+        let mut second = HashMap::new();
+        second.insert("email", AvroValue::String("gmbluth@example.com".to_string()));
+        second.insert("age", AvroValue::Int(16));
+
+        let expected_values = vec![AvroValue::Record(first), AvroValue::Record(second)];
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/record.avro", &mut schema_registry).unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        assert_eq!(actual_values, expected_values);
+    }
+
+    #[test]
+    fn override_field_types_with_promotion() {
+        let mut first = HashMap::new();
+        first.insert("email", AvroValue::String("bloblaw@example.com".to_string()));
+        first.insert("age", AvroValue::Long(42));
+
+        let mut second = HashMap::new();
+        second.insert("email", AvroValue::String("gmbluth@example.com".to_string()));
+        second.insert("age", AvroValue::Long(16));
+
+        let expected_values = vec![AvroValue::Record(first), AvroValue::Record(second)];
+
+        let options = ReadOptions::new().field_override("age", SchemaType::Long);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile =
+            AvroDatafile::open_with_options("test_cases/record.avro", &mut schema_registry, options).unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        assert_eq!(actual_values, expected_values);
+
+        let options = ReadOptions::new().field_override("age", SchemaType::Boolean);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile =
+            AvroDatafile::open_with_options("test_cases/record.avro", &mut schema_registry, options).unwrap();
+        assert_eq!(
+            datafile.next(),
+            Some(Err(Error::Resolution(ResolutionError::IncompatibleTypes {
+                writer: "int".to_string(),
+                reader: "boolean".to_string(),
+            })))
+        );
+
+        // Logical types promote like their underlying type.
+        let options = ReadOptions::new().field_override("age", SchemaType::TimestampMillis);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile =
+            AvroDatafile::open_with_options("test_cases/record.avro", &mut schema_registry, options).unwrap();
+        let ages: Vec<Option<AvroValue>> = datafile
+            .map(|value| match value.unwrap() {
+                AvroValue::Record(mut fields) => fields.remove("age"),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            ages,
+            vec![
+                Some(AvroValue::TimestampMillis(42)),
+                Some(AvroValue::TimestampMillis(16))
+            ]
+        );
+    }
+
+    #[test]
+    fn read_records_with_reader_schema() {
+        let reader_schema = Schema::parse(
+            r#"{
+              "type": "record",
+              "name": "user",
+              "fields": [{"name": "age", "type": "long"}]
+            }"#,
+        )
+        .unwrap();
+
+        let mut first = HashMap::new();
+        first.insert("age", AvroValue::Long(42));
+
+        let mut second = HashMap::new();
+        second.insert("age", AvroValue::Long(16));
+
+        let expected_values = vec![AvroValue::Record(first), AvroValue::Record(second)];
+
+        let options = ReadOptions::new().reader_schema(&reader_schema);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile =
+            AvroDatafile::open_with_options("test_cases/record.avro", &mut schema_registry, options).unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        assert_eq!(actual_values, expected_values);
+    }
+
+    #[test]
+    fn fill_reader_only_fields_with_defaults() {
+        let reader_schema = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "country", "type": "string", "default": "US"}
+               ]}"#,
+        )
+        .unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().reader_schema(&reader_schema);
+        let datafile =
+            AvroDatafile::open_with_options("test_cases/record.avro", &mut schema_registry, options).unwrap();
+        let values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        assert_eq!(values.len(), 2);
+        for value in values {
+            match value {
+                AvroValue::Record(fields) => {
+                    assert_eq!(fields.len(), 2);
+                    assert_eq!(fields.get("country"), Some(&AvroValue::String("US".to_string())));
+                }
+                _ => panic!("expected a record"),
+            }
+        }
+    }
+
+    #[test]
+    fn reject_reader_schema_with_different_root() {
+        let reader_schema = Schema::parse(r#""long""#).unwrap();
+
+        let options = ReadOptions::new().reader_schema(&reader_schema);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let result = AvroDatafile::open_with_options("test_cases/record.avro", &mut schema_registry, options);
+        assert_eq!(
+            result.unwrap_err(),
+            Error::Resolution(ResolutionError::IncompatibleTypes {
+                writer: "user".to_string(),
+                reader: "long".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn combine_read_options() {
+        let expected_values = vec![
+            AvroValue::String("foo".to_string()),
+            AvroValue::String("b\u{FFFD}r".to_string()),
+        ];
+
+        let options = ReadOptions::new().limit(2).lossy_utf8(true);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile =
+            AvroDatafile::open_with_options("test_cases/string_invalid_utf8.avro", &mut schema_registry, options)
+                .unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        assert_eq!(actual_values, expected_values);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/string_invalid_utf8.avro", &mut schema_registry).unwrap();
+        let actual_values: Vec<Result<AvroValue, Error>> = datafile.collect();
+        assert_eq!(actual_values[1], Err(Error::BadEncoding));
+    }
+
+    #[test]
+    fn continue_reading_after_value_error() {
+        let options = ReadOptions::new().continue_on_error(true);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile =
+            AvroDatafile::open_with_options("test_cases/string_corrupt_block.avro", &mut schema_registry, options)
+                .unwrap();
+        let actual_values: Vec<Result<AvroValue, Error>> = datafile.collect();
+
+        assert_eq!(
+            actual_values,
+            vec![
+                Ok(AvroValue::String("foo".to_string())),
+                Ok(AvroValue::String("bar".to_string())),
+                Err(Error::BadEncoding),
+                Ok(AvroValue::String("qux".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn report_whether_iteration_finished_cleanly() {
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::open("test_cases/string.avro", &mut schema_registry).unwrap();
+        assert!(!datafile.finished_cleanly());
+        assert!(datafile.by_ref().all(|value| value.is_ok()));
+        assert!(datafile.finished_cleanly());
+
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::open("test_cases/string_corrupt_block.avro", &mut schema_registry).unwrap();
+        let values: Vec<_> = datafile.by_ref().collect();
+        assert!(values.contains(&Err(Error::BadEncoding)));
+        assert!(!datafile.finished_cleanly());
+
+        let options = ReadOptions::new().continue_on_error(true);
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile =
+            AvroDatafile::open_with_options("test_cases/string_corrupt_block.avro", &mut schema_registry, options)
+                .unwrap();
+        assert_eq!(datafile.by_ref().count(), 4);
+        assert!(!datafile.finished_cleanly());
+
+        let options = ReadOptions::new().limit(1);
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile =
+            AvroDatafile::open_with_options("test_cases/string.avro", &mut schema_registry, options).unwrap();
+        assert_eq!(datafile.by_ref().count(), 1);
+        assert!(!datafile.finished_cleanly());
+    }
+
+    #[test]
+    fn salvage_partial_record_at_end_of_truncated_file() {
+        let mut input = std::fs::read("test_cases/record.avro").unwrap();
+        // Drop the sync marker and the last record's `age` field.
+        input.truncate(input.len() - 17);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::from_reader(input.as_slice(), &mut schema_registry).unwrap();
+        datafile.next().unwrap().unwrap();
+        assert_eq!(
+            datafile.next(),
+            Some(Err(Error::TruncatedRecord {
+                field: "age".to_string()
+            }))
+        );
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().allow_partial_last_record(true);
+        let datafile = AvroDatafile::from_reader_with_options(input.as_slice(), &mut schema_registry, options).unwrap();
+        let values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        assert_eq!(values.len(), 2);
+        match &values[1] {
+            AvroValue::Record(fields) => {
+                assert!(matches!(fields.get("email"), Some(AvroValue::String(_))));
+                assert_eq!(fields.get("age"), None);
+            }
+            _ => panic!("expected a record"),
+        }
+    }
+
+    #[test]
+    fn keep_short_blocks_before_the_end_as_errors_when_salvaging() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "age", "type": "int"}
+               ]}"#,
+        )
+        .unwrap();
+        let sync_marker = *b"abcdefghijklmnop";
+
+        let user = |email: &str, age| {
+            let mut fields = HashMap::new();
+            fields.insert("email", AvroValue::String(email.to_string()));
+            fields.insert("age", AvroValue::Int(age));
+            encode(&AvroValue::Record(fields), &schema).unwrap()
+        };
+
+        // The first block claims two records but its data stops after
+        // the second record's email, and a whole block follows it.
+        let mut first_block = user("bloblaw@example.com", 42);
+        first_block.extend_from_slice(&user("gmbluth@example.com", 16)[..20]);
+        let second_block = user("tobias@example.com", 45);
+
+        let mut output = Vec::new();
+        write_header(&mut output, &schema, Codec::Null, sync_marker, &HashMap::new()).unwrap();
+        for (count, block) in [(2, &first_block), (1, &second_block)].iter() {
+            encoding::write_long(&mut output, *count).unwrap();
+            encoding::write_long(&mut output, block.len() as i64).unwrap();
+            output.extend_from_slice(block);
+            output.extend_from_slice(&sync_marker);
+        }
+
+        // The short record fails just as it would without the option,
+        // and the block after it is still read.
+        for &allow_partial_last_record in [false, true].iter() {
+            let mut schema_registry = SchemaRegistry::new();
+            let options = ReadOptions::new().allow_partial_last_record(allow_partial_last_record);
+            let mut datafile =
+                AvroDatafile::from_reader_with_options(output.as_slice(), &mut schema_registry, options).unwrap();
+            assert!(datafile.next().unwrap().is_ok());
+            assert_eq!(
+                datafile.next(),
+                Some(Err(Error::TruncatedRecord {
+                    field: "age".to_string()
+                }))
+            );
+            assert_eq!(
+                datafile.next().map(|value| encode(&value.unwrap(), &schema).unwrap()),
+                Some(second_block.clone())
+            );
+            assert_eq!(datafile.next(), None);
+        }
+    }
+
+    #[test]
+    fn write_datafile_and_read_it_back() {
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/int.avro", &mut schema_registry).unwrap();
+        let schema = datafile.schema;
+        let expected: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        for &codec in [Codec::Null, Codec::Deflate, Codec::Snappy].iter() {
+            let mut writer = AvroDatafileWriter::new(Vec::new(), schema, codec).with_block_size(1);
+            for value in expected.iter() {
+                writer.append(value).unwrap();
+            }
+            let output = writer.finish().unwrap();
+
+            assert_eq!(&output[..MAGIC.len()], &MAGIC);
+
+            let mut schema_registry = SchemaRegistry::new();
+            let mut datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+            assert_eq!(datafile.codec, codec);
+
+            let actual: Vec<AvroValue> = datafile.by_ref().collect::<Result<_, Error>>().unwrap();
+            assert_eq!(actual, expected, "{:?}", codec);
+            assert_eq!(datafile.stats().blocks, expected.len() as u64);
+        }
+    }
+
+    #[test]
+    fn skip_blocks_that_fail_to_decompress() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+        let sync_marker = *b"abcdefghijklmnop";
+
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Deflate).with_sync_marker(sync_marker);
+        for block in [["foo", "bar"], ["baz", "qux"], ["quux", "corge"]].iter() {
+            for value in block.iter() {
+                writer.append(&AvroValue::String(value.to_string())).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+        let mut output = writer.finish().unwrap();
+
+        // Overwrite the second block's compressed data with bytes that
+        // start a deflate block of an invalid type. The block header is
+        // the object count and byte length, one byte each.
+        let second_block = output
+            .windows(sync_marker.len())
+            .enumerate()
+            .filter(|(_, window)| *window == sync_marker)
+            .map(|(position, _)| position + sync_marker.len())
+            .nth(1)
+            .unwrap();
+        let mut byte_length = &output[second_block + 1..];
+        let byte_length = encoding::read_long(&mut byte_length).unwrap() as usize;
+        for byte in &mut output[second_block + 2..second_block + 2 + byte_length] {
+            *byte = 0xff;
+        }
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().skip_bad_blocks(true);
+        let datafile =
+            AvroDatafile::from_reader_with_options(output.as_slice(), &mut schema_registry, options).unwrap();
+        let actual_values: Vec<Result<AvroValue, Error>> = datafile.collect();
+
+        assert_eq!(actual_values.len(), 5);
+        assert_eq!(actual_values[0], Ok(AvroValue::String("foo".to_string())));
+        assert_eq!(actual_values[1], Ok(AvroValue::String("bar".to_string())));
+        assert!(actual_values[2].is_err());
+        assert_eq!(actual_values[3], Ok(AvroValue::String("quux".to_string())));
+        assert_eq!(actual_values[4], Ok(AvroValue::String("corge".to_string())));
+
+        // Snappy blocks are checked before any of their values are read.
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().skip_bad_blocks(true);
+        let datafile =
+            AvroDatafile::open_with_options("test_cases/string_snappy_bad_crc.avro", &mut schema_registry, options)
+                .unwrap();
+        let actual_values: Vec<Result<AvroValue, Error>> = datafile.collect();
+        assert_eq!(actual_values, vec![Err(Error::ChecksumMismatch)]);
+    }
+
+    #[test]
+    fn decode_bytes_as_base64_strings() {
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().bytes_as_base64(true);
+        let datafile = AvroDatafile::open_with_options("test_cases/bytes.avro", &mut schema_registry, options).unwrap();
+        let values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        assert_eq!(
+            values,
+            vec![
+                AvroValue::String("AQID".to_string()),
+                AvroValue::String("/wE=".to_string())
+            ]
+        );
+
+        let schema = Schema::parse(r#"{"type": "fixed", "name": "md5", "size": 2}"#).unwrap();
+        let options = ReadOptions::new().bytes_as_base64(true);
+        assert_eq!(
+            AvroDatafile::read_value(&mut &[0xff, 0x01][..], schema.root(), &schema, &options),
+            Ok(AvroValue::String("/wE=".to_string()))
+        );
+    }
+
+    #[test]
+    fn stream_large_fixed_values_into_sink() {
+        let schema = Schema::parse(
+            r#"{
+                "type": "record",
+                "name": "upload",
+                "fields": [
+                    {"name": "checksum", "type": {"type": "fixed", "name": "md5", "size": 16}},
+                    {"name": "contents", "type": {"type": "fixed", "name": "blob", "size": 1048576}}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let checksum: Vec<u8> = (0..16).collect();
+        let contents: Vec<u8> = (0..1_048_576).map(|i| (i % 251) as u8).collect();
+        let mut input = checksum.clone();
+        input.extend_from_slice(&contents);
+
+        let mut streamed = Vec::new();
+        let value = {
+            let options = ReadOptions::new().large_fixed_sink(1024, &mut streamed);
+            AvroDatafile::read_value(&mut input.as_slice(), schema.root(), &schema, &options).unwrap()
+        };
+
+        let mut expected = HashMap::new();
+        expected.insert("checksum", AvroValue::Fixed(checksum));
+        expected.insert("contents", AvroValue::Fixed(Vec::new()));
+
+        assert_eq!(value, AvroValue::Record(expected));
+        assert_eq!(streamed, contents);
+    }
+
+    #[test]
+    fn count_blocks_and_objects_read() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Null);
+        writer.append(&AvroValue::String("foo".to_string())).unwrap();
+        writer.append(&AvroValue::String("bar".to_string())).unwrap();
+        writer.flush().unwrap();
+        writer.append(&AvroValue::String("baz".to_string())).unwrap();
+        let output = writer.finish().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+        assert_eq!(datafile.stats(), &Stats::default());
+
+        let values: Vec<AvroValue> = datafile.by_ref().collect::<Result<_, Error>>().unwrap();
+
+        assert_eq!(
+            datafile.stats(),
+            &Stats {
+                blocks: 2,
+                bytes: 12,
+                objects: values.len() as u64,
+            }
+        );
+    }
+
+    #[test]
+    fn reject_strings_and_bytes_past_max_len() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Null);
+        writer.append(&AvroValue::String("short".to_string())).unwrap();
+        writer.append(&AvroValue::String("x".repeat(100))).unwrap();
+        let output = writer.finish().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().max_string_len(10);
+        let mut datafile =
+            AvroDatafile::from_reader_with_options(output.as_slice(), &mut schema_registry, options).unwrap();
+        assert_eq!(datafile.next(), Some(Ok(AvroValue::String("short".to_string()))));
+        assert_eq!(
+            datafile.next(),
+            Some(Err(Error::LimitExceeded {
+                kind: "string",
+                len: 100
+            }))
+        );
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().max_string_len(10).lossy_utf8(true);
+        let mut datafile =
+            AvroDatafile::from_reader_with_options(output.as_slice(), &mut schema_registry, options).unwrap();
+        assert_eq!(
+            datafile.nth(1),
+            Some(Err(Error::LimitExceeded {
+                kind: "string",
+                len: 100
+            }))
+        );
+
+        let schema = Schema::parse(r#""bytes""#).unwrap();
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Null);
+        writer.append(&AvroValue::Bytes(vec![0; 100])).unwrap();
+        let output = writer.finish().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().max_bytes_len(99).max_string_len(1000);
+        let mut datafile =
+            AvroDatafile::from_reader_with_options(output.as_slice(), &mut schema_registry, options).unwrap();
+        assert_eq!(
+            datafile.next(),
+            Some(Err(Error::LimitExceeded {
+                kind: "bytes",
+                len: 100
+            }))
+        );
+    }
+
+    #[test]
+    fn reject_values_nested_past_max_depth() {
+        let schema = Schema::parse(
+            r#"{
+                "type": "record",
+                "name": "long_list",
+                "fields": [
+                    {"name": "value", "type": "long"},
+                    {"name": "next", "type": ["null", "long_list"]}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        // Each link adds two levels, the next field and the union
+        // branch holding the next record, so 200 links are nested 400
+        // levels deep.
+        let mut encoded = Vec::new();
+        for value in 0..200 {
+            encoding::write_long(&mut encoded, value).unwrap();
+            encoding::write_long(&mut encoded, if value < 199 { 1 } else { 0 }).unwrap();
+        }
+
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Null);
+        writer.append_raw(&encoded).unwrap();
+        let output = writer.finish().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().max_depth(500);
+        let mut datafile =
+            AvroDatafile::from_reader_with_options(output.as_slice(), &mut schema_registry, options).unwrap();
+        assert!(datafile.next().unwrap().is_ok());
+
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+        assert_eq!(
+            datafile.next(),
+            Some(Err(Error::LimitExceeded {
+                kind: "depth",
+                len: 257
+            }))
+        );
+    }
+
+    #[test]
+    fn read_values_one_block_at_a_time() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Deflate);
+        writer.append(&AvroValue::String("foo".to_string())).unwrap();
+        writer.append(&AvroValue::String("bar".to_string())).unwrap();
+        writer.flush().unwrap();
+        writer.append(&AvroValue::String("baz".to_string())).unwrap();
+        let output = writer.finish().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+
+        assert_eq!(
+            datafile.next_block(),
+            Some(Ok(vec![
+                AvroValue::String("foo".to_string()),
+                AvroValue::String("bar".to_string()),
+            ]))
+        );
+        assert_eq!(
+            datafile.next_block(),
+            Some(Ok(vec![AvroValue::String("baz".to_string())]))
+        );
+        assert_eq!(datafile.next_block(), None);
+    }
+
+    #[test]
+    fn read_each_block_in_reverse() {
+        let schema = Schema::parse(r#""int""#).unwrap();
+
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Null);
+        for value in 1..=3 {
+            writer.append(&AvroValue::Int(value)).unwrap();
+        }
+        writer.flush().unwrap();
+        for value in 4..=5 {
+            writer.append(&AvroValue::Int(value)).unwrap();
+        }
+        let output = writer.finish().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+        let blocks: Vec<Vec<AvroValue>> = datafile.blocks_reversed().collect::<Result<_, Error>>().unwrap();
+
+        assert_eq!(
+            blocks,
+            vec![
+                vec![AvroValue::Int(3), AvroValue::Int(2), AvroValue::Int(1)],
+                vec![AvroValue::Int(5), AvroValue::Int(4)],
+            ]
+        );
+    }
+
+    #[test]
+    fn read_values_in_batches() {
+        let schema = Schema::parse(r#""long""#).unwrap();
+
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Null);
+        for value in 0..6 {
+            writer.append(&AvroValue::Long(value)).unwrap();
+
+            if value == 2 {
+                writer.flush().unwrap();
+            }
+        }
+        let output = writer.finish().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+        let batches: Vec<Vec<AvroValue>> = datafile.batches(4).map(Result::unwrap).collect();
+
+        assert_eq!(
+            batches,
+            vec![
+                vec![
+                    AvroValue::Long(0),
+                    AvroValue::Long(1),
+                    AvroValue::Long(2),
+                    AvroValue::Long(3),
+                ],
+                vec![AvroValue::Long(4), AvroValue::Long(5)],
+            ]
+        );
+
+        // Cut the file off before the last value.
+        let truncated = &output[..output.len() - 17];
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::from_reader(truncated, &mut schema_registry).unwrap();
+        let mut batches = datafile.batches(4);
+
+        assert!(matches!(batches.next(), Some(Ok(batch)) if batch.len() == 4));
+        assert_eq!(batches.next(), Some(Ok(vec![AvroValue::Long(4)])));
+        assert!(matches!(batches.next(), Some(Err(_))));
+    }
+
+    #[test]
+    fn decode_timestamps_and_unknown_logical_types() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "event", "fields": [
+                 {"name": "created_at", "type": {
+                   "type": "long",
+                   "connect.name": "org.apache.kafka.connect.data.Timestamp",
+                   "logicalType": "timestamp-millis"
+                 }},
+                 {"name": "updated_at", "type": {"type": "long", "logicalType": "timestamp-micros"}},
+                 {"name": "expires_at", "type": {"type": "long", "logicalType": "epoch-seconds"}}
+               ]}"#,
+        )
+        .unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert("created_at", AvroValue::TimestampMillis(1_600_000_000_000));
+        expected.insert("updated_at", AvroValue::TimestampMicros(1_600_000_000_000_000));
+        expected.insert("expires_at", AvroValue::Long(1_600_000_000));
+        let expected = AvroValue::Record(expected);
+
+        let encoded = encode(&expected, &schema).unwrap();
+        assert_eq!(decode(&encoded, &schema), Ok(expected));
+
+        let long_schema = Schema::parse(r#""long""#).unwrap();
+        assert_eq!(decode(&encoded, &long_schema), Ok(AvroValue::Long(1_600_000_000_000)));
+    }
+
+    #[test]
+    fn report_uncompressed_length_of_current_block() {
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::open("test_cases/string.avro", &mut schema_registry).unwrap();
+
+        assert_eq!(datafile.current_block_uncompressed_len(), None);
+
+        datafile.next().unwrap().unwrap();
+        assert_eq!(datafile.current_block_uncompressed_len(), Some(datafile.stats().bytes));
+
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::open("test_cases/string_deflate.avro", &mut schema_registry).unwrap();
+
+        datafile.next().unwrap().unwrap();
+        assert_eq!(datafile.current_block_uncompressed_len(), None);
+    }
+
+    #[test]
+    fn share_field_names_between_owned_values() {
+        let schema =
+            Schema::parse(r#"{"type": "record", "name": "user", "fields": [{"name": "age", "type": "int"}]}"#).unwrap();
+
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Null);
+        for age in 0..1000 {
+            let mut fields = HashMap::new();
+            fields.insert("age", AvroValue::Int(age));
+            writer.append(&AvroValue::Record(fields)).unwrap();
+        }
+        let output = writer.finish().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+        let values: Vec<OwnedValue> = datafile.into_owned_iter().collect::<Result<_, Error>>().unwrap();
+
+        let field_names: Vec<&Arc<str>> = values
+            .iter()
+            .map(|value| match value {
+                OwnedValue::Record(fields) => fields.keys().next().unwrap(),
+                _ => panic!("expected a record"),
+            })
+            .collect();
+
+        assert_eq!(field_names.len(), 1000);
+        assert_eq!(&**field_names[0], "age");
+        assert!(field_names.iter().all(|name| Arc::ptr_eq(name, field_names[0])));
+    }
+
+    #[test]
+    fn decode_values_straight_into_json() {
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/record.avro", &mut schema_registry).unwrap();
+        let schema = datafile.schema;
+        let expected: Vec<serde_json::Value> = datafile
+            .map(|value| json::value_to_json(&value?, schema.root(), schema))
+            .collect::<Result<_, Error>>()
+            .unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/record.avro", &mut schema_registry).unwrap();
+        let actual: Vec<serde_json::Value> = datafile.into_json_iter().collect::<Result<_, Error>>().unwrap();
+
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual, expected);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().bytes_as_base64(true);
+        let datafile = AvroDatafile::open_with_options("test_cases/bytes.avro", &mut schema_registry, options).unwrap();
+        let actual: Vec<serde_json::Value> = datafile.into_json_iter().collect::<Result<_, Error>>().unwrap();
+        assert_eq!(actual, vec![serde_json::json!("AQID"), serde_json::json!("/wE=")]);
+
+        let reader_schema =
+            Schema::parse(r#"{"type": "record", "name": "user", "fields": [{"name": "age", "type": "long"}]}"#)
+                .unwrap();
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().reader_schema(&reader_schema);
+        let datafile =
+            AvroDatafile::open_with_options("test_cases/record.avro", &mut schema_registry, options).unwrap();
+        let actual: Vec<serde_json::Value> = datafile.into_json_iter().collect::<Result<_, Error>>().unwrap();
+        assert_eq!(
+            actual,
+            vec![serde_json::json!({"age": 42}), serde_json::json!({"age": 16})]
+        );
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().field_override("age", SchemaType::Double);
+        let datafile =
+            AvroDatafile::open_with_options("test_cases/record.avro", &mut schema_registry, options).unwrap();
+        let actual: Vec<serde_json::Value> = datafile.into_json_iter().collect::<Result<_, Error>>().unwrap();
+        assert_eq!(
+            actual,
+            vec![
+                serde_json::json!({"email": "bloblaw@example.com", "age": 42.0}),
+                serde_json::json!({"email": "gmbluth@example.com", "age": 16.0}),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_ndjson_into_datafile() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "age", "type": ["null", "int"]}
+               ]}"#,
+        )
+        .unwrap();
+
+        let input = concat!(
+            r#"{"email": "a@example.com", "age": {"int": 30}}"#,
+            "\n\n",
+            r#"{"email": "b@example.com", "age": null}"#,
+            "\n",
+        );
+
+        let mut output = Vec::new();
+        let value_count = ndjson_to_avro(input.as_bytes(), &schema, &mut output, Codec::Deflate).unwrap();
+        assert_eq!(value_count, 2);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+        let values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        let mut first = HashMap::new();
+        first.insert("email", AvroValue::String("a@example.com".to_string()));
+        first.insert("age", AvroValue::Int(30));
+        let mut second = HashMap::new();
+        second.insert("email", AvroValue::String("b@example.com".to_string()));
+        second.insert("age", AvroValue::Null);
+
+        assert_eq!(values, vec![AvroValue::Record(first), AvroValue::Record(second)]);
+
+        let input = "{\"email\": \"a@example.com\", \"age\": null}\n{\"email\": 1, \"age\": null}\n";
+        assert_eq!(
+            ndjson_to_avro(input.as_bytes(), &schema, Vec::new(), Codec::Null),
+            Err(Error::InvalidJsonLine { line: 2 })
+        );
+    }
+
+    #[test]
+    fn read_values_with_their_encoded_lengths() {
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::open("test_cases/record.avro", &mut schema_registry).unwrap();
+        datafile.next().unwrap().unwrap();
+        let block_length = datafile.current_block_uncompressed_len().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/record.avro", &mut schema_registry).unwrap();
+        let lengths: Vec<usize> = datafile
+            .with_lengths()
+            .map(|result| result.map(|(_, length)| length))
+            .collect::<Result<_, Error>>()
+            .unwrap();
+
+        assert_eq!(count_blocks("test_cases/record.avro"), Ok(1));
+        assert_eq!(lengths.len(), 2);
+        assert_eq!(lengths.iter().sum::<usize>() as u64, block_length);
+    }
+
+    #[test]
+    fn decode_arrays_with_block_sizes() {
+        let schema = Schema::parse(r#"{"type": "array", "items": "long"}"#).unwrap();
+
+        // A count of -2 followed by the block's size of 2 bytes.
+        let input = vec![0x03, 0x04, 0x02, 0x04, 0x02, 0x06, 0x00];
+        assert_eq!(
+            decode(&input, &schema),
+            Ok(AvroValue::Array(vec![
+                AvroValue::Long(1),
+                AvroValue::Long(2),
+                AvroValue::Long(3),
+            ]))
+        );
+    }
+
+    #[test]
+    fn open_datafile_expecting_schema() {
+        let expected = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "age", "type": "int"}
+               ]}"#,
+        )
+        .unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open_expecting("test_cases/record.avro", &mut schema_registry, &expected);
+        assert_eq!(datafile.unwrap().count(), 2);
+
+        let expected = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "age", "type": "long"}
+               ]}"#,
+        )
+        .unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open_expecting("test_cases/record.avro", &mut schema_registry, &expected);
+        assert_eq!(datafile.unwrap_err(), Error::SchemaMismatch);
+    }
+
+    #[test]
+    fn decode_records_into_columns() {
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/record.avro", &mut schema_registry).unwrap();
+        let columns = datafile.into_columns().unwrap();
+
+        assert_eq!(columns.len(), 2);
+        assert_eq!(
+            columns["email"],
+            vec![
+                AvroValue::String("bloblaw@example.com".to_string()),
+                AvroValue::String("gmbluth@example.com".to_string()),
+            ]
+        );
+        assert_eq!(columns["age"].len(), 2);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/int.avro", &mut schema_registry).unwrap();
+        assert_eq!(datafile.into_columns(), Err(Error::SchemaMismatch));
+    }
+
+    #[test]
+    fn open_datafile_expecting_field_names() {
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile =
+            AvroDatafile::open_with_expected_fields("test_cases/record.avro", &mut schema_registry, &["age", "email"]);
+        assert_eq!(datafile.unwrap().count(), 2);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile =
+            AvroDatafile::open_with_expected_fields("test_cases/record.avro", &mut schema_registry, &["email"]);
+        assert_eq!(
+            datafile.unwrap_err(),
+            Error::FieldsMismatch {
+                missing: vec![],
+                unexpected: vec!["age".to_string()],
+            }
+        );
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile =
+            AvroDatafile::open_with_expected_fields("test_cases/record.avro", &mut schema_registry, &["email", "name"]);
+        assert_eq!(
+            datafile.unwrap_err(),
+            Error::FieldsMismatch {
+                missing: vec!["name".to_string()],
+                unexpected: vec!["age".to_string()],
+            }
+        );
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open_with_expected_fields("test_cases/int.avro", &mut schema_registry, &[]);
+        assert_eq!(datafile.unwrap_err(), Error::SchemaMismatch);
+    }
+
+    #[test]
+    fn handle_invalid_avro_files() {
+        let examples = [
+            ("test_cases/nonexistent_file", Error::IO(io::ErrorKind::NotFound)),
+            ("test_cases/non_avro_file", Error::InvalidFormat),
+        ];
+
+        for (filename, expected_err) in examples.iter() {
+            let mut schema_registry = SchemaRegistry::new();
+            let result = AvroDatafile::open(filename, &mut schema_registry);
+            assert!(result.is_err());
+            assert_eq!(result.unwrap_err(), *expected_err);
+        }
+    }
+
+    #[test]
+    fn scan_past_junk_before_magic() {
+        let mut input = vec![0xef, 0xbb, 0xbf, b'\n'];
+        input.extend(std::fs::read("test_cases/string.avro").unwrap());
+
+        let mut schema_registry = SchemaRegistry::new();
+        let result = AvroDatafile::from_reader(input.as_slice(), &mut schema_registry);
+        assert_eq!(result.err(), Some(Error::InvalidFormat));
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().scan_for_magic(3);
+        let result = AvroDatafile::from_reader_with_options(input.as_slice(), &mut schema_registry, options);
+        assert_eq!(result.err(), Some(Error::InvalidFormat));
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().scan_for_magic(16);
+        let datafile = AvroDatafile::from_reader_with_options(input.as_slice(), &mut schema_registry, options).unwrap();
+        assert_eq!(datafile.header_offset(), 4);
+
+        let values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        assert_eq!(values.len(), 4);
+    }
+
+    #[test]
+    fn read_metadata_without_opening_datafile() {
+        let metadata = read_file_metadata("test_cases/string_deflate.avro").unwrap();
+
+        assert_eq!(metadata.get("avro.schema"), Some(&br#""string""#.to_vec()));
+        assert_eq!(metadata.get("avro.codec"), Some(&b"deflate".to_vec()));
+
+        let result = read_file_metadata("test_cases/non_avro_file");
+        assert_eq!(result, Err(Error::InvalidFormat));
+    }
+
+    #[test]
+    fn expose_raw_and_canonical_schema_json() {
+        let schema_json = r#"{
+          "type": "record",
+          "name": "user",
+          "fields": [
+            {"name": "email", "type": "string"},
+            {"name": "age", "type": "int"}
+          ]
+        }"#;
+
+        let mut input = MAGIC.to_vec();
+        encoding::write_metadata(&mut input, &[("avro.schema", schema_json.as_bytes())]).unwrap();
+        input.extend_from_slice(&[0; 16]);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::from_reader(&input[..], &mut schema_registry).unwrap();
+
+        let raw = datafile.schema_json_raw();
+        let canonical = datafile.schema_json_canonical();
+
+        assert_eq!(raw, schema_json);
+        assert_eq!(
+            canonical,
+            r#"{"name":"user","type":"record","fields":[{"name":"email","type":"string"},{"name":"age","type":"int"}]}"#
+        );
+
+        let raw_schema = Schema::parse(raw).unwrap();
+        let canonical_schema = Schema::parse(&canonical).unwrap();
+        assert!(raw_schema.structurally_eq(&canonical_schema));
+    }
+
+    #[test]
+    fn read_root_kind_of_file_schemas() {
+        let root_kind = |path| {
+            let metadata = read_file_metadata(path).unwrap();
+            let schema_str = std::str::from_utf8(&metadata["avro.schema"]).unwrap();
+            Schema::parse(schema_str).unwrap().root_kind()
+        };
+
+        assert_eq!(root_kind("test_cases/record.avro"), RootKind::Record);
+        assert_eq!(root_kind("test_cases/int.avro"), RootKind::Primitive);
+    }
+
+    #[test]
+    fn reject_metadata_without_terminating_block() {
+        let mut input = std::fs::read("test_cases/string.avro").unwrap();
+
+        let terminator = b"null\0abcdefghijklmnop";
+        let position = input
+            .windows(terminator.len())
+            .position(|window| window == terminator)
+            .unwrap();
+        input.remove(position + 4);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let result = AvroDatafile::from_reader(input.as_slice(), &mut schema_registry);
+        assert_eq!(result.unwrap_err(), Error::InvalidFormat);
+    }
+
+    #[test]
+    fn deserialize_files_with_deflate_codec() {
+        let expected_values = vec![
+            AvroValue::String("foo".to_string()),
+            AvroValue::String("bar".to_string()),
+            AvroValue::String("foo".to_string()),
+        ];
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/string_deflate.avro", &mut schema_registry).unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        assert_eq!(actual_values, expected_values);
+    }
+
+    #[test]
+    fn deserialize_files_with_snappy_codec() {
+        let expected_values = vec![
+            AvroValue::String("foo".to_string()),
+            AvroValue::String("bar".to_string()),
+            AvroValue::String("foo".to_string()),
+        ];
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/string_snappy.avro", &mut schema_registry).unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        assert_eq!(actual_values, expected_values);
+    }
+
+    #[test]
+    fn report_snappy_checksum_mismatch() {
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::open("test_cases/string_snappy_bad_crc.avro", &mut schema_registry).unwrap();
+        assert_eq!(datafile.next(), Some(Err(Error::ChecksumMismatch)));
+    }
+
+    /// Writes a deflate datafile of `count` strings, starting a new block
+    /// after every `block_size` of them.
+    fn multi_block_deflate_file(count: usize, block_size: usize) -> Vec<u8> {
+        let schema = Schema::parse(r#""string""#).unwrap();
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Deflate);
+
+        for i in 0..count {
+            let value = format!("{}-{}", i, "x".repeat(i % 40));
+            writer.append(&AvroValue::String(value)).unwrap();
+
+            if i % block_size == block_size - 1 {
+                writer.flush().unwrap();
+            }
+        }
+
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn decompress_deflate_blocks_like_deflate_decoder() {
+        let output = multi_block_deflate_file(2_000, 7);
+        let blocks: Vec<RawBlock> = raw_blocks(output.as_slice())
+            .unwrap()
+            .collect::<Result<_, Error>>()
+            .unwrap();
+        assert!(blocks.len() > 200);
+
+        let mut recycled = RecycledBlockState::default();
+
+        for (index, block) in blocks.iter().enumerate() {
+            let mut expected = Vec::new();
+            flate2::read::DeflateDecoder::new(block.data.as_slice())
+                .read_to_end(&mut expected)
+                .unwrap();
+
+            // Read in uneven chunks so the output of a single call to
+            // `decompress` is split across reads.
+            let mut decoder = DeflateBlock {
+                inner: block.data.as_slice(),
+                state: recycled.take_inflater(),
+            };
+            let mut chunk = vec![0; 1 + index % 13];
+            let mut actual = Vec::new();
+
+            loop {
+                let read = decoder.read(&mut chunk).unwrap();
+                if read == 0 {
+                    break;
+                }
+                actual.extend_from_slice(&chunk[..read]);
+            }
+
+            assert_eq!(actual, expected, "block {}", index);
+            recycled.inflater = Some(decoder.state);
+        }
+    }
+
+    #[test]
+    fn reuse_decompression_state_across_blocks() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+        let values: Vec<String> = (0..300).map(|i| format!("{}-{}", i, "x".repeat(i % 40))).collect();
+
+        for &codec in [Codec::Deflate, Codec::Snappy].iter() {
+            let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, codec);
+            for (i, value) in values.iter().enumerate() {
+                writer.append(&AvroValue::String(value.clone())).unwrap();
+                if i % 7 == 6 {
+                    writer.flush().unwrap();
+                }
+            }
+            let output = writer.finish().unwrap();
+
+            let mut schema_registry = SchemaRegistry::new();
+            let mut datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+            let actual: Vec<_> = (&mut datafile).collect();
+            let expected: Vec<_> = values
+                .iter()
+                .map(|value| Ok(AvroValue::String(value.clone())))
+                .collect();
+
+            assert_eq!(actual, expected);
+            assert!(datafile.stats().blocks > 40);
+
+            match codec {
+                Codec::Deflate => assert!(datafile.recycled.inflater.is_some()),
+                _ => assert!(datafile.recycled.snappy_block.capacity() > 0),
+            }
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn read_and_write_gzip_blocks() {
+        assert_eq!(Codec::from_metadata("gzip"), Some(Codec::Gzip));
+        assert_eq!(Codec::Gzip.name(), "gzip");
+
+        let schema = Schema::parse(r#""string""#).unwrap();
+        let values: Vec<String> = (0..50).map(|i| format!("{}-{}", i, "x".repeat(i % 40))).collect();
+
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Gzip);
+        for (i, value) in values.iter().enumerate() {
+            writer.append(&AvroValue::String(value.clone())).unwrap();
+            if i % 7 == 6 {
+                writer.flush().unwrap();
+            }
+        }
+        let output = writer.finish().unwrap();
+
+        let stats: Vec<BlockStats> = block_stats(output.as_slice())
+            .unwrap()
+            .collect::<Result<_, Error>>()
+            .unwrap();
+        assert_eq!(stats.len(), 8);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+        let actual: Vec<_> = (&mut datafile).collect();
+        let expected: Vec<_> = values
+            .iter()
+            .map(|value| Ok(AvroValue::String(value.clone())))
+            .collect();
+
+        assert_eq!(actual, expected);
+        assert!(datafile.finished_cleanly());
+
+        let expected_values = vec![
+            AvroValue::String("foo".to_string()),
+            AvroValue::String("bar".to_string()),
+            AvroValue::String("foo".to_string()),
+        ];
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/string_gzip.avro", &mut schema_registry).unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        assert_eq!(actual_values, expected_values);
+
+        // The trailer's checksum is checked once the block is read, and
+        // is the last 8 bytes before the sync marker with the length.
+        let mut input = std::fs::read("test_cases/string_gzip.avro").unwrap();
+        let checksum_offset = input.len() - 16 - 8;
+        input[checksum_offset] ^= 0xff;
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::from_reader(input.as_slice(), &mut schema_registry).unwrap();
+        let actual: Vec<_> = datafile.collect();
+        assert_eq!(actual.len(), 4);
+        assert!(matches!(actual[3], Err(Error::IO(_))));
+    }
+
+    #[test]
+    fn reject_invalid_boolean_bytes_unless_lenient() {
+        let schema = Schema::parse(r#""boolean""#).unwrap();
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Null);
+        writer.append_raw(&[0x01]).unwrap();
+        writer.append_raw(&[0x02]).unwrap();
+        let output = writer.finish().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+        assert_eq!(datafile.next(), Some(Ok(AvroValue::Boolean(true))));
+        assert_eq!(datafile.next(), Some(Err(Error::BadEncoding)));
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().lenient(true);
+        let datafile =
+            AvroDatafile::from_reader_with_options(output.as_slice(), &mut schema_registry, options).unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        assert_eq!(actual_values, vec![AvroValue::Boolean(true), AvroValue::Boolean(false)]);
+    }
+
+    #[test]
+    fn read_snappy_blocks_without_checksum_when_lenient() {
+        let mut schema_registry = SchemaRegistry::new();
+        let mut datafile = AvroDatafile::open("test_cases/string_snappy_no_crc.avro", &mut schema_registry).unwrap();
+        assert_eq!(datafile.next(), Some(Err(Error::BadEncoding)));
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().lenient(true);
+        let datafile =
+            AvroDatafile::open_with_options("test_cases/string_snappy_no_crc.avro", &mut schema_registry, options)
+                .unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        assert_eq!(
+            actual_values,
+            vec![
+                AvroValue::String("foo".to_string()),
+                AvroValue::String("bar".to_string()),
+                AvroValue::String("foo".to_string()),
+            ]
+        );
+
+        let mut schema_registry = SchemaRegistry::new();
+        let options = ReadOptions::new().lenient(true);
+        let mut datafile =
+            AvroDatafile::open_with_options("test_cases/string_snappy_bad_crc.avro", &mut schema_registry, options)
+                .unwrap();
+        assert_eq!(datafile.next(), Some(Err(Error::ChecksumMismatch)));
+    }
+
+    #[test]
+    fn copy_raw_records_into_new_file() {
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/record.avro", &mut schema_registry).unwrap();
+
+        let mut writer = AvroDatafileWriter::new(Vec::new(), datafile.schema, Codec::Deflate);
+        for raw_record in datafile.raw_records() {
+            writer.append_raw(&raw_record.unwrap()).unwrap();
+        }
+        let output = writer.finish().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/record.avro", &mut schema_registry).unwrap();
+        let expected_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        assert_eq!(actual_values.len(), 2);
+        assert_eq!(actual_values, expected_values);
+    }
+
+    #[test]
+    fn report_blocks_whose_object_count_disagrees_with_their_data() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+        let sync_marker = *b"abcdefghijklmnop";
+
+        let mut data = Vec::new();
+        for value in ["foo", "bar"].iter() {
+            encoding::write_string(&mut data, value).unwrap();
+        }
+
+        for &codec in [Codec::Null, Codec::Deflate, Codec::Snappy].iter() {
+            let block = compress_block(codec, &data).unwrap();
+            let file_with_counts = |object_counts: &[i64]| {
+                let mut output = Vec::new();
+                write_header(&mut output, &schema, codec, sync_marker, &HashMap::new()).unwrap();
+                for &object_count in object_counts {
+                    encoding::write_long(&mut output, object_count).unwrap();
+                    encoding::write_bytes(&mut output, &block).unwrap();
+                    output.extend_from_slice(&sync_marker);
+                }
+                output
+            };
+
+            let input = file_with_counts(&[3]);
+            let mut schema_registry = SchemaRegistry::new();
+            let mut datafile = AvroDatafile::from_reader(input.as_slice(), &mut schema_registry).unwrap();
+            let actual: Vec<_> = datafile.by_ref().collect();
+            assert_eq!(
+                actual,
+                vec![
+                    Ok(AvroValue::String("foo".to_string())),
+                    Ok(AvroValue::String("bar".to_string())),
+                    Err(Error::BlockInconsistent {
+                        expected: 3,
+                        found: 2,
+                        unread_bytes: 0,
+                    }),
+                ],
+                "{:?}",
+                codec
+            );
+            assert!(!datafile.finished_cleanly());
+
+            // Reading stops at the error like any other unless bad
+            // blocks are skipped, in which case the next block is read.
+            let input = file_with_counts(&[1, 2]);
+            let inconsistent = || Error::BlockInconsistent {
+                expected: 1,
+                found: 1,
+                unread_bytes: 4,
+            };
+
+            let mut schema_registry = SchemaRegistry::new();
+            let datafile = AvroDatafile::from_reader(input.as_slice(), &mut schema_registry).unwrap();
+            let actual: Vec<_> = datafile.collect();
+            assert_eq!(
+                actual,
+                vec![Ok(AvroValue::String("foo".to_string())), Err(inconsistent())],
+                "{:?}",
+                codec
+            );
+
+            let mut schema_registry = SchemaRegistry::new();
+            let options = ReadOptions::new().skip_bad_blocks(true);
+            let datafile =
+                AvroDatafile::from_reader_with_options(input.as_slice(), &mut schema_registry, options).unwrap();
+            let actual: Vec<_> = datafile.collect();
+            assert_eq!(
+                actual,
+                vec![
+                    Ok(AvroValue::String("foo".to_string())),
+                    Err(inconsistent()),
+                    Ok(AvroValue::String("foo".to_string())),
+                    Ok(AvroValue::String("bar".to_string())),
+                ],
+                "{:?}",
+                codec
+            );
+        }
+    }
+
+    #[test]
+    fn write_header_separately_from_data() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+        let sync_marker = *b"abcdefghijklmnop";
+
+        let mut extra_metadata = HashMap::new();
+        extra_metadata.insert("tool.version".to_string(), b"1.2".to_vec());
+        extra_metadata.insert("avro.codec".to_string(), b"snappy".to_vec());
+
+        let mut output = Vec::new();
+        write_header(&mut output, &schema, Codec::Deflate, sync_marker, &extra_metadata).unwrap();
+        let header_len = output.len();
+
+        let block = compress_block(
+            Codec::Deflate,
+            &encode(&AvroValue::String("foo".to_string()), &schema).unwrap(),
+        )
+        .unwrap();
+        encoding::write_long(&mut output, 1).unwrap();
+        encoding::write_bytes(&mut output, &block).unwrap();
+        output.extend_from_slice(&sync_marker);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::from_reader(&output[..header_len], &mut schema_registry).unwrap();
+        assert_eq!(datafile.codec, Codec::Deflate);
+        assert_eq!(datafile.sync_marker, sync_marker);
+        assert_eq!(datafile.count(), 0);
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
+        let actual: Vec<_> = datafile.collect();
+        assert_eq!(actual, vec![Ok(AvroValue::String("foo".to_string()))]);
+
+        let metadata = read_header_metadata(&mut output.as_slice()).unwrap();
+        assert_eq!(metadata.len(), 3);
+        assert_eq!(metadata.get("tool.version"), Some(&b"1.2".to_vec()));
+        assert_eq!(metadata.get("avro.codec"), Some(&b"deflate".to_vec()));
+    }
+
+    #[test]
+    fn transcode_datafile_to_another_codec() {
+        let path = TempPath::new("transcode");
+
+        let record_count = transcode("test_cases/string_deflate.avro", &path, Codec::Null).unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open(&path, &mut schema_registry).unwrap();
+        assert_eq!(datafile.codec, Codec::Null);
+        assert_eq!(
+            datafile.schema.canonical_form(),
+            Schema::parse(r#""string""#).unwrap().canonical_form()
+        );
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/string_deflate.avro", &mut schema_registry).unwrap();
+        let expected_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        assert_eq!(record_count, expected_values.len() as u64);
+        assert_eq!(actual_values, expected_values);
+
+        // Keeping the same codec copies the blocks without recompressing
+        // them.
+        transcode("test_cases/string_deflate.avro", &path, Codec::Deflate).unwrap();
+        assert_eq!(
+            read_raw_blocks(&path),
+            read_raw_blocks("test_cases/string_deflate.avro")
+        );
+    }
+
+    #[test]
+    fn concatenate_datafiles() {
+        let path = TempPath::new("concat");
+
+        let record_count = concat_files(&["test_cases/string.avro", "test_cases/string.avro"], &path).unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open(&path, &mut schema_registry).unwrap();
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/string.avro", &mut schema_registry).unwrap();
+        let mut expected_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/string.avro", &mut schema_registry).unwrap();
+        expected_values.extend(datafile.map(Result::unwrap));
+
+        assert_eq!(record_count, expected_values.len() as u64);
+        assert_eq!(actual_values, expected_values);
+
+        // Blocks are copied as they are when the codecs match, and
+        // recompressed with the first input's codec when they don't.
+        let record_count = concat_files(&["test_cases/string_deflate.avro", "test_cases/string.avro"], &path).unwrap();
+        let actual_blocks = read_raw_blocks(&path);
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open(&path, &mut schema_registry).unwrap();
+        assert_eq!(datafile.codec, Codec::Deflate);
+        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+        // The output shouldn't be created when the schemas don't match.
+        std::fs::remove_file(&path).unwrap();
+
+        let deflate_blocks = read_raw_blocks("test_cases/string_deflate.avro");
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/string_deflate.avro", &mut schema_registry).unwrap();
+        let mut expected_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open("test_cases/string.avro", &mut schema_registry).unwrap();
+        expected_values.extend(datafile.map(Result::unwrap));
+
+        assert_eq!(record_count, expected_values.len() as u64);
+        assert_eq!(actual_blocks.len(), 2);
+        assert_eq!(actual_blocks[0], deflate_blocks[0]);
+        assert_eq!(actual_values, expected_values);
+
+        let result = concat_files(&["test_cases/string.avro", "test_cases/int.avro"], &path);
+        assert_eq!(result, Err(Error::SchemaMismatch));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn reproject_records_to_subset_of_fields() {
+        let path = TempPath::new("reproject");
+
+        let record_count = reproject("test_cases/record.avro", &path, &["email"]).unwrap();
+
+        let mut schema_registry = SchemaRegistry::new();
+        let datafile = AvroDatafile::open(&path, &mut schema_registry).unwrap();
+        let field_names: Vec<&str> = match datafile.schema.resolve_named_type(0) {
+            NamedType::Record(fields) => fields.iter().map(|field| field.name()).collect(),
+            _ => panic!("expected a record"),
+        };
+        assert_eq!(field_names, vec!["email"]);
+
+        let values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
+
+        assert_eq!(values.len() as u64, record_count);
+        for value in values {
+            match value {
+                AvroValue::Record(fields) => {
+                    assert_eq!(fields.len(), 1);
+                    assert!(matches!(fields.get("email"), Some(AvroValue::String(_))));
+                }
+                _ => panic!("expected a record"),
+            }
+        }
+
+        let result = reproject("test_cases/record.avro", &path, &["missing"]);
+        assert_eq!(result, Err(Error::SchemaMismatch));
+    }
+
+    #[test]
+    fn reject_ints_outside_i32_range() {
+        let schema =
+            Schema::parse(r#"{"type": "record", "name": "counter", "fields": [{"name": "count", "type": "int"}]}"#)
+                .unwrap();
 
-impl<R> DataBlockReader<R> {
-    fn inner(self) -> R {
-        match self {
-            Self::Deflate(decoder) => decoder.into_inner().into_inner(),
-            Self::NoCodec(reader) => reader.into_inner(),
-        }
+        let max_int = [0xfe, 0xff, 0xff, 0xff, 0x0f];
+        let mut expected_fields = HashMap::new();
+        expected_fields.insert("count", AvroValue::Int(2_147_483_647));
+        assert_eq!(decode(&max_int, &schema), Ok(AvroValue::Record(expected_fields)));
+
+        let past_max_int = [0x80, 0x80, 0x80, 0x80, 0x10];
+        assert_eq!(
+            decode(&past_max_int, &schema),
+            Err(Error::IntegerOverflow {
+                declared: "int",
+                value: 2_147_483_648
+            })
+        );
+
+        let past_min_int = [0x81, 0x80, 0x80, 0x80, 0x10];
+        assert_eq!(
+            decode(&past_min_int, &schema),
+            Err(Error::IntegerOverflow {
+                declared: "int",
+                value: -2_147_483_649
+            })
+        );
     }
-}
 
-impl<R: BufRead> Read for DataBlockReader<R> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match self {
-            Self::Deflate(decoder) => decoder.read(buf),
-            Self::NoCodec(reader) => reader.read(buf),
-        }
+    #[test]
+    fn decode_with_schemas_built_without_parsing() {
+        let schema = Schema::primitive(SchemaType::Int).unwrap();
+        assert_eq!(decode(&[0x54], &schema), Ok(AvroValue::Int(42)));
+
+        let schema = Schema::record("user", vec![("email", SchemaType::String)]).unwrap();
+        let mut encoded = Vec::new();
+        encoding::write_string(&mut encoded, "bloblaw@example.com").unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert("email", AvroValue::String("bloblaw@example.com".to_string()));
+        assert_eq!(decode(&encoded, &schema), Ok(AvroValue::Record(expected)));
     }
-}
 
-impl<'a> Iterator for AvroDatafile<'a> {
-    type Item = Result<AvroValue<'a>, Error>;
+    #[test]
+    fn decode_values_packed_in_one_buffer() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "age", "type": "int"}
+               ]}"#,
+        )
+        .unwrap();
 
-    fn next(&mut self) -> Option<Result<AvroValue<'a>, Error>> {
-        // We use an Option for position so we can take ownership of
-        // the reader using `take`. This is necessary when we're
-        // starting or finishing a datablock and we need to convert
-        // the reader to the appropriate codec.
-        match self.position.take() {
-            Some(ReaderPosition::StartOfDataBlock { mut reader }) => {
-                let objects_in_block = match encoding::read_long(&mut reader) {
-                    Ok(object_count) => object_count as u64,
-                    Err(Error::IO(io::ErrorKind::UnexpectedEof)) => return None,
-                    Err(e) => return Some(Err(e)),
-                };
+        let mut first = HashMap::new();
+        first.insert("email", AvroValue::String("bloblaw@example.com".to_string()));
+        first.insert("age", AvroValue::Int(42));
+        let first = AvroValue::Record(first);
 
-                let byte_length = match encoding::read_long(&mut reader) {
-                    Ok(byte_length) => byte_length,
-                    Err(e) => return Some(Err(e)),
-                };
+        let mut second = HashMap::new();
+        second.insert("email", AvroValue::String("gmbluth@example.com".to_string()));
+        second.insert("age", AvroValue::Int(-1));
+        let second = AvroValue::Record(second);
 
-                let data_block_reader = match self.codec {
-                    Codec::Null => DataBlockReader::NoCodec(reader.take(byte_length as u64)),
-                    Codec::Deflate => DataBlockReader::Deflate(DeflateDecoder::new(reader.take(byte_length as u64))),
-                };
+        let mut buffer = encode(&first, &schema).unwrap();
+        let first_length = buffer.len();
+        buffer.extend(encode(&second, &schema).unwrap());
 
-                self.position = Some(ReaderPosition::InDataBlock {
-                    remaining_object_count: objects_in_block,
-                    reader: data_block_reader,
-                });
+        let (value, consumed) = decode_counted(&buffer, &schema).unwrap();
+        assert_eq!((value, consumed), (first, first_length));
 
-                self.next()
-            }
-            Some(ReaderPosition::InDataBlock {
-                remaining_object_count,
-                mut reader,
-            }) => {
-                if remaining_object_count > 0 {
-                    let value = Self::read_value(&mut reader, self.schema.root(), self.schema);
-                    self.position = Some(ReaderPosition::InDataBlock {
-                        remaining_object_count: remaining_object_count - 1,
-                        reader,
-                    });
-                    Some(value)
-                } else {
-                    let mut reader = reader.inner();
+        let (value, consumed) = decode_counted(&buffer[first_length..], &schema).unwrap();
+        assert_eq!((value, consumed), (second, buffer.len() - first_length));
 
-                    let mut sync_marker: SyncMarker = [0; 16];
-                    if let Err(e) = reader.read_exact(&mut sync_marker) {
-                        return Some(Err(Error::IO(e.kind())));
-                    }
+        assert_eq!(
+            decode_counted(&buffer[buffer.len()..], &schema),
+            Err(Error::IO(io::ErrorKind::UnexpectedEof))
+        );
+    }
 
-                    if sync_marker != self.sync_marker {
-                        return Some(Err(Error::BadEncoding));
-                    }
+    #[test]
+    fn name_the_field_where_a_record_was_truncated() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "address", "type": {"type": "record", "name": "address", "fields": [
+                   {"name": "street", "type": "string"},
+                   {"name": "city", "type": "string"}
+                 ]}}
+               ]}"#,
+        )
+        .unwrap();
 
-                    self.position = Some(ReaderPosition::StartOfDataBlock { reader });
-                    self.next()
-                }
+        let mut encoded = Vec::new();
+        encoding::write_string(&mut encoded, "bloblaw@example.com").unwrap();
+        assert_eq!(
+            decode(&encoded, &schema),
+            Err(Error::TruncatedRecord {
+                field: "address".to_string()
+            })
+        );
+
+        encoding::write_string(&mut encoded, "1 Main St").unwrap();
+        assert_eq!(
+            decode(&encoded, &schema),
+            Err(Error::TruncatedRecord {
+                field: "city".to_string()
+            })
+        );
+
+        assert_eq!(decode(&[], &schema), Err(Error::IO(io::ErrorKind::UnexpectedEof)));
+    }
+
+    #[test]
+    fn encode_and_decode_values_from_datafiles() {
+        let filenames = [
+            "test_cases/null.avro",
+            "test_cases/boolean.avro",
+            "test_cases/int.avro",
+            "test_cases/long.avro",
+            "test_cases/float.avro",
+            "test_cases/double.avro",
+            "test_cases/string.avro",
+            "test_cases/bytes.avro",
+            "test_cases/union.avro",
+            "test_cases/array.avro",
+            "test_cases/map.avro",
+            "test_cases/enum.avro",
+            "test_cases/fixed.avro",
+            "test_cases/record.avro",
+        ];
+
+        for filename in filenames.iter() {
+            let mut schema_registry = SchemaRegistry::new();
+            let datafile = AvroDatafile::open(filename, &mut schema_registry).unwrap();
+            let schema = datafile.schema;
+
+            for value in datafile {
+                let value = value.unwrap();
+                let encoded = encode(&value, schema).unwrap();
+                assert_eq!(decode(&encoded, schema), Ok(value));
             }
-            // TODO throw an error, shouldn't get here
-            None => None,
         }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
     #[test]
-    fn reads_datafiles() {
+    fn reject_values_that_do_not_match_the_schema() {
         let examples = [
-            ("test_cases/null.avro", vec![AvroValue::Null, AvroValue::Null]),
-            (
-                "test_cases/boolean.avro",
-                vec![AvroValue::Boolean(true), AvroValue::Boolean(false)],
-            ),
-            (
-                "test_cases/int.avro",
-                vec![
-                    AvroValue::Int(42),
-                    AvroValue::Int(-100),
-                    AvroValue::Int(0),
-                    AvroValue::Int(2147483647),
-                    AvroValue::Int(-2147483648),
-                ],
-            ),
-            (
-                "test_cases/long.avro",
-                vec![
-                    AvroValue::Long(42),
-                    AvroValue::Long(-100),
-                    AvroValue::Long(0),
-                    AvroValue::Long(-9223372036854775808),
-                    AvroValue::Long(9223372036854775807),
-                ],
-            ),
-            (
-                "test_cases/float.avro",
-                vec![
-                    AvroValue::Float(std::f32::consts::PI),
-                    AvroValue::Float(0.0),
-                    AvroValue::Float(3.402_823_5E38),
-                    AvroValue::Float(-3.402_823_5E38),
-                ],
-            ),
-            (
-                "test_cases/double.avro",
-                vec![
-                    AvroValue::Double(0.0),
-                    AvroValue::Double(std::f64::MAX),
-                    AvroValue::Double(std::f64::MIN),
-                ],
-            ),
+            (r#""int""#, AvroValue::Long(42), "int", "long"),
+            (r#""string""#, AvroValue::Bytes(vec![0x66]), "string", "bytes"),
             (
-                "test_cases/string.avro",
-                vec![
-                    AvroValue::String("foo".to_string()),
-                    AvroValue::String("bar".to_string()),
-                    AvroValue::String("".to_string()),
-                    AvroValue::String("\u{263A}".to_string()),
-                ],
+                r#"{"type": "array", "items": "int"}"#,
+                AvroValue::Array(vec![AvroValue::Boolean(true)]),
+                "int",
+                "boolean",
             ),
             (
-                "test_cases/bytes.avro",
-                vec![AvroValue::Bytes(vec![1, 2, 3]), AvroValue::Bytes(vec![0xff, 0x01])],
+                r#"{"type": "enum", "name": "suit", "symbols": ["hearts", "spades"]}"#,
+                AvroValue::Fixed(vec![0; 4]),
+                "suit",
+                "fixed",
             ),
-            ("test_cases/union.avro", vec![AvroValue::Null, AvroValue::Boolean(true)]),
+        ];
+
+        for (schema_str, value, expected, found) in examples.iter() {
+            let schema = Schema::parse(schema_str).unwrap();
+            assert_eq!(
+                encode(value, &schema),
+                Err(Error::ValueMismatch {
+                    expected: expected.to_string(),
+                    found,
+                })
+            );
+        }
+
+        let examples = [
             (
-                "test_cases/array.avro",
-                vec![
-                    AvroValue::Array(vec![AvroValue::Int(1), AvroValue::Int(2), AvroValue::Int(3)]),
-                    AvroValue::Array(vec![AvroValue::Int(-10), AvroValue::Int(-20)]),
-                ],
+                r#"{"type": "fixed", "name": "md5", "size": 16}"#,
+                AvroValue::Fixed(vec![0; 4]),
             ),
             (
-                "test_cases/enum.avro",
-                vec![
-                    AvroValue::Enum("clubs"),
-                    AvroValue::Enum("hearts"),
-                    AvroValue::Enum("spades"),
-                ],
+                r#"{"type": "enum", "name": "suit", "symbols": ["hearts", "spades"]}"#,
+                AvroValue::Enum("clubs"),
             ),
             (
-                "test_cases/fixed.avro",
-                vec![AvroValue::Fixed(vec![1, 2, 3, 4]), AvroValue::Fixed(vec![5, 6, 7, 8])],
+                r#"{"type": "record", "name": "user", "fields": [{"name": "age", "type": "int"}]}"#,
+                AvroValue::Record(HashMap::new()),
             ),
         ];
 
-        for (filename, expected_values) in examples.iter() {
-            let mut schema_registry = SchemaRegistry::new();
-            let datafile = AvroDatafile::open(filename, &mut schema_registry).unwrap();
-            let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
-            assert_eq!(actual_values, *expected_values);
+        for (schema_str, value) in examples.iter() {
+            let schema = Schema::parse(schema_str).unwrap();
+            assert_eq!(encode(value, &schema), Err(Error::SchemaMismatch));
         }
     }
 
     #[test]
-    fn read_maps_from_file() {
-        // There isn't an easy way to define hashmap literals in the
-        // previous test, so pulling this out as a separate test.
-        let mut first = HashMap::new();
-        first.insert("foo".to_string(), AvroValue::Int(1));
-        first.insert("bar".to_string(), AvroValue::Int(2));
+    fn encode_values_into_union_branches() {
+        let schema = Schema::parse(r#"["null", "string"]"#).unwrap();
 
-        let mut second = HashMap::new();
-        second.insert("hi".to_string(), AvroValue::Int(-1));
+        assert_eq!(encode(&AvroValue::Null, &schema), Ok(vec![0x00]));
+        assert_eq!(
+            encode(&AvroValue::String("foo".to_string()), &schema),
+            Ok(vec![0x02, 0x06, 0x66, 0x6f, 0x6f])
+        );
+        assert_eq!(encode(&AvroValue::Int(42), &schema), Err(Error::NoMatchingUnionBranch));
 
-        let expected_values = vec![AvroValue::Map(first), AvroValue::Map(second)];
+        let schema = Schema::parse(
+            r#"[
+                {"type": "enum", "name": "suit", "symbols": ["hearts"]},
+                {"type": "fixed", "name": "md5", "size": 2}
+            ]"#,
+        )
+        .unwrap();
 
-        let mut schema_registry = SchemaRegistry::new();
-        let datafile = AvroDatafile::open("test_cases/map.avro", &mut schema_registry).unwrap();
-        let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
-        assert_eq!(actual_values, expected_values);
+        assert_eq!(
+            encode(&AvroValue::Fixed(vec![1, 2]), &schema),
+            Ok(vec![0x02, 0x01, 0x02])
+        );
+        assert_eq!(encode(&AvroValue::Enum("hearts"), &schema), Ok(vec![0x00, 0x00]));
     }
 
     #[test]
-    fn read_records_from_file() {
-        let mut first = HashMap::new();
-        first.insert("email", AvroValue::String("bloblaw@example.com".to_string()));
-        first.insert("age", AvroValue::Int(42));
+    fn encode_values_into_named_branches_of_the_same_kind() {
+        let schema = Schema::parse(
+            r#"[
+                {"type": "fixed", "name": "short_id", "size": 4},
+                {"type": "fixed", "name": "long_id", "size": 8}
+            ]"#,
+        )
+        .unwrap();
 
-        let mut second = HashMap::new();
-        second.insert("email", AvroValue::String("gmbluth@example.com".to_string()));
-        second.insert("age", AvroValue::Int(16));
+        assert_eq!(
+            encode(&AvroValue::Fixed(vec![1; 4]), &schema),
+            Ok(vec![0x00, 1, 1, 1, 1])
+        );
+        assert_eq!(
+            encode(&AvroValue::Fixed(vec![1; 8]), &schema),
+            Ok(vec![0x02, 1, 1, 1, 1, 1, 1, 1, 1])
+        );
+        assert_eq!(
+            encode(&AvroValue::Fixed(vec![1; 2]), &schema),
+            Err(Error::NoMatchingUnionBranch)
+        );
 
-        let expected_values = vec![AvroValue::Record(first), AvroValue::Record(second)];
+        let schema = Schema::parse(
+            r#"[
+                {"type": "enum", "name": "suit", "symbols": ["hearts", "spades"]},
+                {"type": "enum", "name": "color", "symbols": ["red", "black"]}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(encode(&AvroValue::Enum("spades"), &schema), Ok(vec![0x00, 0x02]));
+        assert_eq!(encode(&AvroValue::Enum("black"), &schema), Ok(vec![0x02, 0x02]));
+        assert_eq!(
+            encode(&AvroValue::Enum("green"), &schema),
+            Err(Error::NoMatchingUnionBranch)
+        );
+
+        let schema = Schema::parse(
+            r#"[
+                {"type": "record", "name": "point", "fields": [{"name": "x", "type": "int"}]},
+                {"type": "record", "name": "user", "fields": [{"name": "age", "type": "int"}]}
+            ]"#,
+        )
+        .unwrap();
+
+        let mut fields = HashMap::new();
+        fields.insert("age", AvroValue::Int(42));
+        assert_eq!(encode(&AvroValue::Record(fields), &schema), Ok(vec![0x02, 0x54]));
+    }
+
+    #[test]
+    fn encode_missing_fields_with_defaults() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "nickname", "type": ["null", "string"], "default": null},
+                 {"name": "age", "type": "int", "default": 21},
+                 {"name": "email", "type": "string"}
+               ]}"#,
+        )
+        .unwrap();
+
+        let mut fields = HashMap::new();
+        fields.insert("email", AvroValue::String("a".to_string()));
+        let value = AvroValue::Record(fields);
+
+        assert_eq!(encode(&value, &schema), Ok(vec![0x00, 0x2a, 0x02, 0x61]));
+        assert_eq!(
+            encode(&AvroValue::Record(HashMap::new()), &schema),
+            Err(Error::SchemaMismatch)
+        );
+    }
+
+    #[test]
+    fn encode_and_decode_null_only_unions() {
+        let schema = Schema::parse(r#"["null"]"#).unwrap();
+
+        assert_eq!(decode(&[0x00], &schema), Ok(AvroValue::Null));
+        assert_eq!(decode(&[0x02], &schema), Err(Error::InvalidFormat));
+
+        assert_eq!(encode(&AvroValue::Null, &schema), Ok(vec![0x00]));
+        assert_eq!(encode(&AvroValue::Int(1), &schema), Err(Error::NoMatchingUnionBranch));
+    }
+
+    #[test]
+    fn reject_out_of_range_union_indexes() {
+        let schema = Schema::parse(r#"["null", "string"]"#).unwrap();
+
+        let mut huge_index = Vec::new();
+        encoding::write_long(&mut huge_index, i64::MAX).unwrap();
+        assert_eq!(decode(&huge_index, &schema), Err(Error::InvalidFormat));
+
+        // 2^32 would wrap around to index 0 if it were truncated to 32 bits.
+        let mut wrapping_index = Vec::new();
+        encoding::write_long(&mut wrapping_index, 1 << 32).unwrap();
+        assert_eq!(decode(&wrapping_index, &schema), Err(Error::InvalidFormat));
+
+        let mut negative_index = Vec::new();
+        encoding::write_long(&mut negative_index, -1).unwrap();
+        assert_eq!(decode(&negative_index, &schema), Err(Error::BadEncoding));
+    }
+
+    #[test]
+    fn create_datafile_from_path() {
+        let schema = Schema::parse(r#""int""#).unwrap();
+        let values = vec![AvroValue::Int(42), AvroValue::Int(-100), AvroValue::Int(0)];
+        let path = TempPath::new("create");
+
+        let mut writer = AvroDatafileWriter::create(&path, &schema, Codec::Deflate).unwrap();
+        for value in values.iter() {
+            writer.append(value).unwrap();
+        }
+        writer.finish().unwrap();
 
         let mut schema_registry = SchemaRegistry::new();
-        let datafile = AvroDatafile::open("test_cases/record.avro", &mut schema_registry).unwrap();
+        let datafile = AvroDatafile::open(&path, &mut schema_registry).unwrap();
         let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
-        assert_eq!(actual_values, expected_values);
+
+        assert_eq!(actual_values, values);
     }
 
     #[test]
-    fn handle_invalid_avro_files() {
-        let examples = [
-            ("test_cases/nonexistent_file", Error::IO(io::ErrorKind::NotFound)),
-            ("test_cases/non_avro_file", Error::InvalidFormat),
-        ];
+    fn write_identical_files_with_fixed_sync_marker() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+
+        let write_file = || {
+            let mut writer =
+                AvroDatafileWriter::new(Vec::new(), &schema, Codec::Snappy).with_sync_marker(*b"abcdefghijklmnop");
+            writer.append(&AvroValue::String("foo".to_string())).unwrap();
+            writer.append(&AvroValue::String("bar".to_string())).unwrap();
+            writer.finish().unwrap()
+        };
+
+        let output = write_file();
+        assert_eq!(output, write_file());
+        assert_eq!(&output[output.len() - 16..], b"abcdefghijklmnop");
+    }
+
+    #[test]
+    fn count_blocks_in_datafiles() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+        let path = TempPath::new("count_blocks");
+
+        let mut writer = AvroDatafileWriter::create(&path, &schema, Codec::Deflate).unwrap();
+        writer.append(&AvroValue::String("foo".to_string())).unwrap();
+        writer.append(&AvroValue::String("bar".to_string())).unwrap();
+        writer.flush().unwrap();
+        writer.append(&AvroValue::String("baz".to_string())).unwrap();
+        writer.finish().unwrap();
+
+        let block_count = count_blocks(&path);
+
+        assert_eq!(block_count, Ok(2));
+        assert_eq!(count_blocks("test_cases/string_corrupt_block.avro"), Ok(3));
+        assert_eq!(count_blocks("test_cases/non_avro_file"), Err(Error::InvalidFormat));
+
+        let mut negative_count = Vec::new();
+        write_header(&mut negative_count, &schema, Codec::Null, [0; 16], &HashMap::new()).unwrap();
+        encoding::write_long(&mut negative_count, -1).unwrap();
+        encoding::write_long(&mut negative_count, 0).unwrap();
+        negative_count.extend_from_slice(&[0; 16]);
+        std::fs::write(&path, negative_count).unwrap();
+
+        assert_eq!(count_blocks(&path), Err(Error::BadEncoding));
+    }
+
+    #[test]
+    fn count_records_with_and_without_seeking() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+        let path = TempPath::new("count_records");
+
+        let mut writer = AvroDatafileWriter::create(&path, &schema, Codec::Deflate).unwrap();
+        writer.append(&AvroValue::String("foo".to_string())).unwrap();
+        writer.append(&AvroValue::String("bar".to_string())).unwrap();
+        writer.flush().unwrap();
+        writer.append(&AvroValue::String("baz".to_string())).unwrap();
+        writer.finish().unwrap();
+
+        let seeking_count = count_records(&path);
+        let copying_count = count_records_from_reader(File::open(&path).unwrap());
+
+        assert_eq!(seeking_count, Ok(3));
+        assert_eq!(copying_count, Ok(3));
+
+        for path in [
+            "test_cases/string.avro",
+            "test_cases/record.avro",
+            "test_cases/string_snappy.avro",
+        ]
+        .iter()
+        {
+            let copying_count = count_records_from_reader(File::open(path).unwrap());
+            assert_eq!(count_records(path), copying_count);
+        }
+
+        let mut truncated = std::fs::read("test_cases/string.avro").unwrap();
+        truncated.truncate(truncated.len() - 4);
+        assert_eq!(
+            count_records_from_reader(truncated.as_slice()),
+            Err(Error::IO(io::ErrorKind::UnexpectedEof))
+        );
+    }
+
+    #[test]
+    fn read_the_last_values_of_a_file() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+        let path = TempPath::new("tail");
+
+        // Large enough that the tail has to be found by scanning for a
+        // sync marker, and in the last case widened a few times.
+        let mut writer = AvroDatafileWriter::create(&path, &schema, Codec::Null).unwrap();
+        for block in 0..20 {
+            for i in 0..1000 {
+                writer
+                    .append(&AvroValue::String(format!("{:04}-{:04}-{}", block, i, "x".repeat(50))))
+                    .unwrap();
+            }
+            writer.flush().unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut registry = SchemaRegistry::new();
+        let last_two = AvroDatafile::tail(&path, &mut registry, 2);
+        let mut registry = SchemaRegistry::new();
+        let last_many = AvroDatafile::tail(&path, &mut registry, 5000).map(|values| values.len());
+        let mut registry = SchemaRegistry::new();
+        let everything = AvroDatafile::tail(&path, &mut registry, 50_000).map(|values| values.len());
+        let mut registry = SchemaRegistry::new();
+        let nothing = AvroDatafile::tail(&path, &mut registry, 0).map(|values| values.len());
+
+        let x = "x".repeat(50);
+        assert_eq!(
+            last_two,
+            Ok(vec![
+                AvroValue::String(format!("0019-0998-{}", x)),
+                AvroValue::String(format!("0019-0999-{}", x)),
+            ])
+        );
+        assert_eq!(last_many, Ok(5000));
+        assert_eq!(everything, Ok(20_000));
+        assert_eq!(nothing, Ok(0));
+    }
+
+    #[test]
+    fn read_whole_files_into_owned_values() {
+        assert_eq!(
+            read_file("test_cases/int.avro"),
+            Ok(vec![
+                OwnedValue::Int(42),
+                OwnedValue::Int(-100),
+                OwnedValue::Int(0),
+                OwnedValue::Int(i32::MAX),
+                OwnedValue::Int(i32::MIN),
+            ])
+        );
+        assert_eq!(read_file("test_cases/non_avro_file"), Err(Error::InvalidFormat));
+    }
+
+    #[test]
+    fn measure_compression_of_each_block() {
+        let schema = Schema::parse(r#""string""#).unwrap();
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Deflate);
+        for _ in 0..100 {
+            writer
+                .append(&AvroValue::String("the same string every time".to_string()))
+                .unwrap();
+        }
+        let file = writer.finish().unwrap();
+
+        let stats: Vec<BlockStats> = block_stats(file.as_slice())
+            .unwrap()
+            .collect::<Result<_, Error>>()
+            .unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].object_count, 100);
+        assert_eq!(stats[0].uncompressed_len, 2700);
+        assert!(stats[0].compressed_len < stats[0].uncompressed_len);
+
+        for path in [
+            "test_cases/string.avro",
+            "test_cases/string_deflate.avro",
+            "test_cases/string_snappy.avro",
+        ]
+        .iter()
+        {
+            let file = File::open(path).unwrap();
+            let stats: Vec<BlockStats> = block_stats(BufReader::new(file))
+                .unwrap()
+                .collect::<Result<_, Error>>()
+                .unwrap();
 
-        for (filename, expected_err) in examples.iter() {
             let mut schema_registry = SchemaRegistry::new();
-            let result = AvroDatafile::open(filename, &mut schema_registry);
-            assert!(result.is_err());
-            assert_eq!(result.unwrap_err(), *expected_err);
+            let expected_len: u64 = AvroDatafile::open(path, &mut schema_registry)
+                .unwrap()
+                .map(|value| {
+                    let mut encoded = Vec::new();
+                    match value.unwrap() {
+                        AvroValue::String(value) => encoding::write_string(&mut encoded, &value).unwrap(),
+                        _ => panic!("expected a string"),
+                    }
+                    encoded.len() as u64
+                })
+                .sum();
+            assert_eq!(
+                stats.iter().map(|block| block.uncompressed_len).sum::<u64>(),
+                expected_len,
+                "{}",
+                path
+            );
+        }
+
+        let file = File::open("test_cases/string.avro").unwrap();
+        for block in block_stats(BufReader::new(file)).unwrap() {
+            let block = block.unwrap();
+            assert_eq!(block.compressed_len, block.uncompressed_len);
         }
+
+        let file = File::open("test_cases/string_snappy_bad_crc.avro").unwrap();
+        let results: Vec<_> = block_stats(BufReader::new(file)).unwrap().collect();
+        assert_eq!(results, vec![Err(Error::ChecksumMismatch)]);
     }
 
     #[test]
-    fn deserialize_files_with_deflate_codec() {
-        let expected_values = vec![
-            AvroValue::String("foo".to_string()),
-            AvroValue::String("bar".to_string()),
-            AvroValue::String("foo".to_string()),
-        ];
+    fn summarize_files_without_decoding() {
+        let summary = summarize_file("test_cases/string_deflate.avro").unwrap();
+
+        assert_eq!(summary.root_kind, RootKind::Primitive);
+        assert_eq!(summary.codec, Codec::Deflate);
+        assert_eq!(Ok(summary.records), count_records("test_cases/string_deflate.avro"));
+        assert_eq!(Ok(summary.blocks), count_blocks("test_cases/string_deflate.avro"));
+        assert!(summary.min_block_bytes <= summary.avg_block_bytes());
+        assert!(summary.avg_block_bytes() <= summary.max_block_bytes);
+        assert!(summary
+            .to_string()
+            .contains(&format!("\nrecords: {}\n", summary.records)));
+
+        let summary = summarize_file("test_cases/record.avro").unwrap();
+        assert_eq!(summary.root_kind, RootKind::Record);
+        assert_eq!(summary.codec, Codec::Null);
+
+        assert_eq!(summarize_file("test_cases/non_avro_file"), Err(Error::InvalidFormat));
+    }
+
+    #[test]
+    fn write_values_with_inferred_schema() {
+        let mut first = HashMap::new();
+        first.insert("email", AvroValue::String("bloblaw@example.com".to_string()));
+        first.insert("age", AvroValue::Int(42));
+
+        let mut second = HashMap::new();
+        second.insert("email", AvroValue::String("gmbluth@example.com".to_string()));
+        second.insert("age", AvroValue::Int(16));
+
+        let values = vec![AvroValue::Record(first), AvroValue::Record(second)];
+        let schema = inference::infer_schema(&values[0], "user").unwrap();
+
+        let mut writer = AvroDatafileWriter::new(Vec::new(), &schema, Codec::Null);
+        for value in values.iter() {
+            writer.append(value).unwrap();
+        }
+        let output = writer.finish().unwrap();
 
         let mut schema_registry = SchemaRegistry::new();
-        let datafile = AvroDatafile::open("test_cases/string_deflate.avro", &mut schema_registry).unwrap();
+        let datafile = AvroDatafile::from_reader(output.as_slice(), &mut schema_registry).unwrap();
         let actual_values: Vec<AvroValue> = datafile.collect::<Result<_, Error>>().unwrap();
-        assert_eq!(actual_values, expected_values);
+        assert_eq!(actual_values, values);
     }
 }
@@ -0,0 +1,248 @@
+use crate::schema::{NamedType, Schema, SchemaType};
+use crate::{is_value_of_type, AvroValue, Error};
+use std::fmt::Write;
+
+const INDENT: &str = "  ";
+
+/// Formats a value for people to read, using its schema to name
+/// records and render each type: records and maps are indented with one
+/// entry per line, bytes and fixed values are hex, enums are their bare
+/// symbol, and timestamps are UTC dates. Arrays longer than
+/// `max_items` are cut off with a `... (N more)` line. Returns
+/// `Error::SchemaMismatch` if the value doesn't fit the schema.
+pub(crate) fn pretty_print(value: &AvroValue, schema: &Schema, max_items: usize) -> Result<String, Error> {
+    let mut printer = PrettyPrinter {
+        schema,
+        max_items,
+        output: String::new(),
+    };
+
+    printer.write_value(value, schema.root(), 0)?;
+    Ok(printer.output)
+}
+
+struct PrettyPrinter<'s> {
+    schema: &'s Schema,
+    max_items: usize,
+    output: String,
+}
+
+impl<'s> PrettyPrinter<'s> {
+    fn write_value(&mut self, value: &AvroValue, schema_type: &SchemaType, depth: usize) -> Result<(), Error> {
+        match (value, schema_type) {
+            (value, SchemaType::Union(branches)) => {
+                let branch = branches
+                    .iter()
+                    .find(|branch| is_value_of_type(value, branch, self.schema))
+                    .ok_or(Error::SchemaMismatch)?;
+
+                return self.write_value(value, branch, depth);
+            }
+            (AvroValue::Null, SchemaType::Null) => self.write("null"),
+            (AvroValue::Boolean(value), SchemaType::Boolean) => self.write(&value.to_string()),
+            (AvroValue::Int(value), SchemaType::Int) => self.write(&value.to_string()),
+            (AvroValue::Long(value), SchemaType::Long) => self.write(&value.to_string()),
+            (AvroValue::Float(value), SchemaType::Float) => self.write(&value.to_string()),
+            (AvroValue::Double(value), SchemaType::Double) => self.write(&value.to_string()),
+            (AvroValue::String(value), SchemaType::String) => self.write(&format!("{:?}", value)),
+            (AvroValue::Bytes(value), SchemaType::Bytes) => self.write(&hex(value)),
+            (AvroValue::TimestampMillis(millis), SchemaType::TimestampMillis) => self.write(&format_timestamp(
+                millis.div_euclid(1_000),
+                millis.rem_euclid(1_000) * 1_000_000,
+                3,
+            )),
+            (AvroValue::TimestampMicros(micros), SchemaType::TimestampMicros) => self.write(&format_timestamp(
+                micros.div_euclid(1_000_000),
+                micros.rem_euclid(1_000_000) * 1_000,
+                6,
+            )),
+            (AvroValue::Array(items), SchemaType::Array(item_type)) => {
+                if items.is_empty() {
+                    self.write("[]");
+                    return Ok(());
+                }
+
+                self.write("[\n");
+                for item in items.iter().take(self.max_items) {
+                    self.write_indent(depth + 1);
+                    self.write_value(item, item_type, depth + 1)?;
+                    self.write("\n");
+                }
+
+                if items.len() > self.max_items {
+                    self.write_indent(depth + 1);
+                    self.write(&format!("... ({} more)\n", items.len() - self.max_items));
+                }
+
+                self.write_indent(depth);
+                self.write("]")
+            }
+            (AvroValue::Map(entries), SchemaType::Map(value_type)) => {
+                if entries.is_empty() {
+                    self.write("{}");
+                    return Ok(());
+                }
+
+                let mut keys: Vec<&String> = entries.keys().collect();
+                keys.sort();
+
+                self.write("{\n");
+                for key in keys {
+                    self.write_indent(depth + 1);
+                    self.write(&format!("{:?}: ", key));
+                    self.write_value(&entries[key], value_type, depth + 1)?;
+                    self.write("\n");
+                }
+
+                self.write_indent(depth);
+                self.write("}")
+            }
+            (value, SchemaType::Reference(id)) => match (value, self.schema.resolve_named_type(*id)) {
+                (AvroValue::Fixed(value), NamedType::Fixed(size)) if value.len() == *size => self.write(&hex(value)),
+                (AvroValue::Enum(symbol), NamedType::Enum(_)) => self.write(symbol),
+                (AvroValue::EnumUnknown(index), NamedType::Enum(_)) => {
+                    self.write(&format!("<unknown symbol {}>", index))
+                }
+                (AvroValue::Record(field_values), NamedType::Record(fields)) => {
+                    let schema = self.schema;
+                    self.write(schema.named_type_fullname(*id));
+                    self.write(" {\n");
+
+                    for field in fields {
+                        let value = field_values.get(field.name()).ok_or(Error::SchemaMismatch)?;
+
+                        self.write_indent(depth + 1);
+                        self.write(field.name());
+                        self.write(": ");
+                        self.write_value(value, field.schema_type(), depth + 1)?;
+                        self.write("\n");
+                    }
+
+                    self.write_indent(depth);
+                    self.write("}")
+                }
+                _ => return Err(Error::SchemaMismatch),
+            },
+            _ => return Err(Error::SchemaMismatch),
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    fn write_indent(&mut self, depth: usize) {
+        for _ in 0..depth {
+            self.output.push_str(INDENT);
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(2 + bytes.len() * 2);
+    output.push_str("0x");
+
+    for byte in bytes {
+        write!(output, "{:02x}", byte).unwrap();
+    }
+
+    output
+}
+
+/// Formats seconds and nanoseconds since the Unix epoch as an RFC 3339
+/// UTC date with `precision` fractional digits.
+fn format_timestamp(seconds: i64, nanos: i64, precision: usize) -> String {
+    let days = seconds.div_euclid(86_400);
+    let seconds_of_day = seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    let fraction = format!("{:09}", nanos);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60,
+        seconds_of_day % 60,
+        &fraction[..precision]
+    )
+}
+
+/// Converts days since 1970-01-01 into a proleptic Gregorian date,
+/// using Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn pretty_print_records() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "nickname", "type": ["null", "string"]},
+                 {"name": "suit", "type": {"type": "enum", "name": "suit", "symbols": ["HEARTS"]}},
+                 {"name": "token", "type": "bytes"},
+                 {"name": "created_at", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+                 {"name": "scores", "type": {"type": "array", "items": "int"}}
+               ]}"#,
+        )
+        .unwrap();
+
+        let mut fields = HashMap::new();
+        fields.insert("email", AvroValue::String("a@example.com".to_string()));
+        fields.insert("nickname", AvroValue::Null);
+        fields.insert("suit", AvroValue::Enum("HEARTS"));
+        fields.insert("token", AvroValue::Bytes(vec![0x01, 0xff]));
+        fields.insert("created_at", AvroValue::TimestampMillis(1_600_000_000_123));
+        fields.insert("scores", AvroValue::Array((1..=5).map(AvroValue::Int).collect()));
+
+        let expected = r#"user {
+  email: "a@example.com"
+  nickname: null
+  suit: HEARTS
+  token: 0x01ff
+  created_at: 2020-09-13T12:26:40.123Z
+  scores: [
+    1
+    2
+    3
+    ... (2 more)
+  ]
+}"#;
+
+        assert_eq!(
+            pretty_print(&AvroValue::Record(fields), &schema, 3),
+            Ok(expected.to_string())
+        );
+        assert_eq!(pretty_print(&AvroValue::Int(1), &schema, 3), Err(Error::SchemaMismatch));
+    }
+
+    #[test]
+    fn format_timestamps_before_and_after_epoch() {
+        assert_eq!(format_timestamp(0, 0, 3), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_timestamp(-1, 999_999_000, 6), "1969-12-31T23:59:59.999999Z");
+        assert_eq!(format_timestamp(951_782_400, 0, 3), "2000-02-29T00:00:00.000Z");
+    }
+}
@@ -0,0 +1,1032 @@
+use crate::encoding;
+use crate::schema::{NamedType, NamedTypeId, Schema, SchemaType};
+use crate::{default_value, skip_value, AvroDatafile, AvroValue, Error, ReadOptions, DEFAULT_MAX_DEPTH};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+/// Decodes values written with one schema into the shape of another,
+/// following the schema resolution rules from the specification.
+///
+/// Resolution currently requires the reader and writer root types to
+/// match structurally. A reader schema that projects a different root
+/// (e.g. a single field out of a writer's record) isn't supported and
+/// is rejected up front with `Error::Resolution`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Resolver<'a> {
+    writer: &'a Schema,
+    reader: &'a Schema,
+}
+
+/// Reasons a value written with one schema can't be read with another.
+/// Types are described by their fullname when they're named and by
+/// their kind (e.g. `"long"`, `"array"`) otherwise.
+#[derive(PartialEq, Debug)]
+pub(crate) enum ResolutionError {
+    /// The reader's record has a field the writer's doesn't, and the
+    /// field has no default to fill it in with.
+    MissingField { record: String, field: String },
+
+    /// The writer's type is neither the reader's type nor promotable
+    /// to it.
+    IncompatibleTypes { writer: String, reader: String },
+
+    /// None of the reader's union branches can read the writer's type.
+    NoMatchingBranch { writer: String },
+
+    /// Both schemas declare the fixed type, but with different sizes.
+    FixedSizeMismatch {
+        name: String,
+        writer_size: usize,
+        reader_size: usize,
+    },
+
+    /// The writer's enum symbol doesn't exist in the reader's enum and
+    /// the reader's enum has no default to use instead.
+    UnknownSymbol { enum_name: String, symbol: String },
+}
+
+impl<'a> Resolver<'a> {
+    pub(crate) fn new(writer: &'a Schema, reader: &'a Schema) -> Result<Self, Error> {
+        let resolver = Self { writer, reader };
+        resolver.check(writer.root(), reader.root(), &mut HashSet::new())?;
+
+        Ok(resolver)
+    }
+
+    pub(crate) fn read_value<R: Read>(&self, reader: &mut R, options: &ReadOptions) -> Result<AvroValue<'a>, Error> {
+        self.read_resolved(reader, self.writer.root(), self.reader.root(), options, 0)
+    }
+
+    fn read_resolved<R: Read>(
+        &self,
+        reader: &mut R,
+        writer_type: &'a SchemaType,
+        reader_type: &'a SchemaType,
+        options: &ReadOptions,
+        depth: usize,
+    ) -> Result<AvroValue<'a>, Error> {
+        if depth > options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH) {
+            return Err(Error::LimitExceeded {
+                kind: "depth",
+                len: depth,
+            });
+        }
+
+        match (writer_type, reader_type) {
+            (SchemaType::Union(writer_branches), _) => {
+                let index = encoding::read_len(reader)?;
+                let writer_branch = writer_branches.get(index).ok_or(Error::InvalidFormat)?;
+
+                self.read_resolved(reader, writer_branch, reader_type, options, depth + 1)
+            }
+            (_, SchemaType::Union(reader_branches)) => {
+                let reader_branch = self
+                    .matching_branch(writer_type, reader_branches)
+                    .ok_or_else(|| Error::Resolution(self.no_matching_branch(writer_type)))?;
+
+                self.read_resolved(reader, writer_type, reader_branch, options, depth + 1)
+            }
+            (SchemaType::Array(writer_items), SchemaType::Array(reader_items)) => {
+                let mut values = Vec::new();
+                let mut num_values = encoding::read_block_count(reader)?;
+
+                while num_values > 0 {
+                    for _ in 0..num_values {
+                        values.push(self.read_resolved(reader, writer_items, reader_items, options, depth + 1)?);
+                    }
+
+                    num_values = encoding::read_block_count(reader)?;
+                }
+
+                Ok(AvroValue::Array(values))
+            }
+            (SchemaType::Map(writer_values), SchemaType::Map(reader_values)) => {
+                let mut entries = HashMap::new();
+                let mut num_values = encoding::read_block_count(reader)?;
+
+                while num_values > 0 {
+                    for _ in 0..num_values {
+                        let key = encoding::read_string(reader)?;
+                        let value = self.read_resolved(reader, writer_values, reader_values, options, depth + 1)?;
+
+                        entries.insert(key, value);
+                    }
+
+                    num_values = encoding::read_block_count(reader)?;
+                }
+
+                Ok(AvroValue::Map(entries))
+            }
+            (SchemaType::Reference(writer_id), SchemaType::Reference(reader_id)) => {
+                self.read_named(reader, *writer_id, *reader_id, options, depth)
+            }
+            (SchemaType::Reference(_), _) | (_, SchemaType::Reference(_)) => {
+                Err(Error::Resolution(self.incompatible_types(writer_type, reader_type)))
+            }
+            (writer_type, reader_type) if writer_type == reader_type => {
+                AvroDatafile::read_nested_value(reader, writer_type, self.writer, options, depth)
+            }
+            (writer_type, reader_type)
+                if self.is_same_kind(writer_type, reader_type) || is_promotable(writer_type, reader_type) =>
+            {
+                promote(
+                    AvroDatafile::read_nested_value(reader, writer_type, self.writer, options, depth)?,
+                    reader_type,
+                )
+            }
+            (writer_type, reader_type) => Err(Error::Resolution(self.incompatible_types(writer_type, reader_type))),
+        }
+    }
+
+    fn read_named<R: Read>(
+        &self,
+        reader: &mut R,
+        writer_id: NamedTypeId,
+        reader_id: NamedTypeId,
+        options: &ReadOptions,
+        depth: usize,
+    ) -> Result<AvroValue<'a>, Error> {
+        match (
+            self.writer.resolve_named_type(writer_id),
+            self.reader.resolve_named_type(reader_id),
+        ) {
+            (NamedType::Fixed(writer_size), NamedType::Fixed(reader_size)) if writer_size == reader_size => {
+                AvroDatafile::read_fixed(reader, *writer_size, options)
+            }
+            (NamedType::Enum(writer_symbols), NamedType::Enum(reader_symbols)) => {
+                let symbol = AvroDatafile::read_enum_value(reader, writer_symbols)?;
+
+                let reader_symbol = reader_symbols
+                    .symbol_index(symbol)
+                    .or_else(|| {
+                        reader_symbols
+                            .default()
+                            .and_then(|default| reader_symbols.symbol_index(default))
+                    })
+                    .and_then(|index| reader_symbols.symbol(index));
+
+                match reader_symbol {
+                    Some(reader_symbol) => Ok(AvroValue::Enum(reader_symbol)),
+                    None if options.keep_unknown_enum_symbols => {
+                        let index = writer_symbols.symbol_index(symbol).ok_or(Error::InvalidFormat)?;
+                        Ok(AvroValue::EnumUnknown(index))
+                    }
+                    None => Err(Error::Resolution(ResolutionError::UnknownSymbol {
+                        enum_name: self.reader.named_type_fullname(reader_id).to_string(),
+                        symbol: symbol.to_string(),
+                    })),
+                }
+            }
+            (NamedType::Record(writer_fields), NamedType::Record(reader_fields)) => {
+                let mut field_values = HashMap::with_capacity(reader_fields.len());
+
+                for (index, writer_field) in writer_fields.iter().enumerate() {
+                    match reader_fields.iter().find(|field| field.name() == writer_field.name()) {
+                        Some(reader_field) => {
+                            let value = self
+                                .read_resolved(
+                                    reader,
+                                    writer_field.schema_type(),
+                                    reader_field.schema_type(),
+                                    options,
+                                    depth + 1,
+                                )
+                                .map_err(|e| e.in_field(index, writer_field.name()))?;
+                            field_values.insert(reader_field.name(), value);
+                        }
+                        None => {
+                            // Fields that only exist in the writer's
+                            // schema are skipped without decoding them,
+                            // jumping over any array and map blocks
+                            // that carry their size in bytes.
+                            skip_value(reader, writer_field.schema_type(), self.writer, options, depth + 1)
+                                .map_err(|e| e.in_field(index, writer_field.name()))?;
+                        }
+                    }
+                }
+
+                // Fields that only exist in the reader's schema take
+                // their default values.
+                for reader_field in reader_fields {
+                    if field_values.contains_key(reader_field.name()) {
+                        continue;
+                    }
+
+                    match reader_field.default() {
+                        Some(default) => {
+                            let value = default_value(default, reader_field.schema_type(), self.reader)?;
+                            field_values.insert(reader_field.name(), value);
+                        }
+                        None => {
+                            return Err(Error::Resolution(ResolutionError::MissingField {
+                                record: self.reader.named_type_fullname(reader_id).to_string(),
+                                field: reader_field.name().to_string(),
+                            }))
+                        }
+                    }
+                }
+
+                Ok(AvroValue::Record(field_values))
+            }
+            _ => Err(Error::Resolution(self.incompatible_types(
+                &SchemaType::Reference(writer_id),
+                &SchemaType::Reference(reader_id),
+            ))),
+        }
+    }
+
+    fn matching_branch(&self, writer_type: &SchemaType, reader_branches: &'a [SchemaType]) -> Option<&'a SchemaType> {
+        reader_branches
+            .iter()
+            .find(|reader_branch| self.is_same_kind(writer_type, reader_branch))
+            .or_else(|| {
+                reader_branches
+                    .iter()
+                    .find(|reader_branch| is_promotable(writer_type, reader_branch))
+            })
+    }
+
+    fn is_same_kind(&self, writer_type: &SchemaType, reader_type: &SchemaType) -> bool {
+        match (writer_type, reader_type) {
+            (SchemaType::Array(_), SchemaType::Array(_)) => true,
+            (SchemaType::Map(_), SchemaType::Map(_)) => true,
+            (SchemaType::Reference(writer_id), SchemaType::Reference(reader_id)) => {
+                self.is_same_named_type(*writer_id, *reader_id)
+            }
+            (SchemaType::Reference(_), _) | (_, SchemaType::Reference(_)) => false,
+            (SchemaType::Union(_), _) | (_, SchemaType::Union(_)) => false,
+            (writer_type, reader_type) => writer_type.underlying_type() == reader_type.underlying_type(),
+        }
+    }
+
+    fn is_same_named_type(&self, writer_id: NamedTypeId, reader_id: NamedTypeId) -> bool {
+        let same_kind = matches!(
+            (
+                self.writer.resolve_named_type(writer_id),
+                self.reader.resolve_named_type(reader_id)
+            ),
+            (NamedType::Fixed(_), NamedType::Fixed(_))
+                | (NamedType::Enum(_), NamedType::Enum(_))
+                | (NamedType::Record(_), NamedType::Record(_))
+        );
+
+        // The specification only requires the unqualified names to
+        // match when resolving named types.
+        same_kind
+            && unqualified_name(self.writer.named_type_fullname(writer_id))
+                == unqualified_name(self.reader.named_type_fullname(reader_id))
+    }
+
+    fn check(
+        &self,
+        writer_type: &SchemaType,
+        reader_type: &SchemaType,
+        visited: &mut HashSet<(NamedTypeId, NamedTypeId)>,
+    ) -> Result<(), Error> {
+        match (writer_type, reader_type) {
+            // Unions are resolved per value, so it's enough for one of
+            // the branches to be readable. Values written with any other
+            // branch will fail to resolve when they're decoded.
+            (SchemaType::Union(writer_branches), _) => {
+                if writer_branches
+                    .iter()
+                    .any(|writer_branch| self.check(writer_branch, reader_type, visited).is_ok())
+                {
+                    Ok(())
+                } else {
+                    Err(Error::Resolution(self.incompatible_types(writer_type, reader_type)))
+                }
+            }
+            (_, SchemaType::Union(reader_branches)) => match self.matching_branch(writer_type, reader_branches) {
+                Some(reader_branch) => self.check(writer_type, reader_branch, visited),
+                None => Err(Error::Resolution(self.no_matching_branch(writer_type))),
+            },
+            (SchemaType::Array(writer_items), SchemaType::Array(reader_items)) => {
+                self.check(writer_items, reader_items, visited)
+            }
+            (SchemaType::Map(writer_values), SchemaType::Map(reader_values)) => {
+                self.check(writer_values, reader_values, visited)
+            }
+            (SchemaType::Reference(writer_id), SchemaType::Reference(reader_id)) => {
+                if !self.is_same_named_type(*writer_id, *reader_id) {
+                    return Err(Error::Resolution(self.incompatible_types(writer_type, reader_type)));
+                }
+
+                // Recursive types would otherwise be checked forever.
+                if !visited.insert((*writer_id, *reader_id)) {
+                    return Ok(());
+                }
+
+                match (
+                    self.writer.resolve_named_type(*writer_id),
+                    self.reader.resolve_named_type(*reader_id),
+                ) {
+                    (NamedType::Fixed(writer_size), NamedType::Fixed(reader_size)) if writer_size != reader_size => {
+                        Err(Error::Resolution(ResolutionError::FixedSizeMismatch {
+                            name: self.reader.named_type_fullname(*reader_id).to_string(),
+                            writer_size: *writer_size,
+                            reader_size: *reader_size,
+                        }))
+                    }
+                    (NamedType::Record(writer_fields), NamedType::Record(reader_fields)) => {
+                        for reader_field in reader_fields {
+                            match writer_fields.iter().find(|field| field.name() == reader_field.name()) {
+                                Some(writer_field) => {
+                                    self.check(writer_field.schema_type(), reader_field.schema_type(), visited)?
+                                }
+                                None if reader_field.default().is_some() => {}
+                                None => {
+                                    return Err(Error::Resolution(ResolutionError::MissingField {
+                                        record: self.reader.named_type_fullname(*reader_id).to_string(),
+                                        field: reader_field.name().to_string(),
+                                    }))
+                                }
+                            }
+                        }
+
+                        Ok(())
+                    }
+                    _ => Ok(()),
+                }
+            }
+            (writer_type, reader_type) if self.is_same_kind(writer_type, reader_type) => Ok(()),
+            (writer_type, reader_type) if is_promotable(writer_type, reader_type) => Ok(()),
+            (writer_type, reader_type) => Err(Error::Resolution(self.incompatible_types(writer_type, reader_type))),
+        }
+    }
+
+    fn find_incompatibilities(
+        &self,
+        writer_type: &SchemaType,
+        reader_type: &SchemaType,
+        path: &str,
+        visited: &mut HashSet<(NamedTypeId, NamedTypeId)>,
+        incompatibilities: &mut Vec<Incompatibility>,
+    ) {
+        let mut push = |error| {
+            incompatibilities.push(Incompatibility {
+                path: path.to_string(),
+                error,
+            })
+        };
+
+        match (writer_type, reader_type) {
+            (SchemaType::Union(writer_branches), _) => {
+                for writer_branch in writer_branches {
+                    self.find_incompatibilities(writer_branch, reader_type, path, visited, incompatibilities);
+                }
+            }
+            (_, SchemaType::Union(reader_branches)) => match self.matching_branch(writer_type, reader_branches) {
+                Some(reader_branch) => {
+                    self.find_incompatibilities(writer_type, reader_branch, path, visited, incompatibilities)
+                }
+                None => push(self.no_matching_branch(writer_type)),
+            },
+            (SchemaType::Array(writer_items), SchemaType::Array(reader_items))
+            | (SchemaType::Map(writer_items), SchemaType::Map(reader_items)) => self.find_incompatibilities(
+                writer_items,
+                reader_items,
+                &format!("{}[]", path),
+                visited,
+                incompatibilities,
+            ),
+            (SchemaType::Reference(writer_id), SchemaType::Reference(reader_id)) => {
+                if !self.is_same_named_type(*writer_id, *reader_id) {
+                    return push(self.incompatible_types(writer_type, reader_type));
+                }
+
+                // Recursive types would otherwise be checked forever.
+                if !visited.insert((*writer_id, *reader_id)) {
+                    return;
+                }
+
+                let reader_name = self.reader.named_type_fullname(*reader_id);
+
+                match (
+                    self.writer.resolve_named_type(*writer_id),
+                    self.reader.resolve_named_type(*reader_id),
+                ) {
+                    (NamedType::Fixed(writer_size), NamedType::Fixed(reader_size)) if writer_size != reader_size => {
+                        push(ResolutionError::FixedSizeMismatch {
+                            name: reader_name.to_string(),
+                            writer_size: *writer_size,
+                            reader_size: *reader_size,
+                        })
+                    }
+                    (NamedType::Enum(writer_symbols), NamedType::Enum(reader_symbols))
+                        if reader_symbols.default().is_none() =>
+                    {
+                        for symbol in writer_symbols.symbols() {
+                            if reader_symbols.symbol_index(symbol).is_none() {
+                                push(ResolutionError::UnknownSymbol {
+                                    enum_name: reader_name.to_string(),
+                                    symbol: symbol.clone(),
+                                });
+                            }
+                        }
+                    }
+                    (NamedType::Record(writer_fields), NamedType::Record(reader_fields)) => {
+                        for reader_field in reader_fields {
+                            match writer_fields.iter().find(|field| field.name() == reader_field.name()) {
+                                Some(writer_field) => self.find_incompatibilities(
+                                    writer_field.schema_type(),
+                                    reader_field.schema_type(),
+                                    &format!("{}.{}", path, reader_field.name()),
+                                    visited,
+                                    incompatibilities,
+                                ),
+                                None if reader_field.default().is_some() => {}
+                                None => incompatibilities.push(Incompatibility {
+                                    path: path.to_string(),
+                                    error: ResolutionError::MissingField {
+                                        record: reader_name.to_string(),
+                                        field: reader_field.name().to_string(),
+                                    },
+                                }),
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            (writer_type, reader_type) if self.is_same_kind(writer_type, reader_type) => {}
+            (writer_type, reader_type) if is_promotable(writer_type, reader_type) => {}
+            (writer_type, reader_type) => push(self.incompatible_types(writer_type, reader_type)),
+        }
+    }
+
+    fn incompatible_types(&self, writer_type: &SchemaType, reader_type: &SchemaType) -> ResolutionError {
+        ResolutionError::IncompatibleTypes {
+            writer: type_name(self.writer, writer_type),
+            reader: type_name(self.reader, reader_type),
+        }
+    }
+
+    fn no_matching_branch(&self, writer_type: &SchemaType) -> ResolutionError {
+        ResolutionError::NoMatchingBranch {
+            writer: type_name(self.writer, writer_type),
+        }
+    }
+}
+
+/// Which versions of a schema have to be able to read each other's
+/// data, following the modes schema registries use when accepting a
+/// new version.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Compatibility {
+    /// The reader schema can read data written with the writer schema.
+    Backward,
+    /// The writer schema can read data written with the reader schema.
+    Forward,
+    /// Both `Backward` and `Forward`.
+    Full,
+}
+
+/// A reason one schema can't read data written with another, along
+/// with the path to the type it applies to, e.g. `user.age`.
+#[derive(PartialEq, Debug)]
+pub(crate) struct Incompatibility {
+    pub(crate) path: String,
+    pub(crate) error: ResolutionError,
+}
+
+/// Checks whether `reader` and `writer` can read each other's data in
+/// the direction(s) `mode` requires, listing every incompatibility
+/// found. Unlike `Resolver::new`, this follows the full rules from the
+/// specification: every branch of a writer's union has to be
+/// readable, and reader fields and enums with defaults can cope with
+/// data that lacks them.
+pub(crate) fn check_compatibility(
+    reader: &Schema,
+    writer: &Schema,
+    mode: Compatibility,
+) -> Result<(), Vec<Incompatibility>> {
+    let mut incompatibilities = Vec::new();
+
+    if mode != Compatibility::Forward {
+        Resolver { writer, reader }.find_incompatibilities(
+            writer.root(),
+            reader.root(),
+            &type_name(reader, reader.root()),
+            &mut HashSet::new(),
+            &mut incompatibilities,
+        );
+    }
+
+    if mode != Compatibility::Backward {
+        Resolver {
+            writer: reader,
+            reader: writer,
+        }
+        .find_incompatibilities(
+            reader.root(),
+            writer.root(),
+            &type_name(writer, writer.root()),
+            &mut HashSet::new(),
+            &mut incompatibilities,
+        );
+    }
+
+    if incompatibilities.is_empty() {
+        Ok(())
+    } else {
+        Err(incompatibilities)
+    }
+}
+
+/// Describes a type by its fullname if it's named, or by its kind
+/// (e.g. `"long"`, `"array"`) otherwise.
+pub(crate) fn type_name(schema: &Schema, schema_type: &SchemaType) -> String {
+    match schema_type {
+        SchemaType::Reference(id) => schema.named_type_fullname(*id).to_string(),
+        schema_type => schema_type.kind_name().to_string(),
+    }
+}
+
+fn unqualified_name(fullname: &str) -> &str {
+    match fullname.rfind('.') {
+        Some(position) => &fullname[position + 1..],
+        None => fullname,
+    }
+}
+
+fn is_promotable(writer_type: &SchemaType, reader_type: &SchemaType) -> bool {
+    matches!(
+        (writer_type.underlying_type(), reader_type.underlying_type()),
+        (SchemaType::Int, SchemaType::Long)
+            | (SchemaType::Int, SchemaType::Float)
+            | (SchemaType::Int, SchemaType::Double)
+            | (SchemaType::Long, SchemaType::Float)
+            | (SchemaType::Long, SchemaType::Double)
+            | (SchemaType::Float, SchemaType::Double)
+            | (SchemaType::String, SchemaType::Bytes)
+            | (SchemaType::Bytes, SchemaType::String)
+    )
+}
+
+/// Converts a value decoded with the writer's type into the reader's
+/// type, following the promotion rules from the specification:
+///
+/// - int is promotable to long, float, or double
+/// - long is promotable to float or double
+/// - float is promotable to double
+/// - string is promotable to bytes
+/// - bytes is promotable to string
+///
+/// Logical types resolve as their underlying type, so e.g. a long and
+/// a timestamp-millis read as each other, and an int promotes to a
+/// timestamp-millis just as it would to a long.
+pub(crate) fn promote<'a>(value: AvroValue<'a>, reader_type: &SchemaType) -> Result<AvroValue<'a>, Error> {
+    let writer_type_name = value.type_name();
+    let value = match value {
+        AvroValue::TimestampMillis(value) | AvroValue::TimestampMicros(value) => AvroValue::Long(value),
+        value => value,
+    };
+
+    let promoted = match (value, reader_type.underlying_type()) {
+        (AvroValue::Int(value), SchemaType::Long) => AvroValue::Long(value as i64),
+        (AvroValue::Int(value), SchemaType::Float) => AvroValue::Float(value as f32),
+        (AvroValue::Int(value), SchemaType::Double) => AvroValue::Double(value as f64),
+        (AvroValue::Long(value), SchemaType::Float) => AvroValue::Float(value as f32),
+        (AvroValue::Long(value), SchemaType::Double) => AvroValue::Double(value as f64),
+        (AvroValue::Float(value), SchemaType::Double) => AvroValue::Double(value as f64),
+        (AvroValue::String(value), SchemaType::Bytes) => AvroValue::Bytes(value.into_bytes()),
+        (AvroValue::Bytes(value), SchemaType::String) => {
+            AvroValue::String(String::from_utf8(value).map_err(|_| Error::BadEncoding)?)
+        }
+        (value, underlying_type) if is_primitive_of_type(&value, underlying_type) => value,
+        _ => {
+            return Err(Error::Resolution(ResolutionError::IncompatibleTypes {
+                writer: writer_type_name.to_string(),
+                reader: reader_type.kind_name().to_string(),
+            }))
+        }
+    };
+
+    Ok(match (promoted, reader_type) {
+        (AvroValue::Long(value), SchemaType::TimestampMillis) => AvroValue::TimestampMillis(value),
+        (AvroValue::Long(value), SchemaType::TimestampMicros) => AvroValue::TimestampMicros(value),
+        (value, _) => value,
+    })
+}
+
+fn is_primitive_of_type(value: &AvroValue, schema_type: &SchemaType) -> bool {
+    matches!(
+        (value, schema_type),
+        (AvroValue::Null, SchemaType::Null)
+            | (AvroValue::Boolean(_), SchemaType::Boolean)
+            | (AvroValue::Int(_), SchemaType::Int)
+            | (AvroValue::Long(_), SchemaType::Long)
+            | (AvroValue::Float(_), SchemaType::Float)
+            | (AvroValue::Double(_), SchemaType::Double)
+            | (AvroValue::Bytes(_), SchemaType::Bytes)
+            | (AvroValue::String(_), SchemaType::String)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn promote_values() {
+        let examples = vec![
+            (AvroValue::Int(42), SchemaType::Long, Ok(AvroValue::Long(42))),
+            (AvroValue::Int(42), SchemaType::Float, Ok(AvroValue::Float(42.0))),
+            (AvroValue::Int(42), SchemaType::Double, Ok(AvroValue::Double(42.0))),
+            (AvroValue::Long(-7), SchemaType::Float, Ok(AvroValue::Float(-7.0))),
+            (AvroValue::Long(-7), SchemaType::Double, Ok(AvroValue::Double(-7.0))),
+            (AvroValue::Float(1.5), SchemaType::Double, Ok(AvroValue::Double(1.5))),
+            (
+                AvroValue::String("foo".to_string()),
+                SchemaType::Bytes,
+                Ok(AvroValue::Bytes(vec![0x66, 0x6f, 0x6f])),
+            ),
+            (
+                AvroValue::Bytes(vec![0x66, 0x6f, 0x6f]),
+                SchemaType::String,
+                Ok(AvroValue::String("foo".to_string())),
+            ),
+            (AvroValue::Int(42), SchemaType::Int, Ok(AvroValue::Int(42))),
+            (
+                AvroValue::Long(42),
+                SchemaType::Int,
+                Err(incompatible_types("long", "int")),
+            ),
+            (
+                AvroValue::Double(1.5),
+                SchemaType::Float,
+                Err(incompatible_types("double", "float")),
+            ),
+            (
+                AvroValue::Boolean(true),
+                SchemaType::String,
+                Err(incompatible_types("boolean", "string")),
+            ),
+            (
+                AvroValue::Long(42),
+                SchemaType::TimestampMillis,
+                Ok(AvroValue::TimestampMillis(42)),
+            ),
+            (
+                AvroValue::TimestampMicros(42),
+                SchemaType::Long,
+                Ok(AvroValue::Long(42)),
+            ),
+            (
+                AvroValue::Int(42),
+                SchemaType::TimestampMillis,
+                Ok(AvroValue::TimestampMillis(42)),
+            ),
+            (
+                AvroValue::TimestampMillis(42),
+                SchemaType::Double,
+                Ok(AvroValue::Double(42.0)),
+            ),
+            (
+                AvroValue::TimestampMillis(42),
+                SchemaType::Int,
+                Err(incompatible_types("timestamp-millis", "int")),
+            ),
+        ];
+
+        for (value, reader_type, expected) in examples.into_iter() {
+            let actual = promote(value, &reader_type);
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn reject_incompatible_schemas() {
+        let writer = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "age", "type": "int"}
+               ]}"#,
+        )
+        .unwrap();
+
+        let examples = [
+            (r#""long""#, incompatible_types("user", "long")),
+            (
+                r#"{"type": "array", "items": "int"}"#,
+                incompatible_types("user", "array"),
+            ),
+            (
+                r#"{"type": "record", "name": "account", "fields": [{"name": "age", "type": "int"}]}"#,
+                incompatible_types("user", "account"),
+            ),
+            (
+                r#"{"type": "record", "name": "user", "fields": [{"name": "age", "type": "string"}]}"#,
+                incompatible_types("int", "string"),
+            ),
+            (
+                r#"{"type": "record", "name": "user", "fields": [{"name": "country", "type": "string"}]}"#,
+                Error::Resolution(ResolutionError::MissingField {
+                    record: "user".to_string(),
+                    field: "country".to_string(),
+                }),
+            ),
+            (
+                r#"{"type": "record", "name": "user", "fields": [{"name": "age", "type": ["null", "string"]}]}"#,
+                Error::Resolution(ResolutionError::NoMatchingBranch {
+                    writer: "int".to_string(),
+                }),
+            ),
+        ];
+
+        for (reader_str, expected) in examples.iter() {
+            let reader = Schema::parse(reader_str).unwrap();
+            assert_eq!(&Resolver::new(&writer, &reader).unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn resolve_logical_types_by_their_underlying_type() {
+        let long = Schema::parse(r#""long""#).unwrap();
+        let timestamp = Schema::parse(r#"{"type": "long", "logicalType": "timestamp-millis"}"#).unwrap();
+        let input = vec![0x54];
+
+        let resolver = Resolver::new(&long, &timestamp).unwrap();
+        assert_eq!(
+            resolver.read_value(&mut input.as_slice(), &ReadOptions::new()),
+            Ok(AvroValue::TimestampMillis(42))
+        );
+
+        let resolver = Resolver::new(&timestamp, &long).unwrap();
+        assert_eq!(
+            resolver.read_value(&mut input.as_slice(), &ReadOptions::new()),
+            Ok(AvroValue::Long(42))
+        );
+
+        let int = Schema::parse(r#""int""#).unwrap();
+        let resolver = Resolver::new(&int, &timestamp).unwrap();
+        assert_eq!(
+            resolver.read_value(&mut input.as_slice(), &ReadOptions::new()),
+            Ok(AvroValue::TimestampMillis(42))
+        );
+
+        let string = Schema::parse(r#""string""#).unwrap();
+        assert_eq!(
+            Resolver::new(&string, &timestamp).unwrap_err(),
+            incompatible_types("string", "timestamp-millis")
+        );
+    }
+
+    #[test]
+    fn reject_mismatched_fixed_sizes() {
+        let writer = Schema::parse(r#"{"type": "fixed", "name": "md5", "size": 16}"#).unwrap();
+        let reader = Schema::parse(r#"{"type": "fixed", "name": "md5", "size": 32}"#).unwrap();
+
+        assert_eq!(
+            Resolver::new(&writer, &reader).unwrap_err(),
+            Error::Resolution(ResolutionError::FixedSizeMismatch {
+                name: "md5".to_string(),
+                writer_size: 16,
+                reader_size: 32,
+            })
+        );
+    }
+
+    #[test]
+    fn reject_enum_symbols_missing_from_reader() {
+        let writer = Schema::parse(r#"{"type": "enum", "name": "suit", "symbols": ["HEARTS", "SPADES"]}"#).unwrap();
+        let reader = Schema::parse(r#"{"type": "enum", "name": "suit", "symbols": ["HEARTS"]}"#).unwrap();
+        let resolver = Resolver::new(&writer, &reader).unwrap();
+
+        let input = vec![0x00];
+        assert_eq!(
+            resolver.read_value(&mut input.as_slice(), &ReadOptions::new()),
+            Ok(AvroValue::Enum("HEARTS"))
+        );
+
+        let input = vec![0x02];
+        assert_eq!(
+            resolver.read_value(&mut input.as_slice(), &ReadOptions::new()),
+            Err(Error::Resolution(ResolutionError::UnknownSymbol {
+                enum_name: "suit".to_string(),
+                symbol: "SPADES".to_string(),
+            }))
+        );
+    }
+
+    #[test]
+    fn skip_fields_missing_from_reader_by_block_size() {
+        let writer = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "tags", "type": {"type": "array", "items": "string"}},
+                 {"name": "id", "type": "int"}
+               ]}"#,
+        )
+        .unwrap();
+        let reader =
+            Schema::parse(r#"{"type": "record", "name": "user", "fields": [{"name": "id", "type": "int"}]}"#).unwrap();
+        let resolver = Resolver::new(&writer, &reader).unwrap();
+
+        let examples = [
+            // A block of one item written with a negative count and its
+            // size in bytes. The body isn't a valid string, so it can
+            // only be read past by jumping over it.
+            vec![0x01, 0x04, 0x7f, 0x00, 0x00, 0x54],
+            // Blocks with a positive count have no size, so their items
+            // are skipped one at a time.
+            vec![0x02, 0x06, 0x66, 0x6f, 0x6f, 0x00, 0x54],
+        ];
+
+        for input in examples.iter() {
+            let mut expected = HashMap::new();
+            expected.insert("id", AvroValue::Int(42));
+
+            assert_eq!(
+                resolver.read_value(&mut input.as_slice(), &ReadOptions::new()),
+                Ok(AvroValue::Record(expected))
+            );
+        }
+    }
+
+    #[test]
+    fn resolve_unknown_enum_symbols_leniently() {
+        let writer = Schema::parse(r#"{"type": "enum", "name": "suit", "symbols": ["HEARTS", "SPADES"]}"#).unwrap();
+        let reader = Schema::parse(r#"{"type": "enum", "name": "suit", "symbols": ["HEARTS"]}"#).unwrap();
+        let resolver = Resolver::new(&writer, &reader).unwrap();
+        let options = ReadOptions::new().keep_unknown_enum_symbols(true);
+
+        let input = vec![0x00];
+        assert_eq!(
+            resolver.read_value(&mut input.as_slice(), &options),
+            Ok(AvroValue::Enum("HEARTS"))
+        );
+
+        let input = vec![0x02];
+        assert_eq!(
+            resolver.read_value(&mut input.as_slice(), &options),
+            Ok(AvroValue::EnumUnknown(1))
+        );
+
+        let reader =
+            Schema::parse(r#"{"type": "enum", "name": "suit", "symbols": ["HEARTS", "CLUBS"], "default": "CLUBS"}"#)
+                .unwrap();
+        let resolver = Resolver::new(&writer, &reader).unwrap();
+
+        for options in &[ReadOptions::new(), options] {
+            assert_eq!(
+                resolver.read_value(&mut input.as_slice(), options),
+                Ok(AvroValue::Enum("CLUBS"))
+            );
+        }
+    }
+
+    #[test]
+    fn check_compatibility_of_added_and_removed_fields() {
+        let old = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "age", "type": "int"}
+               ]}"#,
+        )
+        .unwrap();
+
+        let with_defaulted_field = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "age", "type": "long"},
+                 {"name": "nickname", "type": ["null", "string"], "default": null}
+               ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            check_compatibility(&with_defaulted_field, &old, Compatibility::Backward),
+            Ok(())
+        );
+
+        let without_age = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"}
+               ]}"#,
+        )
+        .unwrap();
+
+        let missing_age = Incompatibility {
+            path: "user".to_string(),
+            error: ResolutionError::MissingField {
+                record: "user".to_string(),
+                field: "age".to_string(),
+            },
+        };
+
+        assert_eq!(check_compatibility(&without_age, &old, Compatibility::Backward), Ok(()));
+        assert_eq!(
+            check_compatibility(&without_age, &old, Compatibility::Forward),
+            Err(vec![missing_age])
+        );
+
+        // A long can't be read as an int, so the added field's default
+        // doesn't make the change fully compatible.
+        assert_eq!(
+            check_compatibility(&with_defaulted_field, &old, Compatibility::Full),
+            Err(vec![Incompatibility {
+                path: "user.age".to_string(),
+                error: ResolutionError::IncompatibleTypes {
+                    writer: "long".to_string(),
+                    reader: "int".to_string(),
+                },
+            }])
+        );
+    }
+
+    #[test]
+    fn check_compatibility_of_enums_and_unions() {
+        let old = Schema::parse(
+            r#"{"type": "record", "name": "card", "fields": [
+                 {"name": "suit", "type": {"type": "enum", "name": "suit", "symbols": ["HEARTS", "SPADES"]}},
+                 {"name": "value", "type": ["int", "string"]}
+               ]}"#,
+        )
+        .unwrap();
+
+        let new = Schema::parse(
+            r#"{"type": "record", "name": "card", "fields": [
+                 {"name": "suit", "type": {"type": "enum", "name": "suit", "symbols": ["HEARTS"]}},
+                 {"name": "value", "type": ["null", "long"]}
+               ]}"#,
+        )
+        .unwrap();
+
+        let expected = vec![
+            Incompatibility {
+                path: "card.suit".to_string(),
+                error: ResolutionError::UnknownSymbol {
+                    enum_name: "suit".to_string(),
+                    symbol: "SPADES".to_string(),
+                },
+            },
+            Incompatibility {
+                path: "card.value".to_string(),
+                error: ResolutionError::NoMatchingBranch {
+                    writer: "string".to_string(),
+                },
+            },
+        ];
+
+        assert_eq!(check_compatibility(&new, &old, Compatibility::Backward), Err(expected));
+
+        let with_enum_default = Schema::parse(
+            r#"{"type": "record", "name": "card", "fields": [
+                 {"name": "suit", "type": {"type": "enum", "name": "suit", "symbols": ["HEARTS"], "default": "HEARTS"}},
+                 {"name": "value", "type": ["string", "long"]}
+               ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            check_compatibility(&with_enum_default, &old, Compatibility::Backward),
+            Ok(())
+        );
+    }
+
+    fn incompatible_types(writer: &str, reader: &str) -> Error {
+        Error::Resolution(ResolutionError::IncompatibleTypes {
+            writer: writer.to_string(),
+            reader: reader.to_string(),
+        })
+    }
+
+    #[test]
+    fn resolve_values_into_reader_schema() {
+        let writer = Schema::parse(
+            r#"{"type": "record", "name": "com.example.user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "age", "type": "int"},
+                 {"name": "tags", "type": {"type": "array", "items": "string"}},
+                 {"name": "nickname", "type": ["null", "string"]}
+               ]}"#,
+        )
+        .unwrap();
+
+        let reader = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "nickname", "type": ["null", "bytes"]},
+                 {"name": "age", "type": "double"}
+               ]}"#,
+        )
+        .unwrap();
+
+        #[rustfmt::skip]
+        let input = vec![
+            0x06, 0x66, 0x6f, 0x6f, // email: "foo"
+            0x54, // age: 42
+            0x02, 0x02, 0x78, 0x00, // tags: ["x"]
+            0x02, 0x04, 0x68, 0x69, // nickname: branch 1, "hi"
+        ];
+
+        let resolver = Resolver::new(&writer, &reader).unwrap();
+        let actual = resolver.read_value(&mut input.as_slice(), &ReadOptions::new()).unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert("nickname", AvroValue::Bytes(vec![0x68, 0x69]));
+        expected.insert("age", AvroValue::Double(42.0));
+
+        assert_eq!(actual, AvroValue::Record(expected));
+    }
+}
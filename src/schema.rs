@@ -1,23 +1,29 @@
 #![allow(dead_code)]
 
 use serde_json::{Map, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
 
 // TODO: more descriptive errors
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
-    UnrecognizedType,
+    /// A type name that isn't a primitive or a named type defined
+    /// before it. Holds the fullname that was looked up.
+    UnrecognizedType(String),
     InvalidType,
     InvalidSchema,
+    InvalidDefault,
 }
 
-type NamedTypeId = usize;
+pub(crate) type NamedTypeId = usize;
 
 #[derive(Debug)]
 pub(crate) struct Schema {
     root: SchemaType,
     name_registry: NameRegistry,
+    json: Value,
 }
 
 impl Schema {
@@ -26,16 +32,586 @@ impl Schema {
         let mut name_registry = NameRegistry::new();
         let root = SchemaType::parse(&json, &mut name_registry, None)?;
 
-        Ok(Self { root, name_registry })
+        if let Some(name) = name_registry.undefined_name() {
+            return Err(Error::UnrecognizedType(name.fullname().to_string()));
+        }
+
+        let schema = Self {
+            root,
+            name_registry,
+            json,
+        };
+
+        schema.validate_defaults()?;
+        Ok(schema)
+    }
+
+    /// Builds a schema whose root doesn't define any named types, such
+    /// as `SchemaType::Int` or an array of strings, without going
+    /// through JSON. Returns `Error::InvalidSchema` if the type refers
+    /// to a named type, since there's nothing for it to refer to.
+    pub(crate) fn primitive(root: SchemaType) -> Result<Self, Error> {
+        Self::from_parts(root, NameRegistry::new())
+    }
+
+    /// Builds a record schema with the given fields and no defaults.
+    /// A field can refer back to the record itself with
+    /// `SchemaType::Reference(0)`, but not to any other named type.
+    pub(crate) fn record(name: &str, fields: Vec<(&str, SchemaType)>) -> Result<Self, Error> {
+        let fullname = Fullname::from_name(name);
+        if is_primitive_name(fullname.name()) {
+            return Err(Error::InvalidType);
+        }
+
+        let mut name_registry = NameRegistry::new();
+        let id = name_registry.reserve_name(&fullname);
+
+        let fields = fields
+            .into_iter()
+            .map(|(name, schema_type)| Field {
+                name: name.to_string(),
+                schema_type,
+                default: None,
+            })
+            .collect();
+
+        name_registry.complete_reservation(id, NamedType::Record(fields));
+        Self::from_parts(SchemaType::Reference(id), name_registry)
+    }
+
+    /// Assembles a schema from types that were built directly, deriving
+    /// its JSON from the canonical form with logical types kept.
+    fn from_parts(root: SchemaType, name_registry: NameRegistry) -> Result<Self, Error> {
+        let mut schema = Self {
+            root,
+            name_registry,
+            json: Value::Null,
+        };
+        schema.validate()?;
+
+        let mut json = String::new();
+        schema.write_canonical_type(&schema.root, true, &mut HashSet::new(), &mut json);
+        schema.json = serde_json::from_str(&json).map_err(|_| Error::InvalidSchema)?;
+        Ok(schema)
+    }
+
+    /// Checks that every named type has a definition and that every
+    /// reference points at one. A reserved but empty slot means a name
+    /// was never defined or a definition was never completed, which
+    /// `parse` shouldn't allow, so this is mostly a sanity check.
+    /// Returns `Error::InvalidSchema` if anything is missing.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let definitions = &self.name_registry.type_definitions;
+        if definitions.iter().any(Option::is_none) {
+            return Err(Error::InvalidSchema);
+        }
+
+        let is_defined = |id| self.name_registry.get(id).is_some();
+        let mut fields = definitions.iter().flatten().flat_map(|definition| match definition {
+            NamedType::Record(fields) => fields.as_slice(),
+            _ => &[],
+        });
+
+        let references_are_defined = self.root.references_only(&is_defined)
+            && fields.all(|field| field.schema_type().references_only(&is_defined));
+
+        if references_are_defined {
+            Ok(())
+        } else {
+            Err(Error::InvalidSchema)
+        }
+    }
+
+    /// Checks that every field's default is a value of the field's
+    /// type. For unions, that means a value of the first branch.
+    fn validate_defaults(&self) -> Result<(), Error> {
+        for definition in self.name_registry.type_definitions.iter().flatten() {
+            if let NamedType::Record(fields) = definition {
+                for field in fields {
+                    if let Some(default) = field.default() {
+                        if !self.is_valid_default(default, field.schema_type()) {
+                            return Err(Error::InvalidDefault);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A record default can leave out fields that have defaults of
+    /// their own. Those defaults are checked on their own, so they
+    /// aren't expanded here.
+    fn is_valid_default(&self, default: &Value, schema_type: &SchemaType) -> bool {
+        match (schema_type, default) {
+            (SchemaType::Null, Value::Null) => true,
+            (SchemaType::Boolean, Value::Bool(_)) => true,
+            (SchemaType::Int, Value::Number(value)) => value.as_i64().is_some_and(|value| i32::try_from(value).is_ok()),
+            (SchemaType::Long, Value::Number(value))
+            | (SchemaType::TimestampMillis, Value::Number(value))
+            | (SchemaType::TimestampMicros, Value::Number(value)) => value.is_i64(),
+            (SchemaType::Float, Value::Number(_)) | (SchemaType::Double, Value::Number(_)) => true,
+            (SchemaType::Bytes, Value::String(value)) => default_bytes(value).is_some(),
+            (SchemaType::String, Value::String(_)) => true,
+            (SchemaType::Array(item_type), Value::Array(items)) => {
+                items.iter().all(|item| self.is_valid_default(item, item_type))
+            }
+            (SchemaType::Map(value_type), Value::Object(entries)) => {
+                entries.values().all(|value| self.is_valid_default(value, value_type))
+            }
+            (SchemaType::Union(branches), default) => branches
+                .first()
+                .is_some_and(|first_branch| self.is_valid_default(default, first_branch)),
+            (SchemaType::Reference(id), default) => match (self.resolve_named_type(*id), default) {
+                (NamedType::Fixed(size), Value::String(value)) => {
+                    default_bytes(value).is_some_and(|bytes| bytes.len() == *size)
+                }
+                (NamedType::Enum(symbols), Value::String(value)) => symbols.symbol_index(value).is_some(),
+                (NamedType::Record(fields), Value::Object(field_defaults)) => {
+                    fields.iter().all(|field| match field_defaults.get(field.name()) {
+                        Some(field_default) => self.is_valid_default(field_default, field.schema_type()),
+                        None => field.default().is_some(),
+                    })
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Serializes the schema back to JSON, keeping any attributes from
+    /// the original definition such as docs and aliases.
+    pub(crate) fn to_json(&self) -> String {
+        self.json.to_string()
+    }
+
+    /// Serializes the schema to JSON with the object keys in a stable
+    /// order: `type`, `name`, `namespace`, and `fields` come first,
+    /// followed by the remaining keys alphabetically. Unlike the
+    /// canonical form, no attributes are removed.
+    pub(crate) fn to_json_sorted(&self) -> String {
+        let mut output = String::new();
+        write_sorted_json(&self.json, &mut output);
+        output
+    }
+
+    /// Serializes the schema in the specification's Parsing Canonical
+    /// Form: names are fully qualified, attributes that don't affect
+    /// the encoding (docs, aliases, defaults, logical types) are
+    /// dropped, and there's no whitespace. Schemas that describe the
+    /// same encoding produce the same string.
+    pub(crate) fn canonical_form(&self) -> String {
+        let mut output = String::new();
+        self.write_canonical_type(&self.root, false, &mut HashSet::new(), &mut output);
+        output
+    }
+
+    /// A key for caching anything derived from how values of this schema
+    /// are decoded. Schemas with the same key decode identically. It's
+    /// the CRC-64-AVRO fingerprint of the canonical form, except that
+    /// logical types the decoder understands are kept, since they
+    /// change the values it produces. Field order is already part of
+    /// the canonical form.
+    pub(crate) fn decode_plan_key(&self) -> u64 {
+        let mut output = String::new();
+        self.write_canonical_type(&self.root, true, &mut HashSet::new(), &mut output);
+        rabin_fingerprint(output.as_bytes())
+    }
+
+    fn write_canonical_type(
+        &self,
+        schema_type: &SchemaType,
+        keep_logical_types: bool,
+        written: &mut HashSet<NamedTypeId>,
+        output: &mut String,
+    ) {
+        match schema_type {
+            SchemaType::TimestampMillis if keep_logical_types => {
+                output.push_str(r#"{"type":"long","logicalType":"timestamp-millis"}"#)
+            }
+            SchemaType::TimestampMicros if keep_logical_types => {
+                output.push_str(r#"{"type":"long","logicalType":"timestamp-micros"}"#)
+            }
+            SchemaType::TimestampMillis | SchemaType::TimestampMicros => output.push_str(r#""long""#),
+            SchemaType::Array(item_type) => {
+                output.push_str(r#"{"type":"array","items":"#);
+                self.write_canonical_type(item_type, keep_logical_types, written, output);
+                output.push('}');
+            }
+            SchemaType::Map(value_type) => {
+                output.push_str(r#"{"type":"map","values":"#);
+                self.write_canonical_type(value_type, keep_logical_types, written, output);
+                output.push('}');
+            }
+            SchemaType::Union(branches) => {
+                output.push('[');
+                for (index, branch) in branches.iter().enumerate() {
+                    if index > 0 {
+                        output.push(',');
+                    }
+
+                    self.write_canonical_type(branch, keep_logical_types, written, output);
+                }
+                output.push(']');
+            }
+            SchemaType::Reference(id) => {
+                let fullname = Value::String(self.named_type_fullname(*id).to_string()).to_string();
+
+                // Named types are only defined the first time they
+                // appear, and referenced by name after that.
+                if !written.insert(*id) {
+                    output.push_str(&fullname);
+                    return;
+                }
+
+                match self.resolve_named_type(*id) {
+                    NamedType::Record(fields) => {
+                        output.push_str(&format!(r#"{{"name":{},"type":"record","fields":["#, fullname));
+                        for (index, field) in fields.iter().enumerate() {
+                            if index > 0 {
+                                output.push(',');
+                            }
+
+                            let field_name = Value::String(field.name().to_string()).to_string();
+                            output.push_str(&format!(r#"{{"name":{},"type":"#, field_name));
+                            self.write_canonical_type(field.schema_type(), keep_logical_types, written, output);
+                            output.push('}');
+                        }
+                        output.push_str("]}");
+                    }
+                    NamedType::Enum(symbols) => {
+                        let symbols = Value::from(symbols.symbols().to_vec()).to_string();
+                        output.push_str(&format!(
+                            r#"{{"name":{},"type":"enum","symbols":{}}}"#,
+                            fullname, symbols
+                        ));
+                    }
+                    NamedType::Fixed(size) => {
+                        output.push_str(&format!(r#"{{"name":{},"type":"fixed","size":{}}}"#, fullname, size));
+                    }
+                }
+            }
+            primitive => output.push_str(&format!(r#""{}""#, primitive.kind_name())),
+        }
+    }
+
+    /// Builds a copy of a record schema that only has the named fields,
+    /// keeping them in their original order. Returns
+    /// `Error::InvalidSchema` if the root isn't a record or doesn't
+    /// have one of the fields.
+    pub(crate) fn project(&self, field_names: &[&str]) -> Result<Schema, Error> {
+        if self.root_kind() != RootKind::Record {
+            return Err(Error::InvalidSchema);
+        }
+
+        let mut json = self.json.clone();
+        let fields = match json.get_mut("fields") {
+            Some(Value::Array(fields)) => fields,
+            _ => return Err(Error::InvalidSchema),
+        };
+
+        let is_named = |field: &Value, name: &str| field.get("name").and_then(Value::as_str) == Some(name);
+
+        for name in field_names {
+            if !fields.iter().any(|field| is_named(field, name)) {
+                return Err(Error::InvalidSchema);
+            }
+        }
+
+        fields.retain(|field| field_names.iter().any(|name| is_named(field, name)));
+        Schema::parse(&json.to_string())
     }
 
     pub(crate) fn root(&self) -> &SchemaType {
         &self.root
     }
 
+    /// The kind of the root type, for callers that only need to branch
+    /// on the shape of the data rather than match the full type.
+    pub(crate) fn root_kind(&self) -> RootKind {
+        match &self.root {
+            SchemaType::Array(_) => RootKind::Array,
+            SchemaType::Map(_) => RootKind::Map,
+            SchemaType::Union(_) => RootKind::Union,
+            SchemaType::Reference(id) => match self.resolve_named_type(*id) {
+                NamedType::Record(_) => RootKind::Record,
+                NamedType::Enum(_) => RootKind::Enum,
+                NamedType::Fixed(_) => RootKind::Fixed,
+            },
+            _ => RootKind::Primitive,
+        }
+    }
+
+    /// Every `logicalType` annotation in the schema, including ones the
+    /// parser doesn't support and decodes as the underlying type.
+    pub(crate) fn logical_types(&self) -> HashSet<&str> {
+        let mut logical_types = HashSet::new();
+        collect_logical_types(&self.json, &mut logical_types);
+        logical_types
+    }
+
+    /// The names of the root record's fields that have no default, so
+    /// every value must include them. Empty if the root isn't a record.
+    pub(crate) fn required_fields(&self) -> Vec<&str> {
+        match &self.root {
+            SchemaType::Reference(id) => match self.resolve_named_type(*id) {
+                NamedType::Record(fields) => fields
+                    .iter()
+                    .filter(|field| field.default().is_none())
+                    .map(|field| field.name())
+                    .collect(),
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    /// The type of one of the root record's fields, or `None` if the
+    /// root isn't a record or has no field with that name.
+    pub(crate) fn field_type(&self, field_name: &str) -> Option<&SchemaType> {
+        match &self.root {
+            SchemaType::Reference(id) => match self.resolve_named_type(*id) {
+                NamedType::Record(fields) => fields
+                    .iter()
+                    .find(|field| field.name() == field_name)
+                    .map(|field| field.schema_type()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     pub(crate) fn resolve_named_type(&self, id: NamedTypeId) -> &NamedType {
         self.name_registry.type_definitions[id].as_ref().unwrap()
     }
+
+    pub(crate) fn named_type_fullname(&self, id: NamedTypeId) -> &str {
+        self.name_registry.name_of(id).fullname()
+    }
+
+    /// The symbols of the enum named `fullname`, in declaration order.
+    /// Returns `None` if there's no type with that name or it isn't an
+    /// enum.
+    pub(crate) fn enum_symbols(&self, fullname: &str) -> Option<&[String]> {
+        let id = self.name_registry.lookup_name(&Fullname::from_name(fullname))?;
+
+        match self.name_registry.get(*id)? {
+            NamedType::Enum(symbols) => Some(symbols.symbols()),
+            _ => None,
+        }
+    }
+
+    /// Lists the dotted path and type of every leaf field, starting
+    /// from the root record's name (e.g. `user.name.firstname`). Array
+    /// items and map values are marked with `[]`, and unions descend
+    /// into any record branches while listing their other non-null
+    /// branches as leaves at the union's own path. Recursive records
+    /// are listed as a leaf the second time they're reached.
+    pub(crate) fn flatten_paths(&self) -> Vec<(String, &SchemaType)> {
+        let root_path = match &self.root {
+            SchemaType::Reference(id) => match self.resolve_named_type(*id) {
+                NamedType::Record(_) => self.name_registry.name_of(*id).name().to_string(),
+                _ => String::new(),
+            },
+            _ => String::new(),
+        };
+
+        let mut paths = Vec::new();
+        self.flatten_type(&self.root, root_path, &mut Vec::new(), &mut paths);
+        paths
+    }
+
+    fn flatten_type<'s>(
+        &'s self,
+        schema_type: &'s SchemaType,
+        path: String,
+        enclosing_records: &mut Vec<NamedTypeId>,
+        paths: &mut Vec<(String, &'s SchemaType)>,
+    ) {
+        match schema_type {
+            SchemaType::Reference(id) if self.is_expandable_record(*id, enclosing_records) => {
+                if let NamedType::Record(fields) = self.resolve_named_type(*id) {
+                    enclosing_records.push(*id);
+                    for field in fields {
+                        let field_path = if path.is_empty() {
+                            field.name().to_string()
+                        } else {
+                            format!("{}.{}", path, field.name())
+                        };
+
+                        self.flatten_type(field.schema_type(), field_path, enclosing_records, paths);
+                    }
+                    enclosing_records.pop();
+                }
+            }
+            SchemaType::Array(nested_type) | SchemaType::Map(nested_type) => {
+                self.flatten_type(nested_type, format!("{}[]", path), enclosing_records, paths);
+            }
+            SchemaType::Union(branches)
+                if branches.iter().any(|branch| match branch {
+                    SchemaType::Reference(id) => self.is_expandable_record(*id, enclosing_records),
+                    _ => false,
+                }) =>
+            {
+                for branch in branches {
+                    match branch {
+                        SchemaType::Reference(id) if self.is_expandable_record(*id, enclosing_records) => {
+                            self.flatten_type(branch, path.clone(), enclosing_records, paths);
+                        }
+                        SchemaType::Null => {}
+                        _ => paths.push((path.clone(), branch)),
+                    }
+                }
+            }
+            _ => paths.push((path, schema_type)),
+        }
+    }
+
+    fn is_expandable_record(&self, id: NamedTypeId, enclosing_records: &[NamedTypeId]) -> bool {
+        matches!(self.resolve_named_type(id), NamedType::Record(_)) && !enclosing_records.contains(&id)
+    }
+
+    /// Checks whether both schemas describe the same types with the
+    /// same names, ignoring attributes that don't change how values are
+    /// encoded, such as docs, aliases, and key order.
+    pub(crate) fn structurally_eq(&self, other: &Schema) -> bool {
+        self.type_eq(&self.root, other, &other.root, &mut HashSet::new())
+    }
+
+    fn type_eq(
+        &self,
+        schema_type: &SchemaType,
+        other: &Schema,
+        other_type: &SchemaType,
+        visited: &mut HashSet<(NamedTypeId, NamedTypeId)>,
+    ) -> bool {
+        match (schema_type, other_type) {
+            (SchemaType::Array(nested_type), SchemaType::Array(other_nested_type))
+            | (SchemaType::Map(nested_type), SchemaType::Map(other_nested_type)) => {
+                self.type_eq(nested_type, other, other_nested_type, visited)
+            }
+            (SchemaType::Union(branches), SchemaType::Union(other_branches)) => {
+                branches.len() == other_branches.len()
+                    && branches
+                        .iter()
+                        .zip(other_branches)
+                        .all(|(branch, other_branch)| self.type_eq(branch, other, other_branch, visited))
+            }
+            (SchemaType::Reference(id), SchemaType::Reference(other_id)) => {
+                if self.named_type_fullname(*id) != other.named_type_fullname(*other_id) {
+                    return false;
+                }
+
+                // Recursive types would otherwise be compared forever.
+                if !visited.insert((*id, *other_id)) {
+                    return true;
+                }
+
+                match (self.resolve_named_type(*id), other.resolve_named_type(*other_id)) {
+                    (NamedType::Fixed(size), NamedType::Fixed(other_size)) => size == other_size,
+                    (NamedType::Enum(symbols), NamedType::Enum(other_symbols)) => symbols == other_symbols,
+                    (NamedType::Record(fields), NamedType::Record(other_fields)) => {
+                        fields.len() == other_fields.len()
+                            && fields.iter().zip(other_fields).all(|(field, other_field)| {
+                                field.name() == other_field.name()
+                                    && self.type_eq(field.schema_type(), other, other_field.schema_type(), visited)
+                            })
+                    }
+                    _ => false,
+                }
+            }
+            (schema_type, other_type) => schema_type == other_type,
+        }
+    }
+}
+
+/// Only looks at the places a type can appear, so defaults and other
+/// attributes that happen to contain a `logicalType` key are skipped.
+fn collect_logical_types<'j>(json: &'j Value, logical_types: &mut HashSet<&'j str>) {
+    match json {
+        Value::Array(branches) => {
+            for branch in branches {
+                collect_logical_types(branch, logical_types);
+            }
+        }
+        Value::Object(attributes) => {
+            if let Some(Value::String(logical_type)) = attributes.get("logicalType") {
+                logical_types.insert(logical_type);
+            }
+
+            for key in ["type", "items", "values"].iter() {
+                if let Some(nested_type) = attributes.get(*key) {
+                    collect_logical_types(nested_type, logical_types);
+                }
+            }
+
+            if let Some(Value::Array(fields)) = attributes.get("fields") {
+                for field in fields {
+                    if let Some(field_type) = field.get("type") {
+                        collect_logical_types(field_type, logical_types);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The specification's 64-bit Rabin fingerprint, CRC-64-AVRO.
+fn rabin_fingerprint(data: &[u8]) -> u64 {
+    const EMPTY: u64 = 0xc15d_213a_a4d7_a795;
+
+    let mut table = [0u64; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut fingerprint = i as u64;
+        for _ in 0..8 {
+            fingerprint = (fingerprint >> 1) ^ (EMPTY & (fingerprint & 1).wrapping_neg());
+        }
+        *entry = fingerprint;
+    }
+
+    data.iter().fold(EMPTY, |fingerprint, byte| {
+        (fingerprint >> 8) ^ table[((fingerprint ^ u64::from(*byte)) & 0xff) as usize]
+    })
+}
+
+fn write_sorted_json(json: &Value, output: &mut String) {
+    const LEADING_KEYS: [&str; 4] = ["type", "name", "namespace", "fields"];
+
+    match json {
+        Value::Object(attributes) => {
+            let mut keys: Vec<&String> = attributes.keys().collect();
+            keys.sort_by_key(|key| {
+                let position = LEADING_KEYS.iter().position(|leading_key| leading_key == key);
+                (position.unwrap_or(LEADING_KEYS.len()), key.as_str())
+            });
+
+            output.push('{');
+            for (index, key) in keys.into_iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+
+                output.push_str(&Value::String(key.clone()).to_string());
+                output.push(':');
+                write_sorted_json(&attributes[key], output);
+            }
+            output.push('}');
+        }
+        Value::Array(values) => {
+            output.push('[');
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+
+                write_sorted_json(value, output);
+            }
+            output.push(']');
+        }
+        scalar => output.push_str(&scalar.to_string()),
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -48,16 +624,72 @@ pub(crate) enum SchemaType {
     Double,
     Bytes,
     String,
+    TimestampMillis,
+    TimestampMicros,
     Array(Box<SchemaType>),
     Map(Box<SchemaType>),
     Union(Vec<SchemaType>),
     Reference(NamedTypeId),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RootKind {
+    Record,
+    Enum,
+    Fixed,
+    Array,
+    Map,
+    Union,
+    Primitive,
+}
+
+impl RootKind {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            RootKind::Record => "record",
+            RootKind::Enum => "enum",
+            RootKind::Fixed => "fixed",
+            RootKind::Array => "array",
+            RootKind::Map => "map",
+            RootKind::Union => "union",
+            RootKind::Primitive => "primitive",
+        }
+    }
+}
+
+/// Formats a `SchemaType`, see `SchemaType::display`.
+pub(crate) struct DisplaySchemaType<'s> {
+    schema_type: &'s SchemaType,
+    schema: &'s Schema,
+}
+
+impl<'s> fmt::Display for DisplaySchemaType<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.schema_type {
+            SchemaType::Array(item_type) => write!(f, "array<{}>", item_type.display(self.schema)),
+            SchemaType::Map(value_type) => write!(f, "map<{}>", value_type.display(self.schema)),
+            SchemaType::Union(branches) => {
+                f.write_str("union<")?;
+                for (index, branch) in branches.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+
+                    write!(f, "{}", branch.display(self.schema))?;
+                }
+                f.write_str(">")
+            }
+            SchemaType::Reference(id) => f.write_str(self.schema.named_type_fullname(*id)),
+            primitive => f.write_str(primitive.kind_name()),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub(crate) struct Field {
     name: String,
     schema_type: SchemaType,
+    default: Option<Value>,
 }
 
 impl Field {
@@ -68,19 +700,87 @@ impl Field {
     pub(crate) fn schema_type(&self) -> &SchemaType {
         &self.schema_type
     }
+
+    /// The JSON value readers should use when the writer's record
+    /// doesn't have this field.
+    pub(crate) fn default(&self) -> Option<&Value> {
+        self.default.as_ref()
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub(crate) enum NamedType {
     Fixed(usize),
-    Enum(Vec<String>),
+    Enum(EnumSymbols),
     Record(Vec<Field>),
 }
 
+/// The symbols of an enum type, along with a lookup from each symbol
+/// to its index so encoding a symbol doesn't need to scan the list.
+#[derive(Debug, PartialEq)]
+pub(crate) struct EnumSymbols {
+    symbols: Vec<String>,
+    indexes: HashMap<String, u32>,
+    default: Option<String>,
+}
+
+impl EnumSymbols {
+    pub(crate) fn new(symbols: Vec<String>) -> Self {
+        let indexes = symbols
+            .iter()
+            .enumerate()
+            .map(|(index, symbol)| (symbol.clone(), index as u32))
+            .collect();
+
+        Self {
+            symbols,
+            indexes,
+            default: None,
+        }
+    }
+
+    /// The symbol readers should use in place of a writer's symbol
+    /// that this enum doesn't have.
+    pub(crate) fn default(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    pub(crate) fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    pub(crate) fn symbol_index(&self, symbol: &str) -> Option<u32> {
+        self.indexes.get(symbol).copied()
+    }
+
+    pub(crate) fn symbol(&self, index: u32) -> Option<&str> {
+        self.symbols.get(index as usize).map(String::as_str)
+    }
+}
+
+/// Decodes a bytes or fixed default, which is a string whose code points
+/// are the byte values. Returns `None` if a code point is above 255.
+pub(crate) fn default_bytes(default: &str) -> Option<Vec<u8>> {
+    default.chars().map(|c| u8::try_from(u32::from(c)).ok()).collect()
+}
+
+fn is_primitive_name(name: &str) -> bool {
+    matches!(
+        name,
+        "null" | "boolean" | "int" | "long" | "float" | "double" | "bytes" | "string"
+    )
+}
+
 #[derive(Debug)]
 struct NameRegistry {
     type_definitions: Vec<Option<NamedType>>,
+    type_names: Vec<Fullname>,
     name_to_id_mappings: HashMap<Fullname, NamedTypeId>,
+
+    /// Names that were used before their definition was parsed, such
+    /// as a record referring to one defined later in a union. Each has
+    /// an id reserved for it, which the definition takes over.
+    forward_references: HashSet<NamedTypeId>,
 }
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
@@ -98,13 +798,15 @@ impl<'a> Fullname {
         let (namespace_separator_position, fullname) = match name.rfind('.') {
             Some(position) => (Some(position), name.to_string()),
             None => match namespace {
-                Some(namespace) => {
+                // An empty namespace is the null namespace, so the name
+                // doesn't get a leading dot.
+                Some(namespace) if !namespace.is_empty() => {
                     let mut fullname = namespace.to_string();
                     fullname.push('.');
                     fullname.push_str(name);
                     (Some(namespace.len()), fullname)
                 }
-                None => (None, name.to_string()),
+                _ => (None, name.to_string()),
             },
         };
 
@@ -118,6 +820,13 @@ impl<'a> Fullname {
         self.fullname.as_str()
     }
 
+    fn name(&self) -> &str {
+        match self.namespace_separator_position {
+            Some(index) => &self.fullname[index + 1..],
+            None => self.fullname.as_str(),
+        }
+    }
+
     fn namespace(&'a self) -> Option<&'a str> {
         self.namespace_separator_position.map(|index| &self.fullname[0..index])
     }
@@ -127,7 +836,9 @@ impl NameRegistry {
     fn new() -> Self {
         Self {
             type_definitions: Vec::new(),
+            type_names: Vec::new(),
             name_to_id_mappings: HashMap::new(),
+            forward_references: HashSet::new(),
         }
     }
 
@@ -142,17 +853,25 @@ impl NameRegistry {
         self.name_to_id_mappings.get(name)
     }
 
+    fn name_of(&self, id: NamedTypeId) -> &Fullname {
+        &self.type_names[id]
+    }
+
     fn add_type(&mut self, name: &Fullname, definition: NamedType) -> NamedTypeId {
-        let id = self.type_definitions.len();
-        self.type_definitions.push(Some(definition));
-        self.name_to_id_mappings.insert(name.clone(), id);
+        let id = self.reserve_name(name);
+        self.complete_reservation(id, definition);
         id
     }
 
     fn reserve_name(&mut self, name: &Fullname) -> NamedTypeId {
+        if let Some(id) = self.take_forward_reference(name) {
+            return id;
+        }
+
         // TODO: validate name doesn't already exist
         let id = self.type_definitions.len();
         self.type_definitions.push(None);
+        self.type_names.push(name.clone());
         self.name_to_id_mappings.insert(name.clone(), id);
         id
     }
@@ -161,6 +880,32 @@ impl NameRegistry {
         // TODO: validate that it's replacing an empty definition
         self.type_definitions[id] = Some(definition);
     }
+
+    /// Returns the id for a name, reserving one to be filled in by a
+    /// later definition if the name hasn't been seen yet.
+    fn lookup_or_forward(&mut self, name: &Fullname) -> NamedTypeId {
+        if let Some(id) = self.lookup_name(name) {
+            return *id;
+        }
+
+        let id = self.reserve_name(name);
+        self.forward_references.insert(id);
+        id
+    }
+
+    fn take_forward_reference(&mut self, name: &Fullname) -> Option<NamedTypeId> {
+        let id = *self.lookup_name(name)?;
+        if self.forward_references.remove(&id) {
+            Some(id)
+        } else {
+            None
+        }
+    }
+
+    /// The first name that was referenced but never defined, if any.
+    fn undefined_name(&self) -> Option<&Fullname> {
+        self.forward_references.iter().min().map(|id| self.name_of(*id))
+    }
 }
 
 impl SchemaType {
@@ -174,8 +919,14 @@ impl SchemaType {
                     "fixed" => Self::parse_fixed(attributes, named_types, enclosing_namespace),
                     "enum" => Self::parse_enum(attributes, named_types, enclosing_namespace),
                     "record" => Self::parse_record(attributes, named_types, enclosing_namespace),
-                    _ => Self::match_typename(typename, named_types, enclosing_namespace),
+                    _ => Ok(Self::match_typename(typename, named_types, enclosing_namespace)?
+                        .with_logical_type(attributes.get("logicalType"))),
                 },
+                // Some generators wrap a type in another object, as in
+                // `{"type": {"type": "array", "items": "int"}}`.
+                Some(nested_type @ Value::Object(_)) | Some(nested_type @ Value::Array(_)) => {
+                    Self::parse(nested_type, named_types, enclosing_namespace)
+                }
                 _ => Err(Error::InvalidSchema),
             },
             Value::Array(types) => Self::parse_union(types, named_types, enclosing_namespace),
@@ -213,22 +964,43 @@ impl SchemaType {
         }
     }
 
-    fn parse_fixed(
-        attributes: &Map<String, Value>,
-        named_types: &mut NameRegistry,
-        enclosing_namespace: Option<&str>,
-    ) -> Result<Self, Error> {
+    /// The namespace a named type is declared in. An explicit `""` or
+    /// `null` puts the type in the null namespace, while an absent one
+    /// inherits the enclosing namespace.
+    fn namespace<'n>(attributes: &'n Map<String, Value>, enclosing_namespace: Option<&'n str>) -> Option<&'n str> {
+        match attributes.get("namespace") {
+            Some(Value::String(namespace)) if namespace.is_empty() => None,
+            Some(Value::String(namespace)) => Some(namespace.as_ref()),
+            Some(Value::Null) => None,
+            _ => enclosing_namespace,
+        }
+    }
+
+    /// Reads a named type's `name` and `namespace`. A name can't be a
+    /// primitive type name, since `"int"` always means the primitive
+    /// and such a type could never be referred to.
+    fn parse_fullname(attributes: &Map<String, Value>, enclosing_namespace: Option<&str>) -> Result<Fullname, Error> {
         let name = match attributes.get("name") {
             Some(Value::String(name)) => Ok(name),
             _ => Err(Error::InvalidType),
         }?;
 
-        let namespace = match attributes.get("namespace") {
-            Some(Value::String(namespace)) => Some(namespace.as_ref()),
-            _ => enclosing_namespace,
-        };
+        let namespace = Self::namespace(attributes, enclosing_namespace);
 
         let fullname = Fullname::build(name, namespace);
+        if is_primitive_name(fullname.name()) {
+            return Err(Error::InvalidType);
+        }
+
+        Ok(fullname)
+    }
+
+    fn parse_fixed(
+        attributes: &Map<String, Value>,
+        named_types: &mut NameRegistry,
+        enclosing_namespace: Option<&str>,
+    ) -> Result<Self, Error> {
+        let fullname = Self::parse_fullname(attributes, enclosing_namespace)?;
 
         let size = match attributes.get("size") {
             Some(Value::Number(size)) => {
@@ -247,17 +1019,7 @@ impl SchemaType {
         named_types: &mut NameRegistry,
         enclosing_namespace: Option<&str>,
     ) -> Result<Self, Error> {
-        let name = match attributes.get("name") {
-            Some(Value::String(name)) => Ok(name),
-            _ => Err(Error::InvalidType),
-        }?;
-
-        let namespace = match attributes.get("namespace") {
-            Some(Value::String(namespace)) => Some(namespace.as_ref()),
-            _ => enclosing_namespace,
-        };
-
-        let fullname = Fullname::build(name, namespace);
+        let fullname = Self::parse_fullname(attributes, enclosing_namespace)?;
 
         let symbols = match attributes.get("symbols") {
             Some(Value::Array(symbols)) => symbols
@@ -270,7 +1032,18 @@ impl SchemaType {
             _ => Err(Error::InvalidType),
         }?;
 
-        let id = named_types.add_type(&fullname, NamedType::Enum(symbols));
+        let default = match attributes.get("default") {
+            Some(Value::String(default)) if symbols.contains(default) => Ok(Some(default.clone())),
+            Some(_) => Err(Error::InvalidType),
+            None => Ok(None),
+        }?;
+
+        let enum_symbols = EnumSymbols {
+            default,
+            ..EnumSymbols::new(symbols)
+        };
+
+        let id = named_types.add_type(&fullname, NamedType::Enum(enum_symbols));
         Ok(SchemaType::Reference(id))
     }
 
@@ -279,17 +1052,7 @@ impl SchemaType {
         named_types: &mut NameRegistry,
         enclosing_namespace: Option<&str>,
     ) -> Result<Self, Error> {
-        let name = match attributes.get("name") {
-            Some(Value::String(name)) => Ok(name),
-            _ => Err(Error::InvalidType),
-        }?;
-
-        let namespace = match attributes.get("namespace") {
-            Some(Value::String(namespace)) => Some(namespace.as_ref()),
-            _ => enclosing_namespace,
-        };
-
-        let fullname = Fullname::build(name, namespace);
+        let fullname = Self::parse_fullname(attributes, enclosing_namespace)?;
 
         let id = named_types.reserve_name(&fullname);
 
@@ -323,7 +1086,13 @@ impl SchemaType {
             None => Err(Error::InvalidSchema),
         }?;
 
-        Ok(Field { name, schema_type })
+        let default = attributes.get("default").cloned();
+
+        Ok(Field {
+            name,
+            schema_type,
+            default,
+        })
     }
 
     fn parse_union(
@@ -336,12 +1105,101 @@ impl SchemaType {
             .map(|schema| Self::parse(schema, named_types, enclosing_namespace))
             .collect::<Result<Vec<SchemaType>, Error>>()?;
 
+        // Unions may not directly contain other unions, and each branch
+        // must be distinguishable when encoding. Unnamed types can only
+        // appear once per kind, while named types only conflict when
+        // their fullnames match.
+        let mut seen_kinds: HashSet<&str> = HashSet::with_capacity(union_types.len());
+
+        for union_type in union_types.iter() {
+            let kind = match union_type {
+                SchemaType::Union(_) => return Err(Error::InvalidSchema),
+                SchemaType::Reference(id) => named_types.name_of(*id).fullname(),
+                unnamed_type => unnamed_type.kind_name(),
+            };
+
+            if !seen_kinds.insert(kind) {
+                return Err(Error::InvalidSchema);
+            }
+        }
+
         Ok(SchemaType::Union(union_types))
     }
 
+    /// Applies a `logicalType` annotation to the underlying type. As the
+    /// specification requires, logical types that aren't supported or
+    /// don't fit the underlying type are ignored, so the value decodes
+    /// as the underlying type instead. Other attributes, such as the
+    /// `connect.name` Kafka Connect adds, are always ignored.
+    fn with_logical_type(self, logical_type: Option<&Value>) -> Self {
+        match (self, logical_type.and_then(Value::as_str)) {
+            (SchemaType::Long, Some("timestamp-millis")) => SchemaType::TimestampMillis,
+            (SchemaType::Long, Some("timestamp-micros")) => SchemaType::TimestampMicros,
+            (schema_type, _) => schema_type,
+        }
+    }
+
+    /// The item type of an array or the value type of a map.
+    pub(crate) fn element_type(&self) -> Option<&SchemaType> {
+        match self {
+            SchemaType::Array(element_type) | SchemaType::Map(element_type) => Some(element_type),
+            _ => None,
+        }
+    }
+
+    /// Whether `is_allowed` accepts every named type this type refers
+    /// to, including through arrays, maps, and unions.
+    fn references_only(&self, is_allowed: &dyn Fn(NamedTypeId) -> bool) -> bool {
+        match self {
+            SchemaType::Array(element_type) | SchemaType::Map(element_type) => element_type.references_only(is_allowed),
+            SchemaType::Union(branches) => branches.iter().all(|branch| branch.references_only(is_allowed)),
+            SchemaType::Reference(id) => is_allowed(*id),
+            _ => true,
+        }
+    }
+
+    /// Formats the type as a compact string such as `array<map<long>>`
+    /// or `union<null, com.example.user>`, naming referenced types by
+    /// their fullname in `schema`.
+    pub(crate) fn display<'s>(&'s self, schema: &'s Schema) -> DisplaySchemaType<'s> {
+        DisplaySchemaType {
+            schema_type: self,
+            schema,
+        }
+    }
+
+    /// The primitive type a logical type is encoded as, or the type
+    /// itself for everything else. Schema resolution compares logical
+    /// types by this rather than by the logical type.
+    pub(crate) fn underlying_type(&self) -> &SchemaType {
+        match self {
+            SchemaType::TimestampMillis | SchemaType::TimestampMicros => &SchemaType::Long,
+            schema_type => schema_type,
+        }
+    }
+
+    pub(crate) fn kind_name(&self) -> &'static str {
+        match self {
+            SchemaType::Null => "null",
+            SchemaType::Boolean => "boolean",
+            SchemaType::Int => "int",
+            SchemaType::Long => "long",
+            SchemaType::Float => "float",
+            SchemaType::Double => "double",
+            SchemaType::Bytes => "bytes",
+            SchemaType::String => "string",
+            SchemaType::TimestampMillis => "timestamp-millis",
+            SchemaType::TimestampMicros => "timestamp-micros",
+            SchemaType::Array(_) => "array",
+            SchemaType::Map(_) => "map",
+            SchemaType::Union(_) => "union",
+            SchemaType::Reference(_) => "reference",
+        }
+    }
+
     fn match_typename(
         typename: &str,
-        named_types: &NameRegistry,
+        named_types: &mut NameRegistry,
         enclosing_namespace: Option<&str>,
     ) -> Result<Self, Error> {
         match typename {
@@ -353,10 +1211,10 @@ impl SchemaType {
             "double" => Ok(SchemaType::Double),
             "bytes" => Ok(SchemaType::Bytes),
             "string" => Ok(SchemaType::String),
-            typename => match named_types.lookup_name(&Fullname::build(typename, enclosing_namespace)) {
-                Some(id) => Ok(SchemaType::Reference(*id)),
-                None => Err(Error::UnrecognizedType),
-            },
+            typename => {
+                let fullname = Fullname::build(typename, enclosing_namespace);
+                Ok(SchemaType::Reference(named_types.lookup_or_forward(&fullname)))
+            }
         }
     }
 }
@@ -376,7 +1234,6 @@ mod tests {
             (r#""double""#, Ok(SchemaType::Double)),
             (r#""bytes""#, Ok(SchemaType::Bytes)),
             (r#""string""#, Ok(SchemaType::String)),
-            (r#""option""#, Err(Error::UnrecognizedType)),
             (r#"{"type": "string"}"#, Ok(SchemaType::String)),
             (r#"null"#, Err(Error::InvalidSchema)),
             (r#"true"#, Err(Error::InvalidSchema)),
@@ -391,6 +1248,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_logical_types() {
+        let examples = [
+            (
+                r#"{"type": "long", "logicalType": "timestamp-millis"}"#,
+                SchemaType::TimestampMillis,
+            ),
+            (
+                r#"{"type": "long", "logicalType": "timestamp-micros"}"#,
+                SchemaType::TimestampMicros,
+            ),
+            (
+                r#"{"type": "long", "connect.name": "org.apache.kafka.connect.data.Timestamp", "logicalType": "timestamp-millis"}"#,
+                SchemaType::TimestampMillis,
+            ),
+            (
+                r#"{"type": "long", "connect.name": "com.example.Epoch", "logicalType": "epoch-seconds"}"#,
+                SchemaType::Long,
+            ),
+            (r#"{"type": "int", "logicalType": "timestamp-millis"}"#, SchemaType::Int),
+            (r#"{"type": "long", "logicalType": 42}"#, SchemaType::Long),
+        ];
+
+        for (json_str, expected) in examples.iter() {
+            let json: Value = serde_json::from_str(json_str).unwrap();
+            let mut named_types = NameRegistry::new();
+
+            let actual = SchemaType::parse(&json, &mut named_types, None);
+            assert_eq!(actual.as_ref(), Ok(expected));
+        }
+    }
+
     #[test]
     fn parse_arrays_and_maps() {
         let examples = [
@@ -416,86 +1305,360 @@ mod tests {
             (r#"{"type": "map"}"#, Err(Error::InvalidSchema)),
         ];
 
-        for (json_str, expected) in examples.iter() {
-            let json: Value = serde_json::from_str(json_str).unwrap();
-            let mut named_types = NameRegistry::new();
+        for (json_str, expected) in examples.iter() {
+            let json: Value = serde_json::from_str(json_str).unwrap();
+            let mut named_types = NameRegistry::new();
+
+            let actual = SchemaType::parse(&json, &mut named_types, None);
+            assert_eq!(actual, *expected);
+        }
+    }
+
+    #[test]
+    fn parse_enums_and_fixed_types() {
+        let valid_examples = [
+            (
+                r#"{"type": "fixed", "name": "blob", "size": 42}"#,
+                Some(NamedType::Fixed(42)),
+            ),
+            (
+                r#"{
+                     "type": "enum", "name": "suit",
+                      "symbols": ["HEARTS", "CLUBS", "SPADES", "DIAMONDS"]
+                   }"#,
+                Some(NamedType::Enum(EnumSymbols::new(vec![
+                    "HEARTS".to_string(),
+                    "CLUBS".to_string(),
+                    "SPADES".to_string(),
+                    "DIAMONDS".to_string(),
+                ]))),
+            ),
+        ];
+
+        for (json_str, expected_type_def) in valid_examples.iter() {
+            let json: Value = serde_json::from_str(json_str).unwrap();
+            let mut named_types = NameRegistry::new();
+
+            if let Ok(SchemaType::Reference(id)) = SchemaType::parse(&json, &mut named_types, None) {
+                assert_eq!(named_types.get(id), expected_type_def.as_ref());
+            } else {
+                panic!("parse should have returned a reference");
+            }
+        }
+
+        let invalid_examples = [
+            (r#"{"type": "fixed", "name": "blob"}"#, Err(Error::InvalidType)),
+            (r#"{"type": "fixed", "size": 42}"#, Err(Error::InvalidType)),
+            (r#"{"type": "enum", "symbols": ["foo"]}"#, Err(Error::InvalidType)),
+            (r#"{"type": "enum", "name": "suit"}"#, Err(Error::InvalidType)),
+            (
+                r#"{"type": "enum", "name": "suit", "symbols": "diamonds"}"#,
+                Err(Error::InvalidType),
+            ),
+        ];
+
+        for (json_str, expected_error) in invalid_examples.iter() {
+            let json: Value = serde_json::from_str(json_str).unwrap();
+            let mut named_types = NameRegistry::new();
+
+            let actual = SchemaType::parse(&json, &mut named_types, None);
+            assert_eq!(actual, *expected_error);
+        }
+    }
+
+    #[test]
+    fn look_up_enum_symbols_and_indexes() {
+        let symbols = EnumSymbols::new(vec!["HEARTS".to_string(), "CLUBS".to_string()]);
+
+        assert_eq!(symbols.symbol_index("HEARTS"), Some(0));
+        assert_eq!(symbols.symbol_index("CLUBS"), Some(1));
+        assert_eq!(symbols.symbol_index("SPADES"), None);
+
+        assert_eq!(symbols.symbol(0), Some("HEARTS"));
+        assert_eq!(symbols.symbol(1), Some("CLUBS"));
+        assert_eq!(symbols.symbol(2), None);
+    }
+
+    #[test]
+    fn look_up_symbols_of_parsed_suit_enum() {
+        let schema =
+            Schema::parse(r#"{"type": "enum", "name": "suit", "symbols": ["HEARTS", "CLUBS", "SPADES", "DIAMONDS"]}"#)
+                .unwrap();
+
+        let symbols = match schema.root() {
+            SchemaType::Reference(id) => match schema.resolve_named_type(*id) {
+                NamedType::Enum(symbols) => symbols,
+                _ => panic!("suit should be an enum"),
+            },
+            _ => panic!("parse should have returned a reference"),
+        };
+
+        assert_eq!(symbols.symbol_index("SPADES"), Some(2));
+        assert_eq!(symbols.symbol_index("DIAMONDS"), Some(3));
+        assert_eq!(symbols.symbol_index("JOKER"), None);
+
+        assert_eq!(symbols.symbol(0), Some("HEARTS"));
+        assert_eq!(symbols.symbol(3), Some("DIAMONDS"));
+        assert_eq!(symbols.symbol(4), None);
+    }
+
+    #[test]
+    fn look_up_enum_symbols_by_fullname() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "card", "namespace": "com.example", "fields": [
+                 {"name": "suit", "type": {"type": "enum", "name": "suit",
+                                           "symbols": ["SPADES", "HEARTS", "DIAMONDS", "CLUBS"]}}
+               ]}"#,
+        )
+        .unwrap();
+
+        let expected = ["SPADES", "HEARTS", "DIAMONDS", "CLUBS"];
+        assert_eq!(
+            schema.enum_symbols("com.example.suit"),
+            Some(&expected.map(String::from)[..])
+        );
+        assert_eq!(schema.enum_symbols("suit"), None);
+        assert_eq!(schema.enum_symbols("com.example.card"), None);
+    }
+
+    #[test]
+    fn parse_record() {
+        let json_str = r#"{
+          "type": "record",
+          "name": "user",
+          "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "email", "type": "string"}
+          ]
+        }"#;
+
+        let expected_type_def = NamedType::Record(vec![
+            Field {
+                name: "id".to_string(),
+                schema_type: SchemaType::Long,
+                default: None,
+            },
+            Field {
+                name: "email".to_string(),
+                schema_type: SchemaType::String,
+                default: None,
+            },
+        ]);
+
+        let json: Value = serde_json::from_str(json_str).unwrap();
+        let mut named_types = NameRegistry::new();
+
+        if let Ok(SchemaType::Reference(id)) = SchemaType::parse(&json, &mut named_types, None) {
+            assert_eq!(named_types.get(id), Some(&expected_type_def));
+        } else {
+            panic!("parse should have returned a reference");
+        }
+    }
+
+    #[test]
+    fn parse_field_and_enum_defaults() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "card", "fields": [
+                 {"name": "suit", "type": {"type": "enum", "name": "suit", "symbols": ["HEARTS"], "default": "HEARTS"}},
+                 {"name": "value", "type": "int", "default": 1},
+                 {"name": "note", "type": "string"}
+               ]}"#,
+        )
+        .unwrap();
+
+        let fields = match schema.resolve_named_type(0) {
+            NamedType::Record(fields) => fields,
+            _ => panic!("expected a record"),
+        };
+
+        assert_eq!(fields[1].default(), Some(&Value::from(1)));
+        assert_eq!(fields[2].default(), None);
 
-            let actual = SchemaType::parse(&json, &mut named_types, None);
-            assert_eq!(actual, *expected);
+        match schema.resolve_named_type(1) {
+            NamedType::Enum(symbols) => assert_eq!(symbols.default(), Some("HEARTS")),
+            _ => panic!("expected an enum"),
         }
+
+        let unknown_default = r#"{"type": "enum", "name": "suit", "symbols": ["HEARTS"], "default": "CLUBS"}"#;
+        assert_eq!(Schema::parse(unknown_default).err(), Some(Error::InvalidType));
     }
 
     #[test]
-    fn parse_enums_and_fixed_types() {
-        let valid_examples = [
-            (
-                r#"{"type": "fixed", "name": "blob", "size": 42}"#,
-                Some(NamedType::Fixed(42)),
-            ),
-            (
-                r#"{
-                     "type": "enum", "name": "suit",
-                      "symbols": ["HEARTS", "CLUBS", "SPADES", "DIAMONDS"]
-                   }"#,
-                Some(NamedType::Enum(vec![
-                    "HEARTS".to_string(),
-                    "CLUBS".to_string(),
-                    "SPADES".to_string(),
-                    "DIAMONDS".to_string(),
-                ])),
-            ),
+    fn reject_defaults_that_do_not_match_the_field_type() {
+        let invalid_defaults = vec![
+            r#"{"name": "nickname", "type": ["null", "string"], "default": "x"}"#,
+            r#"{"name": "age", "type": "int", "default": 3000000000}"#,
+            r#"{"name": "scores", "type": {"type": "array", "items": "int"}, "default": ["x"]}"#,
+            r#"{"name": "token", "type": {"type": "fixed", "name": "token", "size": 2}, "default": "x"}"#,
         ];
 
-        for (json_str, expected_type_def) in valid_examples.iter() {
-            let json: Value = serde_json::from_str(json_str).unwrap();
-            let mut named_types = NameRegistry::new();
-
-            if let Ok(SchemaType::Reference(id)) = SchemaType::parse(&json, &mut named_types, None) {
-                assert_eq!(named_types.get(id), expected_type_def.as_ref());
-            } else {
-                panic!("parse should have returned a reference");
-            }
+        for field in invalid_defaults {
+            let json_str = format!(r#"{{"type": "record", "name": "user", "fields": [{}]}}"#, field);
+            assert_eq!(Schema::parse(&json_str).err(), Some(Error::InvalidDefault), "{}", field);
         }
 
-        let invalid_examples = [
-            (r#"{"type": "fixed", "name": "blob"}"#, Err(Error::InvalidType)),
-            (r#"{"type": "fixed", "size": 42}"#, Err(Error::InvalidType)),
-            (r#"{"type": "enum", "symbols": ["foo"]}"#, Err(Error::InvalidType)),
-            (r#"{"type": "enum", "name": "suit"}"#, Err(Error::InvalidType)),
-            (
-                r#"{"type": "enum", "name": "suit", "symbols": "diamonds"}"#,
-                Err(Error::InvalidType),
-            ),
-        ];
+        let recursive_default = r#"{"type": "record", "name": "node", "fields": [
+             {"name": "value", "type": "int", "default": 0},
+             {"name": "next", "type": ["null", "node"], "default": null},
+             {"name": "first", "type": ["node", "null"], "default": {"value": 1}}
+           ]}"#;
+        assert!(Schema::parse(recursive_default).is_ok());
+    }
 
-        for (json_str, expected_error) in invalid_examples.iter() {
-            let json: Value = serde_json::from_str(json_str).unwrap();
-            let mut named_types = NameRegistry::new();
+    #[test]
+    fn serialize_parsing_canonical_form() {
+        let json_str = r#"
+          {
+              "type": "record",
+              "name": "user",
+              "namespace": "com.example",
+              "doc": "A user",
+              "fields": [
+                  {"name": "id", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+                  {"name": "suit", "type": {"type": "enum", "name": "suit", "symbols": ["HEARTS"], "default": "HEARTS"}},
+                  {"name": "tags", "type": {"type": "map", "values": {"type": "fixed", "name": "tag", "size": 2}}},
+                  {"name": "friends", "type": ["null", {"type": "array", "items": "user"}], "default": null}
+              ]
+          }
+        "#;
 
-            let actual = SchemaType::parse(&json, &mut named_types, None);
-            assert_eq!(actual, *expected_error);
-        }
+        let expected = concat!(
+            r#"{"name":"com.example.user","type":"record","fields":["#,
+            r#"{"name":"id","type":"long"},"#,
+            r#"{"name":"suit","type":{"name":"com.example.suit","type":"enum","symbols":["HEARTS"]}},"#,
+            r#"{"name":"tags","type":{"type":"map","values":{"name":"com.example.tag","type":"fixed","size":2}}},"#,
+            r#"{"name":"friends","type":["null",{"type":"array","items":"com.example.user"}]}"#,
+            r#"]}"#,
+        );
+
+        let schema = Schema::parse(json_str).unwrap();
+        assert_eq!(schema.canonical_form(), expected);
+        assert_eq!(
+            Schema::parse(r#"{"type": "int"}"#).unwrap().canonical_form(),
+            r#""int""#
+        );
     }
 
     #[test]
-    fn parse_record() {
+    fn key_decode_plans_by_encoding_and_logical_types() {
+        let schema = r#"{"type": "record", "name": "event", "fields": [
+                          {"name": "id", "type": "long"},
+                          {"name": "at", "type": {"type": "long", "logicalType": "timestamp-millis"}}
+                        ]}"#;
+        let key = Schema::parse(schema).unwrap().decode_plan_key();
+
+        let with_doc = schema.replace(r#""name": "event","#, r#""name": "event", "doc": "An event.","#);
+        assert_eq!(Schema::parse(&with_doc).unwrap().decode_plan_key(), key);
+
+        let without_logical_type = schema.replace(r#", "logicalType": "timestamp-millis""#, "");
+        assert_ne!(Schema::parse(&without_logical_type).unwrap().decode_plan_key(), key);
+
+        let micros = schema.replace("timestamp-millis", "timestamp-micros");
+        assert_ne!(Schema::parse(&micros).unwrap().decode_plan_key(), key);
+
+        let reordered = r#"{"type": "record", "name": "event", "fields": [
+                             {"name": "at", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+                             {"name": "id", "type": "long"}
+                           ]}"#;
+        assert_ne!(Schema::parse(reordered).unwrap().decode_plan_key(), key);
+
+        // The fingerprint of "null" from the specification's reference
+        // implementation.
+        assert_eq!(rabin_fingerprint(br#""null""#), 0x63dd_24e7_cc25_8f8a);
+    }
+
+    #[test]
+    fn list_logical_types() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "payment", "fields": [
+                 {"name": "amount", "type": {"type": "bytes", "logicalType": "decimal", "precision": 9, "scale": 2}},
+                 {"name": "paid_at", "type": ["null", {"type": "long", "logicalType": "timestamp-millis"}]},
+                 {"name": "note", "type": {"type": "map", "values": "string"}, "default": {"logicalType": "uuid"}}
+               ]}"#,
+        )
+        .unwrap();
+
+        let expected: HashSet<&str> = ["decimal", "timestamp-millis"].iter().copied().collect();
+        assert_eq!(schema.logical_types(), expected);
+    }
+
+    #[test]
+    fn get_element_types_of_arrays_and_maps() {
+        let array = SchemaType::Array(Box::new(SchemaType::Int));
+        let map = SchemaType::Map(Box::new(SchemaType::String));
+
+        assert_eq!(array.element_type(), Some(&SchemaType::Int));
+        assert_eq!(map.element_type(), Some(&SchemaType::String));
+        assert_eq!(SchemaType::Long.element_type(), None);
+    }
+
+    #[test]
+    fn display_nested_types() {
+        let schema = Schema::parse(
+            r#"{"type": "array", "items": {"type": "map", "values": [
+                 "null",
+                 {"type": "record", "name": "user", "namespace": "com.example", "fields": []}
+               ]}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            schema.root().display(&schema).to_string(),
+            "array<map<union<null, com.example.user>>>"
+        );
+    }
+
+    #[test]
+    fn list_required_fields() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "nickname", "type": ["null", "string"], "default": null}
+               ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(schema.required_fields(), vec!["email"]);
+        assert!(Schema::parse(r#""string""#).unwrap().required_fields().is_empty());
+    }
+
+    #[test]
+    fn look_up_field_types() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "age", "type": "int"}
+               ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(schema.field_type("age"), Some(&SchemaType::Int));
+        assert_eq!(schema.field_type("email"), Some(&SchemaType::String));
+        assert_eq!(schema.field_type("name"), None);
+        assert_eq!(Schema::parse(r#""int""#).unwrap().field_type("age"), None);
+    }
+
+    #[test]
+    fn parse_field_with_wrapped_type() {
         let json_str = r#"{
           "type": "record",
           "name": "user",
           "fields": [
-            {"name": "id", "type": "long"},
-            {"name": "email", "type": "string"}
+            {"name": "scores", "type": {"type": {"type": "array", "items": "int"}}},
+            {"name": "nickname", "type": {"type": ["null", "string"]}}
           ]
         }"#;
 
         let expected_type_def = NamedType::Record(vec![
             Field {
-                name: "id".to_string(),
-                schema_type: SchemaType::Long,
+                name: "scores".to_string(),
+                schema_type: SchemaType::Array(Box::new(SchemaType::Int)),
+                default: None,
             },
             Field {
-                name: "email".to_string(),
-                schema_type: SchemaType::String,
+                name: "nickname".to_string(),
+                schema_type: SchemaType::Union(vec![SchemaType::Null, SchemaType::String]),
+                default: None,
             },
         ]);
 
@@ -557,10 +1720,12 @@ mod tests {
             Field {
                 name: "firstname".to_string(),
                 schema_type: SchemaType::String,
+                default: None,
             },
             Field {
                 name: "lastname".to_string(),
                 schema_type: SchemaType::String,
+                default: None,
             },
         ]);
 
@@ -583,6 +1748,117 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn reject_duplicate_union_branches() {
+        let examples = [
+            (r#"["null", "null"]"#, Err(Error::InvalidSchema)),
+            (r#"["null", ["string", "long"]]"#, Err(Error::InvalidSchema)),
+            (
+                r#"[{"type": "array", "items": "int"}, {"type": "array", "items": "long"}]"#,
+                Err(Error::InvalidSchema),
+            ),
+            (
+                r#"[
+                     {"type": "record", "name": "Rec", "namespace": "com.a", "fields": []},
+                     "com.a.Rec"
+                   ]"#,
+                Err(Error::InvalidSchema),
+            ),
+            (
+                r#"[
+                     {"type": "fixed", "name": "Rec", "namespace": "com.a", "size": 4},
+                     {"type": "fixed", "name": "Rec", "namespace": "com.a", "size": 8}
+                   ]"#,
+                Err(Error::InvalidSchema),
+            ),
+        ];
+
+        for (json_str, expected) in examples.iter() {
+            let json: Value = serde_json::from_str(json_str).unwrap();
+            let mut named_types = NameRegistry::new();
+
+            let actual = SchemaType::parse(&json, &mut named_types, None);
+            assert_eq!(actual, *expected);
+        }
+    }
+
+    #[test]
+    fn allow_union_branches_with_distinct_fullnames() {
+        let json_str = r#"
+          [
+             {"type": "record", "name": "Rec", "namespace": "com.a", "fields": []},
+             {"type": "record", "name": "Rec", "namespace": "com.b", "fields": []}
+          ]
+        "#;
+        let json: Value = serde_json::from_str(json_str).unwrap();
+        let mut named_types = NameRegistry::new();
+        SchemaType::parse(&json, &mut named_types, None).unwrap();
+
+        let a_id = named_types.lookup_name(&Fullname::from_name("com.a.Rec")).unwrap();
+        let b_id = named_types.lookup_name(&Fullname::from_name("com.b.Rec")).unwrap();
+        assert_ne!(a_id, b_id);
+    }
+
+    #[test]
+    fn build_schemas_without_parsing() {
+        let schema = Schema::primitive(SchemaType::Int).unwrap();
+        assert_eq!(schema.root(), &SchemaType::Int);
+        assert_eq!(schema.to_json(), r#""int""#);
+
+        let schema = Schema::primitive(SchemaType::Array(Box::new(SchemaType::TimestampMillis))).unwrap();
+        assert_eq!(
+            schema.to_json(),
+            r#"{"items":{"logicalType":"timestamp-millis","type":"long"},"type":"array"}"#
+        );
+
+        let schema = Schema::record(
+            "com.example.node",
+            vec![
+                ("value", SchemaType::Long),
+                (
+                    "next",
+                    SchemaType::Union(vec![SchemaType::Null, SchemaType::Reference(0)]),
+                ),
+            ],
+        )
+        .unwrap();
+        let parsed = Schema::parse(
+            r#"{"type": "record", "name": "node", "namespace": "com.example", "fields": [
+                 {"name": "value", "type": "long"},
+                 {"name": "next", "type": ["null", "node"]}
+               ]}"#,
+        )
+        .unwrap();
+        assert_eq!(schema.canonical_form(), parsed.canonical_form());
+        assert_eq!(schema.named_type_fullname(0), "com.example.node");
+
+        assert_eq!(
+            Schema::primitive(SchemaType::Reference(0)).err(),
+            Some(Error::InvalidSchema)
+        );
+        assert_eq!(
+            Schema::record("node", vec![("other", SchemaType::Reference(1))]).err(),
+            Some(Error::InvalidSchema)
+        );
+    }
+
+    #[test]
+    fn reject_named_types_named_after_primitives() {
+        let examples = [
+            r#"{"type": "record", "name": "string", "fields": []}"#,
+            r#"{"type": "record", "name": "com.example.int", "fields": []}"#,
+            r#"{"type": "enum", "name": "null", "symbols": ["NONE"]}"#,
+            r#"{"type": "fixed", "name": "bytes", "namespace": "com.example", "size": 4}"#,
+        ];
+
+        for json_str in examples.iter() {
+            assert_eq!(Schema::parse(json_str).err(), Some(Error::InvalidType), "{}", json_str);
+        }
+
+        assert!(Schema::parse(r#"{"type": "record", "name": "strings", "fields": []}"#).is_ok());
+        assert_eq!(Schema::record("long", vec![]).err(), Some(Error::InvalidType));
+    }
+
     #[test]
     fn parse_self_referential_record() {
         let json_str = r#"{
@@ -606,10 +1882,12 @@ mod tests {
             Field {
                 name: "value".to_string(),
                 schema_type: SchemaType::Long,
+                default: None,
             },
             Field {
                 name: "next".to_string(),
                 schema_type: SchemaType::Union(vec![SchemaType::Null, SchemaType::Reference(type_id)]),
+                default: None,
             },
         ]);
 
@@ -625,6 +1903,9 @@ mod tests {
             ("baz", Some("foo.bar"), Some("foo.bar"), "foo.bar.baz"),
             ("foo.bar", None, Some("foo"), "foo.bar"),
             ("foo.bar", Some("baz"), Some("foo"), "foo.bar"),
+            ("foo", Some(""), None, "foo"),
+            ("foo.bar", Some(""), Some("foo"), "foo.bar"),
+            ("a.b.c", Some("foo"), Some("a.b"), "a.b.c"),
         ];
 
         for (name, given_namespace, expected_namespace, expected_fullname) in examples.iter() {
@@ -715,22 +1996,27 @@ mod tests {
             Field {
                 name: "id1".to_string(),
                 schema_type: SchemaType::Reference(*id_dotcom_ref),
+                default: None,
             },
             Field {
                 name: "id2".to_string(),
                 schema_type: SchemaType::Reference(*id_dotnet_ref),
+                default: None,
             },
             Field {
                 name: "id3".to_string(),
                 schema_type: SchemaType::Reference(*id_dotcom_ref),
+                default: None,
             },
             Field {
                 name: "id4".to_string(),
                 schema_type: SchemaType::Reference(*id_dotcom_ref),
+                default: None,
             },
             Field {
                 name: "id5".to_string(),
                 schema_type: SchemaType::Reference(*id_dotnet_ref),
+                default: None,
             },
         ]);
 
@@ -739,6 +2025,247 @@ mod tests {
         assert_eq!(*actual_user_def, expected_user_def);
     }
 
+    #[test]
+    fn report_undefined_type_names() {
+        assert_eq!(
+            Schema::parse(r#""option""#).unwrap_err(),
+            Error::UnrecognizedType("option".to_string())
+        );
+
+        assert_eq!(
+            Schema::parse(r#"{"type": "array", "items": "NotYetDefined"}"#).unwrap_err(),
+            Error::UnrecognizedType("NotYetDefined".to_string())
+        );
+
+        let json_str = r#"{"type": "record", "name": "Outer", "namespace": "com.example", "fields": [
+                            {"name": "later", "type": {"type": "map", "values": "Later"}},
+                            {"name": "never", "type": ["null", "Never"]},
+                            {"name": "defined", "type": {"type": "fixed", "name": "Later", "size": 1}}
+                          ]}"#;
+        assert_eq!(
+            Schema::parse(json_str).unwrap_err(),
+            Error::UnrecognizedType("com.example.Never".to_string())
+        );
+    }
+
+    #[test]
+    fn validate_references_to_named_types() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "node", "fields": [
+                 {"name": "suit", "type": {"type": "enum", "name": "suit", "symbols": ["HEARTS"]}},
+                 {"name": "next", "type": ["null", "node"]},
+                 {"name": "others", "type": {"type": "map", "values": "suit"}}
+               ]}"#,
+        )
+        .unwrap();
+        assert_eq!(schema.validate(), Ok(()));
+
+        let mut name_registry = NameRegistry::new();
+        let id = name_registry.reserve_name(&Fullname::from_name("node"));
+        let incomplete = Schema {
+            root: SchemaType::Reference(id),
+            name_registry,
+            json: Value::Null,
+        };
+        assert_eq!(incomplete.validate(), Err(Error::InvalidSchema));
+
+        let dangling = Schema {
+            root: SchemaType::Array(Box::new(SchemaType::Reference(0))),
+            name_registry: NameRegistry::new(),
+            json: Value::Null,
+        };
+        assert_eq!(dangling.validate(), Err(Error::InvalidSchema));
+    }
+
+    #[test]
+    fn resolve_references_to_types_defined_later() {
+        let json_str = r#"{"type": "record", "name": "Outer", "namespace": "com.example", "fields": [
+                            {"name": "later", "type": {"type": "map", "values": "Later"}},
+                            {"name": "defined", "type": {"type": "fixed", "name": "Later", "size": 1}}
+                          ]}"#;
+        let schema = Schema::parse(json_str).unwrap();
+        assert_eq!(
+            schema.field_type("later"),
+            Some(&SchemaType::Map(Box::new(SchemaType::Reference(1))))
+        );
+        assert_eq!(schema.resolve_named_type(1), &NamedType::Fixed(1));
+
+        // Each record refers to the other by name before the other is
+        // defined, so neither can be parsed completely first.
+        let json_str = r#"[
+            {"type": "record", "name": "Employee", "fields": [
+              {"name": "name", "type": "string"},
+              {"name": "team", "type": ["null", "Team"]}
+            ]},
+            {"type": "record", "name": "Team", "fields": [
+              {"name": "lead", "type": ["null", "Employee"]},
+              {"name": "members", "type": {"type": "array", "items": "Employee"}}
+            ]}
+          ]"#;
+        let schema = Schema::parse(json_str).unwrap();
+        assert_eq!(
+            schema.root(),
+            &SchemaType::Union(vec![SchemaType::Reference(0), SchemaType::Reference(1)])
+        );
+        assert_eq!(schema.named_type_fullname(0), "Employee");
+        assert_eq!(schema.named_type_fullname(1), "Team");
+
+        match (schema.resolve_named_type(0), schema.resolve_named_type(1)) {
+            (NamedType::Record(employee), NamedType::Record(team)) => {
+                assert_eq!(
+                    employee[1].schema_type(),
+                    &SchemaType::Union(vec![SchemaType::Null, SchemaType::Reference(1)])
+                );
+                assert_eq!(
+                    team[0].schema_type(),
+                    &SchemaType::Union(vec![SchemaType::Null, SchemaType::Reference(0)])
+                );
+                assert_eq!(
+                    team[1].schema_type(),
+                    &SchemaType::Array(Box::new(SchemaType::Reference(0)))
+                );
+            }
+            _ => panic!("expected two records"),
+        }
+    }
+
+    #[test]
+    fn inherit_namespace_through_arrays_and_maps() {
+        let json_str = r#"
+          {
+              "type": "record",
+              "name": "Outer",
+              "namespace": "com.example",
+              "fields": [
+                  {
+                      "name": "inners",
+                      "type": {
+                          "type": "array",
+                          "items": {"type": "record", "name": "Inner", "fields": []}
+                      }
+                  },
+                  {
+                      "name": "tags",
+                      "type": {
+                          "type": "map",
+                          "values": [
+                              "null",
+                              {"type": "enum", "name": "Tag", "symbols": ["A"]}
+                          ]
+                      }
+                  }
+              ]
+          }
+        "#;
+        let json: Value = serde_json::from_str(json_str).unwrap();
+
+        let mut named_types = NameRegistry::new();
+        SchemaType::parse(&json, &mut named_types, None).unwrap();
+
+        assert!(named_types
+            .lookup_name(&Fullname::from_name("com.example.Inner"))
+            .is_some());
+        assert!(named_types
+            .lookup_name(&Fullname::from_name("com.example.Tag"))
+            .is_some());
+        assert!(named_types.lookup_name(&Fullname::from_name("Inner")).is_none());
+    }
+
+    #[test]
+    fn reset_to_null_namespace() {
+        let json_str = r#"
+          {
+              "type": "record",
+              "name": "user",
+              "namespace": "com.example",
+              "fields": [
+                  {
+                      "name": "id",
+                      "type": {"type": "fixed", "name": "identifier", "namespace": "", "size": 16}
+                  },
+                  {
+                      "name": "address",
+                      "type": {
+                          "type": "record",
+                          "name": "address",
+                          "namespace": null,
+                          "fields": [
+                              {"name": "country", "type": {"type": "enum", "name": "country", "symbols": ["US"]}}
+                          ]
+                      }
+                  }
+              ]
+          }
+        "#;
+        let json: Value = serde_json::from_str(json_str).unwrap();
+
+        let mut named_types = NameRegistry::new();
+        SchemaType::parse(&json, &mut named_types, None).unwrap();
+
+        for fullname in &["com.example.user", "identifier", "address", "country"] {
+            assert!(
+                named_types.lookup_name(&Fullname::from_name(fullname)).is_some(),
+                "{} wasn't defined",
+                fullname
+            );
+        }
+
+        assert!(named_types.lookup_name(&Fullname::from_name(".identifier")).is_none());
+    }
+
+    #[test]
+    fn parse_pathological_schemas_without_panicking() {
+        let deeply_nested = format!(
+            "{}\"int\"{}",
+            r#"{"type": "array", "items": "#.repeat(200),
+            "}".repeat(200)
+        );
+
+        let wide_union = format!(
+            "[{}]",
+            (0..10_000)
+                .map(|i| format!(r#"{{"type": "fixed", "name": "f{}", "size": 1}}"#, i))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        let examples = [
+            (deeply_nested.as_str(), Err(Error::InvalidSchema)),
+            (wide_union.as_str(), Ok(())),
+            (r#"{"type": "fixed", "name": "f", "size": -1}"#, Err(Error::InvalidType)),
+            (
+                r#"{"type": "fixed", "name": "f", "size": 1e300}"#,
+                Err(Error::InvalidType),
+            ),
+            (
+                r#"{"type": "fixed", "name": "f", "size": 18446744073709551615}"#,
+                Ok(()),
+            ),
+            (
+                r#"{"type": "enum", "name": "e", "symbols": [1, 2]}"#,
+                Err(Error::InvalidType),
+            ),
+            (
+                r#"{"type": "record", "name": "r", "fields": [{"name": "f"}]}"#,
+                Err(Error::InvalidSchema),
+            ),
+            (
+                r#"{"type": "record", "name": "r", "fields": {}}"#,
+                Err(Error::InvalidType),
+            ),
+            (r#"{"type": "record", "name": "☃.", "fields": []}"#, Ok(())),
+            (r#"{"type": {"type": {"type": []}}}"#, Ok(())),
+            (r#"[[]]"#, Err(Error::InvalidSchema)),
+            (r#"{"type": "array"}"#, Err(Error::InvalidSchema)),
+            (r#"{"type": 42}"#, Err(Error::InvalidSchema)),
+        ];
+
+        for (json_str, expected) in examples.iter() {
+            let actual = Schema::parse(json_str).map(|_| ());
+            assert_eq!(actual, *expected, "{}", &json_str[..json_str.len().min(80)]);
+        }
+    }
+
     #[test]
     fn parse_schema_from_str() {
         let schema = Schema::parse(r#""string""#);
@@ -765,10 +2292,12 @@ mod tests {
             Field {
                 name: "id".to_string(),
                 schema_type: SchemaType::Long,
+                default: None,
             },
             Field {
                 name: "email".to_string(),
                 schema_type: SchemaType::String,
+                default: None,
             },
         ]);
 
@@ -781,4 +2310,162 @@ mod tests {
 
         assert_eq!(*schema.resolve_named_type(*record_id), expected_type_def);
     }
+
+    #[test]
+    fn serialize_schema_with_sorted_keys() {
+        let first = Schema::parse(
+            r#"{
+              "doc": "A user account",
+              "fields": [
+                {"type": "long", "name": "id"},
+                {"name": "email", "aliases": ["mail"], "type": "string"}
+              ],
+              "namespace": "com.example",
+              "name": "user",
+              "type": "record"
+            }"#,
+        )
+        .unwrap();
+
+        let second = Schema::parse(
+            r#"{
+              "type": "record",
+              "name": "user",
+              "namespace": "com.example",
+              "doc": "A user account",
+              "fields": [
+                {"name": "id", "type": "long"},
+                {"type": "string", "name": "email", "aliases": ["mail"]}
+              ]
+            }"#,
+        )
+        .unwrap();
+
+        let expected = concat!(
+            r#"{"type":"record","name":"user","namespace":"com.example","#,
+            r#""fields":[{"type":"long","name":"id"},{"type":"string","name":"email","aliases":["mail"]}],"#,
+            r#""doc":"A user account"}"#
+        );
+
+        assert_eq!(first.to_json_sorted(), expected);
+        assert_eq!(second.to_json_sorted(), expected);
+    }
+
+    #[test]
+    fn compare_schemas_structurally() {
+        let schema = Schema::parse(
+            r#"{"type": "record", "name": "user", "namespace": "com.example", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "friends", "type": {"type": "array", "items": "user"}}
+               ]}"#,
+        )
+        .unwrap();
+
+        let same_schema = Schema::parse(
+            r#"{"fields": [
+                 {"type": "string", "name": "email", "doc": "Where to reach them"},
+                 {"type": {"items": "com.example.user", "type": "array"}, "name": "friends"}
+               ], "name": "com.example.user", "type": "record"}"#,
+        )
+        .unwrap();
+
+        assert!(schema.structurally_eq(&same_schema));
+
+        let different_schemas = [
+            r#"{"type": "record", "name": "user", "fields": [
+                 {"name": "email", "type": "string"},
+                 {"name": "friends", "type": {"type": "array", "items": "user"}}
+               ]}"#,
+            r#"{"type": "record", "name": "user", "namespace": "com.example", "fields": [
+                 {"name": "email", "type": "bytes"},
+                 {"name": "friends", "type": {"type": "array", "items": "user"}}
+               ]}"#,
+            r#"{"type": "record", "name": "user", "namespace": "com.example", "fields": [
+                 {"name": "friends", "type": {"type": "array", "items": "user"}},
+                 {"name": "email", "type": "string"}
+               ]}"#,
+            r#"{"type": "record", "name": "user", "namespace": "com.example", "fields": [
+                 {"name": "email", "type": "string"}
+               ]}"#,
+        ];
+
+        for schema_str in different_schemas.iter() {
+            let different_schema = Schema::parse(schema_str).unwrap();
+            assert!(!schema.structurally_eq(&different_schema));
+        }
+    }
+
+    #[test]
+    fn flatten_field_paths() {
+        let schema = Schema::parse(
+            r#"{
+              "type": "record",
+              "name": "user",
+              "namespace": "com.example",
+              "fields": [
+                {
+                  "name": "name",
+                  "type": {
+                    "type": "record",
+                    "name": "fullname",
+                    "fields": [
+                      {"name": "firstname", "type": "string"},
+                      {"name": "lastname", "type": "string"}
+                    ]
+                  }
+                },
+                {"name": "tags", "type": {"type": "array", "items": "string"}},
+                {"name": "alias", "type": ["null", "fullname"]},
+                {"name": "nickname", "type": ["string", "fullname"]}
+              ]
+            }"#,
+        )
+        .unwrap();
+
+        let paths = schema.flatten_paths();
+        let actual_paths: Vec<&str> = paths.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            actual_paths,
+            vec![
+                "user.name.firstname",
+                "user.name.lastname",
+                "user.tags[]",
+                "user.alias.firstname",
+                "user.alias.lastname",
+                "user.nickname",
+                "user.nickname.firstname",
+                "user.nickname.lastname",
+            ]
+        );
+        assert!(paths.iter().all(|(_, schema_type)| **schema_type == SchemaType::String));
+    }
+
+    #[test]
+    fn flatten_recursive_field_paths() {
+        let schema = Schema::parse(
+            r#"{
+              "type": "record",
+              "name": "long_list",
+              "fields": [
+                {"name": "value", "type": "long"},
+                {"name": "next", "type": ["null", "long_list"]}
+              ]
+            }"#,
+        )
+        .unwrap();
+
+        let list_id = match schema.root() {
+            SchemaType::Reference(id) => *id,
+            _ => panic!("root type should be a reference"),
+        };
+        let next_type = SchemaType::Union(vec![SchemaType::Null, SchemaType::Reference(list_id)]);
+
+        assert_eq!(
+            schema.flatten_paths(),
+            vec![
+                ("long_list.value".to_string(), &SchemaType::Long),
+                ("long_list.next".to_string(), &next_type),
+            ]
+        );
+    }
 }